@@ -1,3 +1,4 @@
+use std::path::Path;
 use std::process::Command;
 
 fn run(cmd: &str, args: &[&str]) {
@@ -8,9 +9,39 @@ fn run(cmd: &str, args: &[&str]) {
     assert!(status.success(), "`{cmd}` exited with {status}");
 }
 
+/// FNV-1a over every file under `dir`, visited in path order so the hash is stable.
+fn hash_dir(dir: &Path, hash: &mut u64) {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("Failed to read `{}`: {e}", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            hash_dir(&path, hash);
+            continue;
+        }
+        let name = path.to_string_lossy().replace('\\', "/");
+        let contents = std::fs::read(&path).unwrap();
+        for byte in name.bytes().chain(contents) {
+            *hash ^= u64::from(byte);
+            *hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
 fn main() {
     let out_dir = std::env::var_os("OUT_DIR").unwrap();
-    let dest_path = std::path::PathBuf::from(&out_dir).join("MCPStudioPlugin.rbxm");
+    let dest_path = std::path::PathBuf::from(&out_dir).join("MCPStudioPlugin.rbxmx");
+
+    // Identifies the plugin sources; the plugin reports it on registration so the server can
+    // tell whether the installed plugin matches the one it bundles
+    let mut hash = 0xcbf29ce484222325;
+    hash_dir(Path::new("plugin"), &mut hash);
+    let build_hash = format!("{hash:016x}");
+    println!("cargo:rustc-env=PLUGIN_BUILD_HASH={build_hash}");
+    println!("cargo:rerun-if-changed=plugin");
+    std::env::set_var("PLUGIN_BUILD_HASH", &build_hash);
 
     if let Ok(prebuilt) = std::env::var("PREBUILT_PLUGIN") {
         println!("cargo:rerun-if-env-changed=PREBUILT_PLUGIN");
@@ -48,7 +79,6 @@ fn main() {
         ],
     );
 
-    println!("cargo:rerun-if-changed=plugin");
     println!("cargo:rerun-if-changed=.darklua.json");
     println!("cargo:rerun-if-changed=plugin.project.json");
     println!("cargo:rerun-if-changed=plugin-build.project.json");
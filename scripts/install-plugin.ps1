@@ -20,6 +20,16 @@ if ($LASTEXITCODE -ne 0) {
   exit 1
 }
 
+# The server generates the auth token on first run; without it the plugin is rejected
+$TokenFile = "$env:APPDATA\rbx-studio-mcp\auth_token"
+if (Test-Path $TokenFile) {
+  $Token = (Get-Content $TokenFile -Raw).Trim()
+  $MainScript = "plugin-build\Main.server.luau"
+  (Get-Content $MainScript -Raw).Replace("{{MCP_AUTH_TOKEN}}", $Token) | Set-Content $MainScript -NoNewline
+} else {
+  Write-Host "Warning: $TokenFile not found. Run the MCP server once, then reinstall the plugin." -ForegroundColor Yellow
+}
+
 rojo build plugin-build.project.json -o MCPStudioPlugin.rbxm
 if ($LASTEXITCODE -ne 0) {
   Write-Host "Failed to build plugin" -ForegroundColor Red
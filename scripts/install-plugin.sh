@@ -12,6 +12,20 @@ PLUGINS_DIR="$HOME/Documents/Roblox/Plugins"
 rojo sourcemap plugin.project.json -o sourcemap.json
 rm -rf plugin-build
 darklua process --config .darklua.json plugin plugin-build
+
+# The server generates the auth token on first run; without it the plugin is rejected
+if [ "$(uname)" = "Darwin" ]; then
+  TOKEN_FILE="$HOME/Library/Application Support/rbx-studio-mcp/auth_token"
+else
+  TOKEN_FILE="${XDG_CONFIG_HOME:-$HOME/.config}/rbx-studio-mcp/auth_token"
+fi
+if [ -f "$TOKEN_FILE" ]; then
+  TOKEN="$(tr -d '[:space:]' < "$TOKEN_FILE")"
+  sed -i.bak "s/{{MCP_AUTH_TOKEN}}/$TOKEN/" plugin-build/Main.server.luau
+  rm plugin-build/Main.server.luau.bak
+else
+  echo "Warning: $TOKEN_FILE not found. Run the MCP server once, then reinstall the plugin."
+fi
 rojo build plugin-build.project.json -o MCPStudioPlugin.rbxm
 
 mkdir -p "$PLUGINS_DIR"
@@ -0,0 +1,345 @@
+//! Human sign-off for tool calls matching `[approval] require`. Clients that support form
+//! elicitation ask their user directly; for any other client the call waits in a queue that
+//! `GET /approvals`, `POST /approvals/{id}/approve|reject` and the matching CLI subcommands
+//! work through.
+
+use crate::policy::ToolPattern;
+use crate::server_state::PackedState;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use color_eyre::eyre::{eyre, Result};
+use rmcp::model::{JsonObject, Tool};
+use rmcp::service::{ElicitationError, RequestContext, ServiceError};
+use rmcp::{schemars, RoleServer};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Arguments that hold Luau source, shown verbatim in the approval prompt.
+const LUAU_ARGUMENTS: [(&str, &str); 3] = [
+    ("run_code", "command"),
+    ("run_code_multi", "command"),
+    ("run_script_in_play_mode", "code"),
+];
+
+/// Tools that make calls to another tool in several Studios. A requirement on that tool covers
+/// them too, and they are approved once for all their calls.
+const FAN_OUT_TOOLS: [(&str, &str); 1] = [("run_code_multi", "run_code")];
+
+/// The `[approval]` table.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, clap::Args)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApprovalSettings {
+    /// Tool, glob or annotation whose calls wait for a human to approve them. May be repeated.
+    #[arg(
+        long = "require-approval",
+        env = "RBX_MCP_REQUIRE_APPROVAL",
+        value_name = "PATTERN",
+        value_delimiter = ','
+    )]
+    pub require: Vec<ToolPattern>,
+
+    /// Seconds a call waits for approval before it fails [default: 300].
+    #[arg(
+        long = "approval-timeout",
+        env = "RBX_MCP_APPROVAL_TIMEOUT",
+        value_name = "SECS"
+    )]
+    pub timeout: Option<u64>,
+}
+
+impl ApprovalSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(300))
+    }
+
+    /// Whether calls to `tool` wait for a human.
+    pub fn requires(&self, tool: &Tool) -> bool {
+        let runs = FAN_OUT_TOOLS
+            .iter()
+            .find(|(fan_out, _)| *fan_out == tool.name)
+            .map(|(_, runs)| Tool {
+                name: (*runs).into(),
+                ..tool.clone()
+            });
+        self.require.iter().any(|pattern| {
+            pattern.matches(tool) || runs.as_ref().is_some_and(|runs| pattern.matches(runs))
+        })
+    }
+}
+
+/// A tool call held for approval, as listed by `GET /approvals`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApprovalRequest {
+    pub id: Uuid,
+    pub tool: String,
+    pub arguments: serde_json::Value,
+    pub session_id: String,
+    pub client: Option<String>,
+    /// Name of the place the call would run in.
+    pub studio: Option<String>,
+    pub requested_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub struct PendingApproval {
+    pub request: ApprovalRequest,
+    responder: oneshot::Sender<Decision>,
+}
+
+enum Decision {
+    Approved,
+    Rejected(Option<String>),
+    TimedOut,
+    Cancelled,
+}
+
+#[derive(Deserialize, schemars::JsonSchema)]
+struct ApprovalForm {
+    /// Run this tool call in Studio
+    approve: bool,
+}
+
+rmcp::elicit_safe!(ApprovalForm);
+
+/// Holds a call to `tool` until a human approves it, if the approval settings require that.
+/// `Err` says why the call must not run.
+pub async fn request_approval(
+    state: &PackedState,
+    context: &RequestContext<RoleServer>,
+    session_id: &str,
+    studio_id: Option<Uuid>,
+    tool: &Tool,
+    arguments: &JsonObject,
+) -> std::result::Result<(), String> {
+    let (timeout, studio) = {
+        let s = state.lock().await;
+        if !s.approval.requires(tool) {
+            return Ok(());
+        }
+        // A fan-out names its Studios in its arguments instead
+        let fan_out = FAN_OUT_TOOLS.iter().any(|(name, _)| *name == tool.name);
+        let studio = s
+            .call_studio(session_id, studio_id)
+            .filter(|_| !fan_out)
+            .map(|(_, conn)| conn.place_name.clone());
+        (s.approval.timeout(), studio)
+    };
+    let request = ApprovalRequest {
+        id: Uuid::new_v4(),
+        tool: tool.name.to_string(),
+        arguments: serde_json::Value::Object(arguments.clone()),
+        session_id: session_id.to_string(),
+        client: context
+            .peer
+            .peer_info()
+            .map(|info| info.client_info.name.clone()),
+        studio,
+        requested_at: chrono::Utc::now(),
+    };
+
+    let id = request.id;
+    let decision = match elicit(context, &request, timeout).await {
+        Some(decision) => decision,
+        None => wait_in_queue(state, request, timeout, &context.ct).await,
+    };
+    match decision {
+        Decision::Approved => {
+            tracing::info!("Approval {id} for `{}` granted", tool.name);
+            Ok(())
+        }
+        Decision::Rejected(reason) => {
+            tracing::info!("Approval {id} for `{}` rejected", tool.name);
+            Err(match reason {
+                Some(reason) => format!("a human rejected it: {reason}"),
+                None => "a human rejected it".to_string(),
+            })
+        }
+        Decision::TimedOut => {
+            tracing::info!("Approval {id} for `{}` timed out", tool.name);
+            Err(format!("no one approved it within {}s", timeout.as_secs()))
+        }
+        Decision::Cancelled => Err("the call was cancelled while waiting for approval".to_string()),
+    }
+}
+
+/// Asks the client's user. `None` when the client cannot show an elicitation form.
+async fn elicit(
+    context: &RequestContext<RoleServer>,
+    request: &ApprovalRequest,
+    timeout: Duration,
+) -> Option<Decision> {
+    let prompt = context
+        .peer
+        .elicit_with_timeout::<ApprovalForm>(describe(request), Some(timeout));
+    let response = tokio::select! {
+        response = prompt => response,
+        _ = context.ct.cancelled() => return Some(Decision::Cancelled),
+    };
+    match response {
+        Ok(Some(ApprovalForm { approve: true })) => Some(Decision::Approved),
+        Ok(_) | Err(ElicitationError::UserDeclined | ElicitationError::UserCancelled) => {
+            Some(Decision::Rejected(None))
+        }
+        Err(ElicitationError::Service(ServiceError::Timeout { .. })) => Some(Decision::TimedOut),
+        Err(ElicitationError::CapabilityNotSupported) => None,
+        Err(e) => {
+            tracing::warn!(
+                "Elicitation failed, queueing approval {} instead: {e}",
+                request.id
+            );
+            None
+        }
+    }
+}
+
+async fn wait_in_queue(
+    state: &PackedState,
+    request: ApprovalRequest,
+    timeout: Duration,
+    ct: &CancellationToken,
+) -> Decision {
+    let id = request.id;
+    tracing::warn!(
+        "`{}` is waiting for approval. Run `rbx-studio-mcp approve {id}` or `rbx-studio-mcp reject {id}`.",
+        request.tool
+    );
+    let (responder, decision) = oneshot::channel();
+    state
+        .lock()
+        .await
+        .approvals
+        .insert(id, PendingApproval { request, responder });
+    let decision = tokio::select! {
+        decision = decision => decision.unwrap_or(Decision::Cancelled),
+        _ = tokio::time::sleep(timeout) => Decision::TimedOut,
+        _ = ct.cancelled() => Decision::Cancelled,
+    };
+    state.lock().await.approvals.remove(&id);
+    decision
+}
+
+/// The prompt shown to the user: what would run where, with any Luau source in full and the
+/// other arguments as JSON.
+fn describe(request: &ApprovalRequest) -> String {
+    let mut text = format!("Allow `{}`", request.tool);
+    if let Some(studio) = &request.studio {
+        text += &format!(" in \"{studio}\"");
+    }
+    if let Some(client) = &request.client {
+        text += &format!(" for {client}");
+    }
+    text += "?";
+    let mut arguments = request.arguments.as_object().cloned().unwrap_or_default();
+    let luau = LUAU_ARGUMENTS
+        .iter()
+        .find(|(tool, _)| *tool == request.tool)
+        .filter(|(_, argument)| arguments.get(*argument).is_some_and(|v| v.is_string()))
+        .and_then(|(_, argument)| arguments.remove(*argument));
+    if let Some(source) = luau.as_ref().and_then(|source| source.as_str()) {
+        text += &format!("\n\n```luau\n{source}\n```");
+    }
+    if !arguments.is_empty() {
+        let arguments = serde_json::to_string_pretty(&arguments).unwrap_or_default();
+        text += &format!("\n\n```json\n{arguments}\n```");
+    }
+    text
+}
+
+pub async fn list_approvals(State(state): State<PackedState>) -> Json<Vec<ApprovalRequest>> {
+    let s = state.lock().await;
+    let mut requests: Vec<ApprovalRequest> =
+        s.approvals.values().map(|p| p.request.clone()).collect();
+    requests.sort_by_key(|request| request.requested_at);
+    Json(requests)
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct Rejection {
+    /// Passed on to the agent.
+    pub reason: Option<String>,
+}
+
+pub async fn approve(State(state): State<PackedState>, Path(id): Path<Uuid>) -> Response {
+    resolve(&state, id, Decision::Approved).await
+}
+
+pub async fn reject(
+    State(state): State<PackedState>,
+    Path(id): Path<Uuid>,
+    rejection: Option<Json<Rejection>>,
+) -> Response {
+    let reason = rejection.and_then(|Json(rejection)| rejection.reason);
+    resolve(&state, id, Decision::Rejected(reason)).await
+}
+
+async fn resolve(state: &PackedState, id: Uuid, decision: Decision) -> Response {
+    let Some(pending) = state.lock().await.approvals.remove(&id) else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("No tool call is waiting for approval as {id}"),
+        )
+            .into_response();
+    };
+    // The caller may have given up in the meantime, which changes nothing for us
+    let _ = pending.responder.send(decision);
+    Json(pending.request).into_response()
+}
+
+/// `rbx-studio-mcp approvals`: prints the queue of the running server.
+pub async fn print_pending(base_url: &str, token: &str) -> Result<()> {
+    let response = reqwest::Client::new()
+        .get(format!("{base_url}/approvals"))
+        .bearer_auth(token)
+        .send()
+        .await?;
+    let requests: Vec<ApprovalRequest> = check(response).await?.json().await?;
+    if requests.is_empty() {
+        println!("No tool calls are waiting for approval.");
+    }
+    for request in requests {
+        let waited = (chrono::Utc::now() - request.requested_at).num_seconds();
+        println!(
+            "{}  {}  {} (waiting {waited}s, session {})",
+            request.id,
+            request.tool,
+            request.studio.as_deref().unwrap_or("no studio"),
+            request.session_id
+        );
+        println!("{}", serde_json::to_string_pretty(&request.arguments)?);
+    }
+    Ok(())
+}
+
+/// `rbx-studio-mcp approve` and `reject`.
+pub async fn send_decision(
+    base_url: &str,
+    token: &str,
+    id: Uuid,
+    approve: bool,
+    reason: Option<String>,
+) -> Result<()> {
+    let action = if approve { "approve" } else { "reject" };
+    let response = reqwest::Client::new()
+        .post(format!("{base_url}/approvals/{id}/{action}"))
+        .bearer_auth(token)
+        .json(&Rejection { reason })
+        .send()
+        .await?;
+    let request: ApprovalRequest = check(response).await?.json().await?;
+    let verb = if approve { "Approved" } else { "Rejected" };
+    println!("{verb} `{}` ({id})", request.tool);
+    Ok(())
+}
+
+async fn check(response: reqwest::Response) -> Result<reqwest::Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(eyre!("server answered {status}: {body}"))
+}
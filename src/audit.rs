@@ -0,0 +1,334 @@
+//! Append-only JSONL record of every tool call: who made it, where it ran, what it was given and
+//! how it went. `dispatch` writes one record per call sent to Studio, the tools the server answers
+//! itself write their own marked `local`, and `call_tool` records calls the policy or a human
+//! refused.
+
+use rmcp::handler::server::wrapper::Json;
+use rmcp::model::CallToolResult;
+use rmcp::ErrorData;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+use uuid::Uuid;
+
+const AUDIT_FILE_NAME: &str = "audit.jsonl";
+
+/// The `[audit]` table.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, clap::Args)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditSettings {
+    /// Record every tool call in the audit log [default: true].
+    #[arg(long = "audit", env = "RBX_MCP_AUDIT", value_name = "BOOL")]
+    pub enabled: Option<bool>,
+
+    /// Audit log file [default: audit.jsonl in the config directory].
+    #[arg(long = "audit-log", env = "RBX_MCP_AUDIT_LOG", value_name = "PATH")]
+    pub path: Option<PathBuf>,
+
+    /// Size in bytes at which the audit log is rotated [default: 10485760].
+    #[arg(
+        long = "audit-max-bytes",
+        env = "RBX_MCP_AUDIT_MAX_BYTES",
+        value_name = "BYTES"
+    )]
+    pub max_bytes: Option<u64>,
+
+    /// Rotated audit logs to keep, as `audit.1.jsonl` (newest) onwards [default: 5].
+    #[arg(long = "audit-keep", env = "RBX_MCP_AUDIT_KEEP", value_name = "N")]
+    pub keep: Option<u32>,
+
+    /// Characters of each response to record [default: 2000].
+    #[arg(
+        long = "audit-response-limit",
+        env = "RBX_MCP_AUDIT_RESPONSE_LIMIT",
+        value_name = "CHARS"
+    )]
+    pub response_limit: Option<usize>,
+}
+
+impl AuditSettings {
+    pub fn path(&self) -> Option<PathBuf> {
+        match &self.path {
+            Some(path) => Some(path.clone()),
+            None => crate::config::config_dir()
+                .ok()
+                .map(|dir| dir.join(AUDIT_FILE_NAME)),
+        }
+    }
+
+    fn max_bytes(&self) -> u64 {
+        self.max_bytes.unwrap_or(10 * 1024 * 1024)
+    }
+
+    fn keep(&self) -> u32 {
+        self.keep.unwrap_or(5)
+    }
+
+    pub fn response_limit(&self) -> usize {
+        self.response_limit.unwrap_or(2000)
+    }
+}
+
+/// One line of the audit log.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditRecord {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub session_id: String,
+    pub studio_id: Option<Uuid>,
+    pub place_id: Option<u64>,
+    pub tool: String,
+    pub arguments: serde_json::Value,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// The result as JSON text, cut to the response limit.
+    pub response: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub response_truncated: bool,
+    /// The tool policy or a human refused the call, so it never reached Studio.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub refused: bool,
+    /// The server answered the call itself, or made it as calls recorded separately (like
+    /// `run_code_multi`), so it never reached Studio as such.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub local: bool,
+}
+
+/// Where a call ran, as far as it got.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AuditTarget {
+    pub studio_id: Option<Uuid>,
+    pub place_id: Option<u64>,
+}
+
+/// A tool result that can be written to the audit log.
+pub trait Audited {
+    /// Whether the call succeeded, and what to record as its response.
+    fn outcome(&self) -> (bool, serde_json::Value);
+}
+
+impl Audited for CallToolResult {
+    fn outcome(&self) -> (bool, serde_json::Value) {
+        let response = match &self.structured_content {
+            Some(structured) => structured.clone(),
+            None => serde_json::to_value(&self.content).unwrap_or_default(),
+        };
+        (!self.is_error.unwrap_or(false), response)
+    }
+}
+
+impl<T: Serialize> Audited for Json<T> {
+    fn outcome(&self) -> (bool, serde_json::Value) {
+        (true, serde_json::to_value(&self.0).unwrap_or_default())
+    }
+}
+
+impl<T: Audited> Audited for Result<T, ErrorData> {
+    fn outcome(&self) -> (bool, serde_json::Value) {
+        match self {
+            Ok(result) => result.outcome(),
+            Err(error) => (false, serde_json::Value::String(error.message.to_string())),
+        }
+    }
+}
+
+pub struct AuditLog {
+    writer: Mutex<AuditWriter>,
+}
+
+struct AuditWriter {
+    settings: AuditSettings,
+    /// Open file and its current size; opened on the first record.
+    file: Option<(File, u64)>,
+}
+
+impl AuditLog {
+    pub fn new(settings: &AuditSettings) -> Self {
+        Self {
+            writer: Mutex::new(AuditWriter {
+                settings: settings.clone(),
+                file: None,
+            }),
+        }
+    }
+
+    /// Takes on reloaded settings; a new path is opened with the next record.
+    pub fn configure(&self, settings: &AuditSettings) {
+        let mut writer = self.writer.lock().unwrap();
+        if writer.settings.path() != settings.path() {
+            writer.file = None;
+        }
+        writer.settings = settings.clone();
+    }
+
+    /// Appends a record for a call that started at `started`.
+    pub fn record(
+        &self,
+        session_id: &str,
+        target: AuditTarget,
+        tool: &str,
+        arguments: serde_json::Value,
+        started: Instant,
+        result: &impl Audited,
+    ) {
+        let record = self.entry(session_id, target, tool, arguments, started, result);
+        self.write(record);
+    }
+
+    /// Appends a record for a call the server answered without asking Studio.
+    pub fn record_local(
+        &self,
+        session_id: &str,
+        target: AuditTarget,
+        tool: &str,
+        arguments: serde_json::Value,
+        started: Instant,
+        result: &impl Audited,
+    ) {
+        let record = AuditRecord {
+            local: true,
+            ..self.entry(session_id, target, tool, arguments, started, result)
+        };
+        self.write(record);
+    }
+
+    /// Appends a record for a call the tool policy or a human refused.
+    pub fn record_refusal(
+        &self,
+        session_id: &str,
+        target: AuditTarget,
+        tool: &str,
+        arguments: serde_json::Value,
+        started: Instant,
+        result: &impl Audited,
+    ) {
+        let record = AuditRecord {
+            refused: true,
+            ..self.entry(session_id, target, tool, arguments, started, result)
+        };
+        self.write(record);
+    }
+
+    /// The audit log followed by the rotated copies it may have, newest first.
+    pub fn logs(&self) -> Vec<PathBuf> {
+        let settings = self.writer.lock().unwrap().settings.clone();
+        let Some(path) = settings.path() else {
+            return Vec::new();
+        };
+        let rotated = (1..=settings.keep()).map(|n| rotated_path(&path, n));
+        std::iter::once(path.clone()).chain(rotated).collect()
+    }
+
+    pub fn response_limit(&self) -> usize {
+        self.writer.lock().unwrap().settings.response_limit()
+    }
+
+    fn entry(
+        &self,
+        session_id: &str,
+        target: AuditTarget,
+        tool: &str,
+        arguments: serde_json::Value,
+        started: Instant,
+        result: &impl Audited,
+    ) -> AuditRecord {
+        let (success, response) = result.outcome();
+        let (response, response_truncated) = truncate(response.to_string(), self.response_limit());
+        AuditRecord {
+            timestamp: chrono::Utc::now(),
+            session_id: session_id.to_string(),
+            studio_id: target.studio_id,
+            place_id: target.place_id,
+            tool: tool.to_string(),
+            arguments,
+            duration_ms: started.elapsed().as_millis() as u64,
+            success,
+            response,
+            response_truncated,
+            refused: false,
+            local: false,
+        }
+    }
+
+    fn write(&self, record: AuditRecord) {
+        let mut writer = self.writer.lock().unwrap();
+        if !writer.settings.enabled.unwrap_or(true) {
+            return;
+        }
+        if let Err(e) = writer.append(&record) {
+            tracing::warn!("Could not write audit record for `{}`: {e}", record.tool);
+            writer.file = None;
+        }
+    }
+}
+
+/// Cuts `text` to `limit` characters; the flag says whether anything was cut.
+pub fn truncate(text: String, limit: usize) -> (String, bool) {
+    if text.chars().count() > limit {
+        (text.chars().take(limit).collect(), true)
+    } else {
+        (text, false)
+    }
+}
+
+impl AuditWriter {
+    fn append(&mut self, record: &AuditRecord) -> std::io::Result<()> {
+        let Some(path) = self.settings.path() else {
+            return Err(std::io::Error::other(
+                "no config directory for the audit log",
+            ));
+        };
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        if self.file.is_none() {
+            self.file = Some(open(&path)?);
+        }
+        let (_, size) = self.file.as_ref().unwrap();
+        if *size > 0 && size + line.len() as u64 > self.settings.max_bytes() {
+            self.file = None;
+            rotate(&path, self.settings.keep())?;
+            self.file = Some(open(&path)?);
+        }
+        let (file, size) = self.file.as_mut().unwrap();
+        file.write_all(&line)?;
+        *size += line.len() as u64;
+        Ok(())
+    }
+}
+
+fn open(path: &Path) -> std::io::Result<(File, u64)> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+/// Shifts `audit.jsonl` to `audit.1.jsonl`, `audit.1.jsonl` to `audit.2.jsonl` and so on,
+/// dropping whatever falls past `keep`.
+fn rotate(path: &Path, keep: u32) -> std::io::Result<()> {
+    if keep == 0 {
+        return std::fs::remove_file(path);
+    }
+    let _ = std::fs::remove_file(rotated_path(path, keep));
+    for n in (1..keep).rev() {
+        let from = rotated_path(path, n);
+        if from.exists() {
+            std::fs::rename(&from, rotated_path(path, n + 1))?;
+        }
+    }
+    std::fs::rename(path, rotated_path(path, 1))
+}
+
+pub fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}.{n}.{}", extension.to_string_lossy()),
+        None => format!("{stem}.{n}"),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,207 @@
+//! Shared-secret authentication for the HTTP listeners. Anything on the machine can reach the
+//! ports, including web pages through DNS rebinding, so `/ws` and `/mcp` requests must name a
+//! `Host` the server listens on, must not come from a browser origin that isn't allowed, and must
+//! carry the token generated on first run.
+
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use color_eyre::eyre::Result;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+const TOKEN_FILE_NAME: &str = "auth_token";
+
+/// Stands in for the token in the plugin sources; `install_plugin` swaps in the real one.
+pub const PLUGIN_TOKEN_PLACEHOLDER: &str = "{{MCP_AUTH_TOKEN}}";
+
+/// The plugin's websocket endpoint, the only one that takes the token in its URL.
+pub const WS_PATH: &str = "/ws";
+
+/// Host names that always refer to this machine.
+const LOOPBACK_HOSTS: [&str; 3] = ["127.0.0.1", "localhost", "[::1]"];
+
+pub fn token_path() -> Result<PathBuf> {
+    Ok(crate::config::config_dir()?.join(TOKEN_FILE_NAME))
+}
+
+/// Reads the token from the config directory, generating and saving one on first run.
+pub fn load_or_create_token() -> Result<String> {
+    let path = token_path()?;
+    match std::fs::read_to_string(&path) {
+        Ok(token) if !token.trim().is_empty() => return Ok(token.trim().to_string()),
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    write_private(&path, &token)?;
+    tracing::info!("Generated auth token in {}", path.display());
+    Ok(token)
+}
+
+/// Writes `contents` readable only by the current user where the platform allows it.
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    use std::io::Write;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+pub struct AuthConfig {
+    token: String,
+    token_path: PathBuf,
+    /// `host:port` values accepted in the `Host` header, or `None` when listening on every
+    /// interface, where clients may use any name for the machine.
+    allowed_hosts: Option<Vec<String>>,
+    /// Browser origins that may connect, e.g. `http://localhost:6274`. Requests without an
+    /// `Origin` header (Studio, MCP clients) are not affected. Replaced on config reload.
+    allowed_origins: RwLock<Vec<String>>,
+}
+
+impl AuthConfig {
+    pub fn new(
+        token: String,
+        token_path: PathBuf,
+        bind_address: IpAddr,
+        ports: &[u16],
+        allowed_origins: Vec<String>,
+    ) -> Self {
+        let allowed_hosts = (!bind_address.is_unspecified()).then(|| {
+            let mut names: Vec<String> = LOOPBACK_HOSTS.iter().map(|h| h.to_string()).collect();
+            if !bind_address.is_loopback() {
+                names.push(match bind_address {
+                    IpAddr::V4(address) => address.to_string(),
+                    IpAddr::V6(address) => format!("[{address}]"),
+                });
+            }
+            names
+                .iter()
+                .flat_map(|name| ports.iter().map(move |port| format!("{name}:{port}")))
+                .collect()
+        });
+        Self {
+            token,
+            token_path,
+            allowed_hosts,
+            allowed_origins: RwLock::new(allowed_origins),
+        }
+    }
+
+    pub fn set_allowed_origins(&self, origins: Vec<String>) {
+        *self.allowed_origins.write().unwrap() = origins;
+    }
+
+    fn host_allowed(&self, host: &str) -> bool {
+        self.allowed_hosts.as_ref().is_none_or(|allowed| {
+            allowed
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(host))
+        })
+    }
+
+    fn origin_allowed(&self, origin: &str) -> bool {
+        let origin = origin.trim_end_matches('/');
+        self.allowed_origins
+            .read()
+            .unwrap()
+            .iter()
+            .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(origin))
+    }
+
+    /// Checks the bearer token, or for the plugin's websocket also a `?token=` query, which
+    /// elsewhere would leak into logs and browser history.
+    fn token_matches(&self, headers: &HeaderMap, path: &str, query: Option<&str>) -> bool {
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        // The plugin's websocket client cannot always set headers, so `/ws` also takes `?token=`
+        let query = query.filter(|_| path == WS_PATH);
+        let from_query = query.and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("token="))
+        });
+        bearer
+            .or(from_query)
+            .is_some_and(|token| constant_time_eq(token.trim().as_bytes(), self.token.as_bytes()))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Rejects requests with an unexpected `Host`, a disallowed `Origin` or a missing token.
+pub async fn require_auth(
+    State(auth): State<Arc<AuthConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    let host = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !auth.host_allowed(host) {
+        tracing::warn!("Rejected request with Host {host:?}");
+        return reject(
+            StatusCode::FORBIDDEN,
+            format!(
+                "Host `{host}` is not allowed. Connect to {} directly.",
+                auth.allowed_hosts
+                    .as_ref()
+                    .and_then(|hosts| hosts.first())
+                    .map_or("the server", String::as_str)
+            ),
+        );
+    }
+
+    if let Some(origin) = headers.get(header::ORIGIN) {
+        let origin = origin.to_str().unwrap_or_default();
+        if !auth.origin_allowed(origin) {
+            tracing::warn!("Rejected request from origin {origin:?}");
+            return reject(
+                StatusCode::FORBIDDEN,
+                format!(
+                    "Origin `{origin}` is not allowed. Pass `--allowed-origin {origin}` to the server if it is trusted."
+                ),
+            );
+        }
+    }
+
+    if !auth.token_matches(headers, request.uri().path(), request.uri().query()) {
+        tracing::warn!(
+            "Rejected request to {} without a valid token",
+            request.uri().path()
+        );
+        let mut response = reject(
+            StatusCode::UNAUTHORIZED,
+            format!(
+                "Missing or invalid auth token. Send `Authorization: Bearer <token>` with the token stored in {}. The Studio plugin receives it when the server installs the plugin.",
+                auth.token_path.display()
+            ),
+        );
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+        return response;
+    }
+
+    next.run(request).await
+}
+
+fn reject(status: StatusCode, message: String) -> Response {
+    (status, message).into_response()
+}
@@ -0,0 +1,108 @@
+//! Connects a mock Studio to a running server and answers its tool calls, for trying out agents
+//! and server changes without Roblox Studio.
+
+use clap::Parser;
+use color_eyre::eyre::{eyre, Result};
+use rbx_studio_mcp::mock_studio::{MockEvent, MockStudio, MockStudioConfig, Reply, PLUGIN_TOOLS};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(about = "Stand in for Roblox Studio against a running rbx-studio-mcp server")]
+struct Args {
+    /// Plugin websocket of the server.
+    #[arg(long, default_value = "ws://localhost:44756/ws")]
+    url: String,
+
+    /// Auth token [default: the one the server stores in its config directory].
+    #[arg(long, env = "RBX_MCP_TOKEN")]
+    token: Option<String>,
+
+    #[arg(long, default_value = "Mock Place")]
+    place_name: String,
+
+    #[arg(long, default_value_t = 1818)]
+    place_id: u64,
+
+    /// Audit log whose recorded responses answer the calls, each tool's in order.
+    #[arg(long, value_name = "PATH")]
+    recorded: Option<PathBuf>,
+
+    /// Answer every call to TOOL with this JSON. May be repeated.
+    #[arg(long, value_name = "TOOL=JSON", value_parser = parse_reply)]
+    reply: Vec<(String, serde_json::Value)>,
+
+    /// Seconds to wait before each answer.
+    #[arg(long, value_name = "SECS")]
+    delay: Option<f64>,
+}
+
+fn parse_reply(value: &str) -> std::result::Result<(String, serde_json::Value), String> {
+    let (tool, json) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected TOOL=JSON, got `{value}`"))?;
+    let json = serde_json::from_str(json).map_err(|e| format!("invalid JSON for {tool}: {e}"))?;
+    Ok((tool.to_string(), json))
+}
+
+/// Where the server keeps its token; see `auth::token_path`.
+fn stored_token() -> Result<String> {
+    let path = dirs::config_dir()
+        .ok_or_else(|| eyre!("could not locate the user config directory"))?
+        .join("rbx-studio-mcp")
+        .join("auth_token");
+    let token = std::fs::read_to_string(&path)
+        .map_err(|e| eyre!("could not read the token from {}: {e}", path.display()))?;
+    Ok(token.trim().to_string())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    let args = Args::parse();
+    let token = match args.token {
+        Some(token) => token,
+        None => stored_token()?,
+    };
+
+    let mut config = MockStudioConfig::new(args.url, token);
+    config.place_name = args.place_name;
+    config.place_id = args.place_id;
+    let mut studio = MockStudio::connect(config)
+        .await
+        .map_err(|e| eyre!("{e}"))?;
+    println!("Registered as studio {}", studio.studio_id);
+
+    if let Some(log) = &args.recorded {
+        let count = studio.load_recorded(log).map_err(|e| eyre!("{e}"))?;
+        println!("Loaded {count} recorded responses from {}", log.display());
+    }
+    let delay = args.delay.map(Duration::from_secs_f64);
+    let with_delay = |reply: Reply| match delay {
+        Some(delay) => Reply::Delayed(delay, Box::new(reply)),
+        None => reply,
+    };
+    for tool in PLUGIN_TOOLS {
+        studio.on(
+            tool,
+            with_delay(Reply::Failure(format!(
+                "The mock Studio has no reply for `{tool}`; pass --reply {tool}=<json>"
+            ))),
+        );
+    }
+    for (tool, response) in args.reply {
+        studio.on(&tool, with_delay(Reply::Success(response)));
+    }
+
+    loop {
+        match studio.next_event(Duration::from_secs(1)).await {
+            Some(MockEvent::Call { id, tool, args }) => println!("{id}  {tool}  {args}"),
+            Some(MockEvent::Cancel { id }) => println!("{id}  cancelled"),
+            Some(MockEvent::Ping { .. }) => {}
+            None if !studio.is_connected() => {
+                return Err(eyre!("the server closed the connection"))
+            }
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,626 @@
+//! Rust mirror of the value encodings in the plugin's `Codec.luau`.
+//!
+//! Each Roblox datatype has a serde type whose wire form matches the corresponding
+//! `Encode*`/`Decode*` pair in Luau, so values round-trip with the plugin unchanged.
+//! [`RobloxValue`] ties them together using the attribute encoding, a single-entry map keyed by
+//! the Luau `typeof` name (`{ "Vector3": [1, 2, 3] }`). The plugin sends typed values inside a
+//! msgpack extension ([`ROBLOX_VALUE_EXT`]) whose payload is that map.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// msgpack extension type carrying a msgpack-encoded [`RobloxValue`].
+pub const ROBLOX_VALUE_EXT: i8 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RobloxValue {
+    #[serde(rename = "string")]
+    String(String),
+    #[serde(rename = "number")]
+    Number(f64),
+    #[serde(rename = "boolean")]
+    Boolean(bool),
+    #[serde(rename = "buffer")]
+    Buffer(Buffer),
+    EnumItem(EnumItem),
+    UDim(UDim),
+    UDim2(UDim2),
+    Vector2int16(Vector2int16),
+    Vector3int16(Vector3int16),
+    Vector2(Vector2),
+    Vector3(Vector3),
+    Color3(Color3),
+    Content(Content),
+    BrickColor(BrickColor),
+    CFrame(CFrame),
+    NumberRange(NumberRange),
+    NumberSequence(NumberSequence),
+    ColorSequence(ColorSequence),
+    Rect(Rect),
+    Font(Font),
+    PhysicalProperties(PhysicalProperties),
+    Axes(Axes),
+    Faces(Faces),
+    DateTime(chrono::DateTime<chrono::Utc>),
+    Ray(Ray),
+    /// Path to an instance, as produced by `Paths.GetRelativePath`.
+    Instance(String),
+    Region3(Region3),
+    Region3int16(Region3int16),
+}
+
+impl RobloxValue {
+    /// Decodes the payload of a [`ROBLOX_VALUE_EXT`] extension.
+    pub fn from_ext(data: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(data)
+    }
+}
+
+impl fmt::Display for RobloxValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RobloxValue::String(v) => write!(f, "{v:?}"),
+            RobloxValue::Number(v) => write!(f, "{v}"),
+            RobloxValue::Boolean(v) => write!(f, "{v}"),
+            RobloxValue::Buffer(v) => write!(f, "buffer({} bytes)", v.0.len()),
+            RobloxValue::EnumItem(v) => v.fmt(f),
+            RobloxValue::UDim(v) => v.fmt(f),
+            RobloxValue::UDim2(v) => v.fmt(f),
+            RobloxValue::Vector2int16(v) => v.fmt(f),
+            RobloxValue::Vector3int16(v) => v.fmt(f),
+            RobloxValue::Vector2(v) => v.fmt(f),
+            RobloxValue::Vector3(v) => v.fmt(f),
+            RobloxValue::Color3(v) => v.fmt(f),
+            RobloxValue::Content(v) => write!(f, "Content({:?})", v.0),
+            RobloxValue::BrickColor(v) => write!(f, "BrickColor({:?})", v.0),
+            RobloxValue::CFrame(v) => v.fmt(f),
+            RobloxValue::NumberRange(v) => v.fmt(f),
+            RobloxValue::NumberSequence(v) => v.fmt(f),
+            RobloxValue::ColorSequence(v) => v.fmt(f),
+            RobloxValue::Rect(v) => v.fmt(f),
+            RobloxValue::Font(v) => v.fmt(f),
+            RobloxValue::PhysicalProperties(v) => v.fmt(f),
+            RobloxValue::Axes(v) => v.fmt(f),
+            RobloxValue::Faces(v) => v.fmt(f),
+            RobloxValue::DateTime(v) => write!(f, "DateTime({})", v.to_rfc3339()),
+            RobloxValue::Ray(v) => v.fmt(f),
+            RobloxValue::Instance(v) => write!(f, "Instance({v:?})"),
+            RobloxValue::Region3(v) => v.fmt(f),
+            RobloxValue::Region3int16(v) => v.fmt(f),
+        }
+    }
+}
+
+/// Writes `Name(a, b, ...)`.
+fn write_call<T: fmt::Display>(f: &mut fmt::Formatter<'_>, name: &str, args: &[T]) -> fmt::Result {
+    write!(f, "{name}(")?;
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{arg}")?;
+    }
+    write!(f, ")")
+}
+
+/// Raw bytes; `EncodeBuffer` sends them as a msgpack string, which need not be valid UTF-8.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Buffer(pub Vec<u8>);
+
+impl Serialize for Buffer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Buffer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BufferVisitor;
+        impl serde::de::Visitor<'_> for BufferVisitor {
+            type Value = Buffer;
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a string or binary buffer")
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Buffer, E> {
+                Ok(Buffer(v.as_bytes().to_vec()))
+            }
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Buffer, E> {
+                Ok(Buffer(v.to_vec()))
+            }
+        }
+        deserializer.deserialize_any(BufferVisitor)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnumItem {
+    #[serde(rename = "Type")]
+    pub enum_type: String,
+    #[serde(rename = "Name")]
+    pub name: String,
+}
+
+impl fmt::Display for EnumItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Enum.{}.{}", self.enum_type, self.name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "(f64, i32)", into = "(f64, i32)")]
+pub struct UDim {
+    pub scale: f64,
+    pub offset: i32,
+}
+
+impl From<(f64, i32)> for UDim {
+    fn from((scale, offset): (f64, i32)) -> Self {
+        Self { scale, offset }
+    }
+}
+
+impl From<UDim> for (f64, i32) {
+    fn from(v: UDim) -> Self {
+        (v.scale, v.offset)
+    }
+}
+
+impl fmt::Display for UDim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UDim({}, {})", self.scale, self.offset)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "(f64, i32, f64, i32)", into = "(f64, i32, f64, i32)")]
+pub struct UDim2 {
+    pub x: UDim,
+    pub y: UDim,
+}
+
+impl From<(f64, i32, f64, i32)> for UDim2 {
+    fn from((xs, xo, ys, yo): (f64, i32, f64, i32)) -> Self {
+        Self {
+            x: (xs, xo).into(),
+            y: (ys, yo).into(),
+        }
+    }
+}
+
+impl From<UDim2> for (f64, i32, f64, i32) {
+    fn from(v: UDim2) -> Self {
+        (v.x.scale, v.x.offset, v.y.scale, v.y.offset)
+    }
+}
+
+impl fmt::Display for UDim2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "UDim2({}, {}, {}, {})",
+            self.x.scale, self.x.offset, self.y.scale, self.y.offset
+        )
+    }
+}
+
+/// Serializes `$name` as `$repr` through its `From` conversions.
+macro_rules! serde_via {
+    ($name:ident, $repr:ty) => {
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                <$repr>::from(*self).serialize(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                <$repr>::deserialize(deserializer).map(Self::from)
+            }
+        }
+    };
+}
+
+/// Defines a struct that travels as a fixed-size array of its fields, in order.
+macro_rules! array_struct {
+    ($name:ident, $ty:ty, $n:literal, [$($field:ident),+ $(,)?]) => {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub struct $name {
+            $(pub $field: $ty,)+
+        }
+
+        impl From<[$ty; $n]> for $name {
+            fn from([$($field),+]: [$ty; $n]) -> Self {
+                Self { $($field),+ }
+            }
+        }
+
+        impl From<$name> for [$ty; $n] {
+            fn from(v: $name) -> Self {
+                [$(v.$field),+]
+            }
+        }
+
+        serde_via!($name, [$ty; $n]);
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write_call(f, stringify!($name), &[$(self.$field),+])
+            }
+        }
+    };
+}
+
+array_struct!(Vector2int16, i16, 2, [x, y]);
+array_struct!(Vector3int16, i16, 3, [x, y, z]);
+array_struct!(Vector2, f64, 2, [x, y]);
+array_struct!(Vector3, f64, 3, [x, y, z]);
+array_struct!(Color3, f64, 3, [r, g, b]);
+array_struct!(NumberRange, f64, 2, [min, max]);
+array_struct!(
+    PhysicalProperties,
+    f64,
+    5,
+    [
+        density,
+        friction,
+        elasticity,
+        friction_weight,
+        elasticity_weight
+    ]
+);
+
+/// Asset URI; `EncodeContent` only preserves URI content.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Content(pub String);
+
+/// BrickColor name, e.g. `"Bright red"`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BrickColor(pub String);
+
+/// Position plus row-major rotation matrix, matching `CFrame:GetComponents()`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[f64; 12]", into = "[f64; 12]")]
+pub struct CFrame {
+    pub position: Vector3,
+    pub rotation: [[f64; 3]; 3],
+}
+
+impl From<[f64; 12]> for CFrame {
+    fn from(c: [f64; 12]) -> Self {
+        Self {
+            position: Vector3 {
+                x: c[0],
+                y: c[1],
+                z: c[2],
+            },
+            rotation: [[c[3], c[4], c[5]], [c[6], c[7], c[8]], [c[9], c[10], c[11]]],
+        }
+    }
+}
+
+impl From<CFrame> for [f64; 12] {
+    fn from(v: CFrame) -> Self {
+        let p = v.position;
+        let r = v.rotation;
+        [
+            p.x, p.y, p.z, r[0][0], r[0][1], r[0][2], r[1][0], r[1][1], r[1][2], r[2][0], r[2][1],
+            r[2][2],
+        ]
+    }
+}
+
+impl fmt::Display for CFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_call(f, "CFrame", &<[f64; 12]>::from(*self))
+    }
+}
+
+array_struct!(NumberSequenceKeypoint, f64, 3, [time, value, envelope]);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NumberSequence(pub Vec<NumberSequenceKeypoint>);
+
+impl fmt::Display for NumberSequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_call(f, "NumberSequence", &self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[f64; 4]", into = "[f64; 4]")]
+pub struct ColorSequenceKeypoint {
+    pub time: f64,
+    pub color: Color3,
+}
+
+impl From<[f64; 4]> for ColorSequenceKeypoint {
+    fn from([time, r, g, b]: [f64; 4]) -> Self {
+        Self {
+            time,
+            color: Color3 { r, g, b },
+        }
+    }
+}
+
+impl From<ColorSequenceKeypoint> for [f64; 4] {
+    fn from(v: ColorSequenceKeypoint) -> Self {
+        [v.time, v.color.r, v.color.g, v.color.b]
+    }
+}
+
+impl fmt::Display for ColorSequenceKeypoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ColorSequenceKeypoint({}, {})", self.time, self.color)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ColorSequence(pub Vec<ColorSequenceKeypoint>);
+
+impl fmt::Display for ColorSequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_call(f, "ColorSequence", &self.0)
+    }
+}
+
+/// Defines a struct made of two `$part`s that travels as their concatenated components.
+macro_rules! pair_struct {
+    ($name:ident, $part:ident, $ty:ty, $n:literal, [$a:ident, $b:ident]) => {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub struct $name {
+            pub $a: $part,
+            pub $b: $part,
+        }
+
+        impl From<[$ty; $n * 2]> for $name {
+            fn from(c: [$ty; $n * 2]) -> Self {
+                let mut a = [<$ty>::default(); $n];
+                let mut b = [<$ty>::default(); $n];
+                a.copy_from_slice(&c[..$n]);
+                b.copy_from_slice(&c[$n..]);
+                Self {
+                    $a: a.into(),
+                    $b: b.into(),
+                }
+            }
+        }
+
+        impl From<$name> for [$ty; $n * 2] {
+            fn from(v: $name) -> Self {
+                let mut c = [<$ty>::default(); $n * 2];
+                c[..$n].copy_from_slice(&<[$ty; $n]>::from(v.$a));
+                c[$n..].copy_from_slice(&<[$ty; $n]>::from(v.$b));
+                c
+            }
+        }
+
+        serde_via!($name, [$ty; $n * 2]);
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({}, {})", stringify!($name), self.$a, self.$b)
+            }
+        }
+    };
+}
+
+pair_struct!(Rect, Vector2, f64, 2, [min, max]);
+pair_struct!(Ray, Vector3, f64, 3, [origin, direction]);
+pair_struct!(Region3, Vector3, f64, 3, [min, max]);
+pair_struct!(Region3int16, Vector3int16, i16, 3, [min, max]);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Font {
+    /// Font family asset URI.
+    pub family: String,
+    /// `Enum.FontWeight` item name.
+    pub weight: String,
+    /// `Enum.FontStyle` item name.
+    pub style: String,
+}
+
+impl fmt::Display for Font {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Font({:?}, {}, {})",
+            self.family, self.weight, self.style
+        )
+    }
+}
+
+/// Defines a set of flags that travels as a map of `PascalCase` booleans.
+macro_rules! flags_struct {
+    ($name:ident, [$($field:ident => $key:literal),+ $(,)?]) => {
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+        pub struct $name {
+            $(
+                #[serde(rename = $key, default)]
+                pub $field: bool,
+            )+
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let set: Vec<&str> = [$((self.$field, $key)),+]
+                    .into_iter()
+                    .filter_map(|(on, key)| on.then_some(key))
+                    .collect();
+                write_call(f, stringify!($name), &set)
+            }
+        }
+    };
+}
+
+flags_struct!(Axes, [x => "X", y => "Y", z => "Z"]);
+flags_struct!(Faces, [
+    top => "Top",
+    bottom => "Bottom",
+    left => "Left",
+    right => "Right",
+    front => "Front",
+    back => "Back",
+]);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmpv::Value;
+
+    /// Encodes `value` the way msgpack.luau would, with integral numbers as integers.
+    fn luau_ext(type_name: &str, payload: Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, &Value::Map(vec![(type_name.into(), payload)]))
+            .unwrap();
+        bytes
+    }
+
+    fn round_trip(value: &RobloxValue) -> RobloxValue {
+        RobloxValue::from_ext(&rmp_serde::to_vec_named(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn decodes_plugin_encodings() {
+        let vector = luau_ext(
+            "Vector3",
+            Value::Array(vec![1.into(), Value::F64(2.5), (-3).into()]),
+        );
+        assert_eq!(
+            RobloxValue::from_ext(&vector).unwrap(),
+            RobloxValue::Vector3(Vector3 {
+                x: 1.0,
+                y: 2.5,
+                z: -3.0
+            })
+        );
+
+        let enum_item = luau_ext(
+            "EnumItem",
+            Value::Map(vec![
+                ("Type".into(), "Material".into()),
+                ("Name".into(), "Plastic".into()),
+            ]),
+        );
+        let decoded = RobloxValue::from_ext(&enum_item).unwrap();
+        assert_eq!(decoded.to_string(), "Enum.Material.Plastic");
+
+        let faces = luau_ext(
+            "Faces",
+            Value::Map(vec![
+                ("Top".into(), true.into()),
+                ("Bottom".into(), false.into()),
+                ("Front".into(), true.into()),
+            ]),
+        );
+        assert_eq!(
+            RobloxValue::from_ext(&faces).unwrap().to_string(),
+            "Faces(Top, Front)"
+        );
+
+        let buffer = luau_ext("buffer", Value::from("\u{0}ab"));
+        assert_eq!(
+            RobloxValue::from_ext(&buffer).unwrap(),
+            RobloxValue::Buffer(Buffer(vec![0, b'a', b'b']))
+        );
+    }
+
+    #[test]
+    fn round_trips_every_type() {
+        let v3 = Vector3 {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+        };
+        let values = vec![
+            RobloxValue::String("hi".into()),
+            RobloxValue::Number(f64::INFINITY),
+            RobloxValue::Boolean(true),
+            RobloxValue::Buffer(Buffer(vec![0xff, 0x00])),
+            RobloxValue::EnumItem(EnumItem {
+                enum_type: "PartType".into(),
+                name: "Ball".into(),
+            }),
+            RobloxValue::UDim((0.5, 10).into()),
+            RobloxValue::UDim2((0.5, 10, 1.0, -4).into()),
+            RobloxValue::Vector2int16([1, -2].into()),
+            RobloxValue::Vector3int16([1, 2, 3].into()),
+            RobloxValue::Vector2([0.25, 4.0].into()),
+            RobloxValue::Vector3(v3),
+            RobloxValue::Color3([1.0, 0.5, 0.0].into()),
+            RobloxValue::Content(Content("rbxassetid://1".into())),
+            RobloxValue::BrickColor(BrickColor("Bright red".into())),
+            RobloxValue::CFrame(
+                [1.0, 2.0, 3.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0].into(),
+            ),
+            RobloxValue::NumberRange([1.0, 2.0].into()),
+            RobloxValue::NumberSequence(NumberSequence(vec![
+                [0.0, 1.0, 0.0].into(),
+                [1.0, 0.0, 0.5].into(),
+            ])),
+            RobloxValue::ColorSequence(ColorSequence(vec![
+                [0.0, 1.0, 0.0, 0.0].into(),
+                [1.0, 0.0, 0.0, 1.0].into(),
+            ])),
+            RobloxValue::Rect([0.0, 0.0, 10.0, 20.0].into()),
+            RobloxValue::Font(Font {
+                family: "rbxasset://fonts/families/SourceSansPro.json".into(),
+                weight: "Bold".into(),
+                style: "Italic".into(),
+            }),
+            RobloxValue::PhysicalProperties([0.7, 0.3, 0.5, 1.0, 1.0].into()),
+            RobloxValue::Axes(Axes {
+                x: true,
+                y: false,
+                z: true,
+            }),
+            RobloxValue::Faces(Faces {
+                top: true,
+                ..Default::default()
+            }),
+            RobloxValue::DateTime("2024-05-01T12:30:00Z".parse().unwrap()),
+            RobloxValue::Ray(Ray {
+                origin: v3,
+                direction: v3,
+            }),
+            RobloxValue::Instance("Workspace/Baseplate".into()),
+            RobloxValue::Region3(Region3 { min: v3, max: v3 }),
+            RobloxValue::Region3int16([0, 0, 0, 4, 4, 4].into()),
+        ];
+        for value in values {
+            assert_eq!(round_trip(&value), value);
+        }
+    }
+
+    #[test]
+    fn renders_readably() {
+        let cases = [
+            (
+                RobloxValue::Vector3([1.0, 2.0, 3.0].into()),
+                "Vector3(1, 2, 3)",
+            ),
+            (
+                RobloxValue::UDim2((0.5, 10, 1.0, -4).into()),
+                "UDim2(0.5, 10, 1, -4)",
+            ),
+            (
+                RobloxValue::Rect([0.0, 0.0, 10.0, 20.0].into()),
+                "Rect(Vector2(0, 0), Vector2(10, 20))",
+            ),
+            (
+                RobloxValue::NumberSequence(NumberSequence(vec![[0.0, 1.0, 0.0].into()])),
+                "NumberSequence(NumberSequenceKeypoint(0, 1, 0))",
+            ),
+            (
+                RobloxValue::BrickColor(BrickColor("Bright red".into())),
+                "BrickColor(\"Bright red\")",
+            ),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(value.to_string(), expected);
+        }
+    }
+}
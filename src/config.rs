@@ -0,0 +1,405 @@
+//! Server settings, layered as defaults < config file < environment < command line. The file is
+//! watched while the server runs; everything except the listeners is re-applied when it changes.
+
+use crate::approval::ApprovalSettings;
+use crate::audit::AuditSettings;
+use crate::auth::AuthConfig;
+use crate::policy::PolicySettings;
+use crate::selection::AutoSelectRule;
+use crate::server_state::{HeartbeatConfig, PackedState, ToolTimeouts};
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+pub const DEFAULT_PORT: u16 = 44756;
+
+/// Stands in for the websocket URL in the plugin sources; `install_plugin` swaps in the real one.
+pub const PLUGIN_URL_PLACEHOLDER: &str = "{{MCP_PLUGIN_URL}}";
+
+const CONFIG_DIR_NAME: &str = "rbx-studio-mcp";
+const CONFIG_FILE_NAME: &str = "config.toml";
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub fn config_dir() -> Result<PathBuf> {
+    let base =
+        dirs::config_dir().ok_or_else(|| eyre!("could not locate the user config directory"))?;
+    Ok(base.join(CONFIG_DIR_NAME))
+}
+
+pub fn default_config_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join(CONFIG_FILE_NAME))
+}
+
+/// Every setting is optional so layers can be merged; the accessors fill in defaults. The same
+/// struct is parsed from `config.toml` and from flags (with `RBX_MCP_*` environment fallbacks).
+#[derive(Clone, Debug, Default, Deserialize, clap::Args)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Address to listen on [default: 127.0.0.1]. Anything but loopback exposes the server,
+    /// and `run_code`, to the network; the auth token is then the only protection.
+    #[arg(long, env = "RBX_MCP_BIND_ADDRESS", value_name = "ADDR")]
+    pub bind_address: Option<IpAddr>,
+
+    /// Port the Studio plugin connects to [default: 44756].
+    #[arg(long, env = "RBX_MCP_PLUGIN_PORT", value_name = "PORT")]
+    pub plugin_port: Option<u16>,
+
+    /// Port serving MCP over HTTP [default: the plugin port].
+    #[arg(long, env = "RBX_MCP_MCP_PORT", value_name = "PORT")]
+    pub mcp_port: Option<u16>,
+
+    /// Log filter, such as `info` or `warn,rbx_studio_mcp=debug` [default: error].
+    #[arg(long, env = "RUST_LOG", value_name = "FILTER")]
+    pub log_level: Option<String>,
+
+    /// Seconds to wait for Studio to answer a tool call before cancelling it. `SECS` sets the
+    /// default for every tool and `TOOL=SECS` overrides a single tool. May be repeated.
+    /// In the config file this is the `[timeouts]` table, with a `default` key.
+    #[arg(long = "tool-timeout", value_name = "[TOOL=]SECS", value_parser = parse_tool_timeout)]
+    #[serde(rename = "timeouts", deserialize_with = "deserialize_timeouts")]
+    pub tool_timeouts: Vec<(Option<String>, u64)>,
+
+    /// Seconds between heartbeats exchanged with each connected Studio [default: 5].
+    #[arg(long, env = "RBX_MCP_HEARTBEAT_INTERVAL", value_name = "SECS")]
+    pub heartbeat_interval: Option<f64>,
+
+    /// Missed heartbeat intervals before a Studio is evicted as unresponsive [default: 3].
+    #[arg(long, env = "RBX_MCP_HEARTBEAT_MAX_MISSED", value_name = "N")]
+    pub heartbeat_max_missed: Option<u32>,
+
+    /// Seconds a disconnected Studio keeps its studio_id and queued requests while the plugin
+    /// reconnects. 0 drops it immediately [default: 15].
+    #[arg(long, env = "RBX_MCP_RECONNECT_GRACE", value_name = "SECS")]
+    pub reconnect_grace: Option<u64>,
+
+    /// Seconds of inactivity after which an agent session, and its studio selection, expires
+    /// [default: 3600].
+    #[arg(long, env = "RBX_MCP_SESSION_IDLE_TIMEOUT", value_name = "SECS")]
+    pub session_idle_timeout: Option<u64>,
+
+    /// Console entries kept per Studio for `get_console_output`; the oldest go first
+    /// [default: 2000].
+    #[arg(long, env = "RBX_MCP_CONSOLE_BUFFER", value_name = "ENTRIES")]
+    pub console_buffer: Option<usize>,
+
+    /// Calls to tools not annotated read-only that each Studio runs at once; later calls wait
+    /// their turn in arrival order [default: 1].
+    #[arg(long, env = "RBX_MCP_STUDIO_CONCURRENCY", value_name = "N")]
+    pub studio_concurrency: Option<usize>,
+
+    /// Browser origin allowed to call `/mcp` and `/ws`, such as a local MCP inspector. Requests
+    /// from any other origin are rejected. May be repeated.
+    #[arg(
+        long = "allowed-origin",
+        env = "RBX_MCP_ALLOWED_ORIGINS",
+        value_name = "ORIGIN",
+        value_delimiter = ','
+    )]
+    pub allowed_origins: Vec<String>,
+
+    /// Rules picking a Studio for sessions that have not called `set_studio`; config file only.
+    #[arg(skip)]
+    pub auto_select: Vec<AutoSelectRule>,
+
+    #[command(flatten)]
+    pub policy: PolicySettings,
+
+    #[command(flatten)]
+    pub approval: ApprovalSettings,
+
+    #[command(flatten)]
+    pub audit: AuditSettings,
+}
+
+impl Settings {
+    /// `over` wins wherever it sets something. Per-tool timeouts are merged, later layers winning.
+    pub fn layered(self, over: Settings) -> Settings {
+        Settings {
+            bind_address: over.bind_address.or(self.bind_address),
+            plugin_port: over.plugin_port.or(self.plugin_port),
+            mcp_port: over.mcp_port.or(self.mcp_port),
+            log_level: over.log_level.or(self.log_level),
+            tool_timeouts: [self.tool_timeouts, over.tool_timeouts].concat(),
+            heartbeat_interval: over.heartbeat_interval.or(self.heartbeat_interval),
+            heartbeat_max_missed: over.heartbeat_max_missed.or(self.heartbeat_max_missed),
+            reconnect_grace: over.reconnect_grace.or(self.reconnect_grace),
+            session_idle_timeout: over.session_idle_timeout.or(self.session_idle_timeout),
+            console_buffer: over.console_buffer.or(self.console_buffer),
+            studio_concurrency: over.studio_concurrency.or(self.studio_concurrency),
+            allowed_origins: non_empty_or(over.allowed_origins, self.allowed_origins),
+            auto_select: non_empty_or(over.auto_select, self.auto_select),
+            policy: PolicySettings {
+                read_only: over.policy.read_only.or(self.policy.read_only),
+                allow: non_empty_or(over.policy.allow, self.policy.allow),
+                deny: non_empty_or(over.policy.deny, self.policy.deny),
+                hide_denied: over.policy.hide_denied.or(self.policy.hide_denied),
+                rules: non_empty_or(over.policy.rules, self.policy.rules),
+            },
+            approval: ApprovalSettings {
+                require: non_empty_or(over.approval.require, self.approval.require),
+                timeout: over.approval.timeout.or(self.approval.timeout),
+            },
+            audit: AuditSettings {
+                enabled: over.audit.enabled.or(self.audit.enabled),
+                path: over.audit.path.or(self.audit.path),
+                max_bytes: over.audit.max_bytes.or(self.audit.max_bytes),
+                keep: over.audit.keep.or(self.audit.keep),
+                response_limit: over.audit.response_limit.or(self.audit.response_limit),
+            },
+        }
+    }
+
+    pub fn bind_address(&self) -> IpAddr {
+        self.bind_address.unwrap_or(Ipv4Addr::LOCALHOST.into())
+    }
+
+    pub fn plugin_port(&self) -> u16 {
+        self.plugin_port.unwrap_or(DEFAULT_PORT)
+    }
+
+    pub fn mcp_port(&self) -> u16 {
+        self.mcp_port.unwrap_or(self.plugin_port())
+    }
+
+    pub fn log_level(&self) -> &str {
+        self.log_level.as_deref().unwrap_or("error")
+    }
+
+    pub fn tool_timeouts(&self) -> ToolTimeouts {
+        let mut timeouts = ToolTimeouts::default();
+        for (tool, secs) in &self.tool_timeouts {
+            let timeout = Duration::from_secs(*secs);
+            match tool {
+                Some(tool) => {
+                    timeouts.per_tool.insert(tool.clone(), timeout);
+                }
+                None => timeouts.default = timeout,
+            }
+        }
+        timeouts
+    }
+
+    pub fn heartbeat(&self) -> HeartbeatConfig {
+        HeartbeatConfig {
+            interval: Duration::from_secs_f64(self.heartbeat_interval.unwrap_or(5.0).max(0.1)),
+            max_missed: self.heartbeat_max_missed.unwrap_or(3).max(1),
+        }
+    }
+
+    pub fn reconnect_grace(&self) -> Duration {
+        Duration::from_secs(self.reconnect_grace.unwrap_or(15))
+    }
+
+    pub fn session_idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.session_idle_timeout.unwrap_or(3600))
+    }
+
+    pub fn console_buffer(&self) -> usize {
+        self.console_buffer.unwrap_or(2000).max(1)
+    }
+
+    pub fn studio_concurrency(&self) -> usize {
+        self.studio_concurrency.unwrap_or(1).max(1)
+    }
+
+    pub fn auto_select(&self) -> Vec<AutoSelectRule> {
+        self.auto_select.clone()
+    }
+
+    /// The websocket URL baked into the plugin at install time.
+    pub fn plugin_url(&self) -> String {
+        let host = match self.bind_address() {
+            address if address.is_loopback() || address.is_unspecified() => "localhost".into(),
+            address => host_for_url(address),
+        };
+        format!("ws://{host}:{}/ws", self.plugin_port())
+    }
+
+    /// Where local clients reach the MCP listener, which also serves `/approvals`.
+    pub fn mcp_base_url(&self) -> String {
+        let host = match self.bind_address() {
+            IpAddr::V4(address) if address.is_unspecified() => Ipv4Addr::LOCALHOST.into(),
+            IpAddr::V6(address) if address.is_unspecified() => std::net::Ipv6Addr::LOCALHOST.into(),
+            address => address,
+        };
+        format!("http://{}:{}", host_for_url(host), self.mcp_port())
+    }
+
+    pub fn mcp_url(&self) -> String {
+        format!("{}/mcp", self.mcp_base_url())
+    }
+
+    /// Whether `other` needs different sockets, which only a restart can provide.
+    pub fn listeners_differ(&self, other: &Settings) -> bool {
+        self.bind_address() != other.bind_address()
+            || self.plugin_port() != other.plugin_port()
+            || self.mcp_port() != other.mcp_port()
+    }
+}
+
+fn host_for_url(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(address) => address.to_string(),
+        IpAddr::V6(address) => format!("[{address}]"),
+    }
+}
+
+fn non_empty_or<T>(preferred: Vec<T>, fallback: Vec<T>) -> Vec<T> {
+    if preferred.is_empty() {
+        fallback
+    } else {
+        preferred
+    }
+}
+
+pub fn parse_tool_timeout(value: &str) -> Result<(Option<String>, u64), String> {
+    let (tool, secs) = match value.split_once('=') {
+        Some((tool, secs)) => (Some(tool.trim().to_string()), secs),
+        None => (None, value),
+    };
+    let secs = secs
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid number of seconds `{secs}`: {e}"))?;
+    Ok((tool, secs))
+}
+
+/// Reads the `[timeouts]` table; its `default` key applies to every tool.
+fn deserialize_timeouts<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<(Option<String>, u64)>, D::Error> {
+    let table = HashMap::<String, u64>::deserialize(deserializer)?;
+    let mut timeouts: Vec<_> = table
+        .into_iter()
+        .map(|(tool, secs)| ((tool != "default").then_some(tool), secs))
+        .collect();
+    // The default first, so it never overrides a per-tool entry from a lower layer
+    timeouts.sort();
+    Ok(timeouts)
+}
+
+/// Reads a config file. A missing file is an error only when the user named it explicitly.
+pub fn load_file(path: &Path, required: bool) -> Result<Settings> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            toml::from_str(&text).map_err(|e| eyre!("invalid config file {}: {e}", path.display()))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => Ok(Settings::default()),
+        Err(e) => Err(eyre!("could not read config file {}: {e}", path.display())),
+    }
+}
+
+/// Everything a reload can change besides `AppState`.
+pub struct ReloadTargets {
+    pub state: PackedState,
+    pub auth: Arc<AuthConfig>,
+    pub log_filter: reload::Handle<EnvFilter, Registry>,
+}
+
+/// Polls `path` and re-applies the layered settings whenever the file changes. `overrides` are
+/// the environment and command-line settings, which keep winning over the file.
+pub fn watch(
+    path: PathBuf,
+    required: bool,
+    overrides: Settings,
+    mut current: Settings,
+    targets: ReloadTargets,
+) {
+    tokio::spawn(async move {
+        let modified = |path: &Path| -> Option<SystemTime> {
+            std::fs::metadata(path).and_then(|m| m.modified()).ok()
+        };
+        let mut last_modified = modified(&path);
+        let mut ticker = tokio::time::interval(RELOAD_POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let now_modified = modified(&path);
+            if now_modified == last_modified {
+                continue;
+            }
+            last_modified = now_modified;
+
+            let next = match load_file(&path, required) {
+                Ok(file) => file.layered(overrides.clone()),
+                Err(e) => {
+                    tracing::warn!("Keeping the previous settings: {e}");
+                    continue;
+                }
+            };
+            apply(&current, &next, &targets).await;
+            current = next;
+            tracing::info!("Reloaded config from {}", path.display());
+        }
+    });
+}
+
+async fn apply(current: &Settings, next: &Settings, targets: &ReloadTargets) {
+    if current.listeners_differ(next) {
+        tracing::warn!("Bind address and port changes take effect after a restart");
+    }
+    if current.session_idle_timeout() != next.session_idle_timeout() {
+        tracing::info!("HTTP sessions pick up the new idle timeout after a restart");
+    }
+    targets.state.lock().await.apply_settings(next);
+    targets
+        .auth
+        .set_allowed_origins(next.allowed_origins.clone());
+    match EnvFilter::try_new(next.log_level()) {
+        Ok(filter) => {
+            if let Err(e) = targets.log_filter.reload(filter) {
+                tracing::warn!("Could not change the log level: {e}");
+            }
+        }
+        Err(e) => tracing::warn!("Invalid log level `{}`: {e}", next.log_level()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Args {
+        #[command(flatten)]
+        settings: Settings,
+    }
+
+    #[test]
+    fn flags_beat_the_environment_which_beats_the_file() {
+        let path =
+            std::env::temp_dir().join(format!("rbx-studio-mcp-test-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "heartbeat_interval = 1.0\nreconnect_grace = 1\nstudio_concurrency = 1\n\n[timeouts]\ndefault = 10\nrun_code = 20\n",
+        )
+        .unwrap();
+        let file = load_file(&path, true);
+        let _ = std::fs::remove_file(&path);
+
+        std::env::set_var("RBX_MCP_RECONNECT_GRACE", "2");
+        std::env::set_var("RBX_MCP_STUDIO_CONCURRENCY", "2");
+        let args = Args::try_parse_from([
+            "rbx-studio-mcp",
+            "--studio-concurrency",
+            "3",
+            "--tool-timeout",
+            "run_code=30",
+        ]);
+        std::env::remove_var("RBX_MCP_RECONNECT_GRACE");
+        std::env::remove_var("RBX_MCP_STUDIO_CONCURRENCY");
+
+        let settings = file.unwrap().layered(args.unwrap().settings);
+        assert_eq!(settings.heartbeat().interval, Duration::from_secs(1));
+        assert_eq!(settings.reconnect_grace(), Duration::from_secs(2));
+        assert_eq!(settings.studio_concurrency(), 3);
+        let timeouts = settings.tool_timeouts();
+        assert_eq!(timeouts.default, Duration::from_secs(10));
+        assert_eq!(timeouts.per_tool["run_code"], Duration::from_secs(30));
+    }
+}
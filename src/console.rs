@@ -0,0 +1,133 @@
+//! Console output streamed by the plugin. Each Studio keeps a bounded ring buffer of entries,
+//! numbered in arrival order so agents can page through it with a cursor.
+
+use regex::Regex;
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Level of a console entry, matching `Enum.MessageType` in Studio.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Output,
+    Info,
+    Warning,
+    Error,
+}
+
+/// One `LogService.MessageOut` entry as the plugin sends it.
+#[derive(Debug, Deserialize)]
+pub struct PluginConsoleEntry {
+    pub message: String,
+    pub level: LogLevel,
+    /// Unix time in seconds.
+    pub timestamp: f64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ConsoleEntry {
+    #[schemars(description = "Position in the Studio's console; gaps mark entries that were lost")]
+    pub seq: u64,
+    pub level: LogLevel,
+    pub message: String,
+    #[schemars(description = "ISO 8601 timestamp of when Studio logged the message")]
+    pub timestamp: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ConsoleBuffer {
+    entries: VecDeque<ConsoleEntry>,
+    next_seq: u64,
+}
+
+/// Which entries `ConsoleBuffer::query` returns.
+pub struct ConsoleQuery<'a> {
+    /// Start here instead of returning the most recent entries.
+    pub cursor: Option<u64>,
+    /// Empty means every level.
+    pub levels: &'a [LogLevel],
+    pub pattern: Option<&'a Regex>,
+    pub limit: usize,
+}
+
+pub struct ConsolePage {
+    pub entries: Vec<ConsoleEntry>,
+    /// Where a follow-up query should start to see only entries after this page.
+    pub next_cursor: u64,
+    /// Entries from the cursor on that were evicted or lost before they could be read.
+    pub missed: u64,
+}
+
+impl ConsoleBuffer {
+    /// Appends what the plugin sent, after leaving a gap for the `dropped` entries it could not
+    /// send, and evicts the oldest entries beyond `capacity`.
+    pub fn push(&mut self, entries: Vec<PluginConsoleEntry>, dropped: u64, capacity: usize) {
+        self.next_seq += dropped;
+        for entry in entries {
+            let timestamp =
+                chrono::DateTime::from_timestamp_millis((entry.timestamp * 1000.0) as i64)
+                    .unwrap_or_else(chrono::Utc::now);
+            self.entries.push_back(ConsoleEntry {
+                seq: self.next_seq,
+                level: entry.level,
+                message: entry.message,
+                timestamp: timestamp.to_rfc3339(),
+            });
+            self.next_seq += 1;
+        }
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Matching entries from `query.cursor` on, oldest first, or without a cursor the most
+    /// recent `query.limit` matching entries.
+    pub fn query(&self, query: &ConsoleQuery) -> ConsolePage {
+        let matches = |entry: &&ConsoleEntry| {
+            (query.levels.is_empty() || query.levels.contains(&entry.level))
+                && query
+                    .pattern
+                    .is_none_or(|pattern| pattern.is_match(&entry.message))
+        };
+        let Some(cursor) = query.cursor else {
+            let mut entries: Vec<ConsoleEntry> = self
+                .entries
+                .iter()
+                .rev()
+                .filter(matches)
+                .take(query.limit)
+                .cloned()
+                .collect();
+            entries.reverse();
+            return ConsolePage {
+                entries,
+                next_cursor: self.next_seq,
+                missed: 0,
+            };
+        };
+
+        let oldest = self
+            .entries
+            .front()
+            .map_or(self.next_seq, |entry| entry.seq);
+        let entries: Vec<ConsoleEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.seq >= cursor)
+            .filter(matches)
+            .take(query.limit)
+            .cloned()
+            .collect();
+        // A full page stops at its last entry; otherwise everything up to now has been seen
+        let next_cursor = match entries.last() {
+            Some(last) if entries.len() == query.limit => last.seq + 1,
+            _ => self.next_seq,
+        };
+        ConsolePage {
+            entries,
+            next_cursor,
+            missed: oldest.saturating_sub(cursor),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Leases that let an agent session claim a Studio. While a session holds an exclusive lease,
+//! `dispatch` refuses other sessions' calls to that Studio; shared leases only keep others from
+//! taking an exclusive one. Leases expire after their TTL and are released when their session
+//! ends. Sessions waiting in `acquire_lease` queue up in arrival order.
+
+use crate::ticket::{Ticket, TicketEntry};
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+pub const MAX_TTL: Duration = Duration::from_secs(3600);
+pub const MAX_WAIT: Duration = Duration::from_secs(300);
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaseMode {
+    /// Only the holder may call tools in the Studio.
+    #[default]
+    Exclusive,
+    /// Any session may call tools, but no other session may take an exclusive lease.
+    Shared,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lease {
+    pub session_id: String,
+    pub mode: LeaseMode,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Lease {
+    pub fn to_info(&self) -> LeaseInfo {
+        LeaseInfo {
+            session_id: self.session_id.clone(),
+            mode: self.mode,
+            expires_at: self.expires_at.to_rfc3339(),
+        }
+    }
+
+    fn conflicts_with(&self, session_id: &str, mode: LeaseMode) -> bool {
+        self.session_id != session_id
+            && (mode == LeaseMode::Exclusive || self.mode == LeaseMode::Exclusive)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct LeaseInfo {
+    #[schemars(description = "Session holding the lease")]
+    pub session_id: String,
+    pub mode: LeaseMode,
+    #[schemars(description = "ISO 8601 timestamp after which the lease lapses")]
+    pub expires_at: String,
+}
+
+#[derive(Clone, Debug)]
+struct Waiter {
+    session_id: String,
+    entry: TicketEntry,
+}
+
+/// Leases on one Studio and the sessions waiting for one. Expired leases are ignored and
+/// dropped the next time the Studio's leases change.
+#[derive(Clone, Debug, Default)]
+pub struct StudioLeases {
+    held: Vec<Lease>,
+    waiting: VecDeque<Waiter>,
+}
+
+impl StudioLeases {
+    pub fn active(&self) -> impl Iterator<Item = &Lease> {
+        let now = chrono::Utc::now();
+        self.held.iter().filter(move |lease| lease.expires_at > now)
+    }
+
+    /// The exclusive lease of another session that keeps `session_id` from using the Studio.
+    pub fn blocker(&self, session_id: &str) -> Option<&Lease> {
+        self.active()
+            .find(|lease| lease.mode == LeaseMode::Exclusive && lease.session_id != session_id)
+    }
+
+    /// Grants `session_id` a lease, or renews its own with the new mode and TTL. Fails with
+    /// the conflicting lease, or `None` when the session must wait its turn in the queue.
+    pub fn try_acquire(
+        &mut self,
+        session_id: &str,
+        mode: LeaseMode,
+        ttl: Duration,
+        ticket: Option<&Ticket>,
+    ) -> Result<Lease, Option<Lease>> {
+        self.prune();
+        if let Some(conflict) = self
+            .held
+            .iter()
+            .find(|lease| lease.conflicts_with(session_id, mode))
+        {
+            return Err(Some(conflict.clone()));
+        }
+        let holds_one = self.held.iter().any(|lease| lease.session_id == session_id);
+        let first_in_line = match (self.waiting.front(), ticket) {
+            (None, _) => true,
+            (Some(front), Some(ticket)) => front.entry.belongs_to(ticket),
+            (Some(_), None) => false,
+        };
+        if !holds_one && !first_in_line {
+            return Err(None);
+        }
+        if first_in_line {
+            self.waiting.pop_front();
+        }
+        self.held.retain(|lease| lease.session_id != session_id);
+        let lease = Lease {
+            session_id: session_id.to_string(),
+            mode,
+            expires_at: chrono::Utc::now() + ttl,
+        };
+        self.held.push(lease.clone());
+        Ok(lease)
+    }
+
+    /// Puts the holder of `ticket` at the back of the queue, unless it is already in it.
+    pub fn enqueue(&mut self, session_id: &str, ticket: &Ticket) {
+        if !self
+            .waiting
+            .iter()
+            .any(|waiter| waiter.entry.belongs_to(ticket))
+        {
+            self.waiting.push_back(Waiter {
+                session_id: session_id.to_string(),
+                entry: ticket.entry(),
+            });
+        }
+    }
+
+    /// Drops `session_id`'s lease and returns whether it held one.
+    pub fn release(&mut self, session_id: &str) -> bool {
+        self.prune();
+        let before = self.held.len();
+        self.held.retain(|lease| lease.session_id != session_id);
+        self.held.len() != before
+    }
+
+    /// Forgets `session_id` entirely, as when the session ends.
+    pub fn forget(&mut self, session_id: &str) -> bool {
+        let released = self.release(session_id);
+        self.waiting
+            .retain(|waiter| waiter.session_id != session_id);
+        released
+    }
+
+    /// When the next lease lapses, which may free the Studio for a waiter.
+    pub fn next_expiry(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.active().map(|lease| lease.expires_at).min()
+    }
+
+    fn prune(&mut self) {
+        let now = chrono::Utc::now();
+        self.held.retain(|lease| lease.expires_at > now);
+        self.waiting.retain(|waiter| waiter.entry.is_live());
+    }
+}
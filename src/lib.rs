@@ -0,0 +1,6 @@
+//! The parts of the server that speak the plugin protocol, shared by the server binary and by
+//! `mock_studio`, which lets tests and the `mock-studio` binary stand in for Roblox Studio.
+
+pub mod error;
+pub mod mock_studio;
+pub mod wire;
@@ -1,23 +1,132 @@
-use axum::routing::get;
+use auth::{
+    load_or_create_token, require_auth, token_path, AuthConfig, PLUGIN_TOKEN_PLACEHOLDER, WS_PATH,
+};
+use axum::routing::{get, post};
+use clap::{Parser, Subcommand};
 use color_eyre::eyre::Result;
-use rbx_studio_server::{ws_handler, RBXStudioServer, STUDIO_PLUGIN_PORT};
-use rmcp::transport::streamable_http_server::{
-    session::local::LocalSessionManager,
-    tower::{StreamableHttpServerConfig, StreamableHttpService},
+use config::{ReloadTargets, Settings, PLUGIN_URL_PLACEHOLDER};
+use futures_util::future::try_join_all;
+use rbx_studio_mcp::{error, wire};
+use rbx_studio_server::{ws_handler, RBXStudioServer};
+use rmcp::{
+    transport::{
+        stdio,
+        streamable_http_server::{
+            session::local::{LocalSessionManager, SessionConfig},
+            tower::{StreamableHttpServerConfig, StreamableHttpService},
+        },
+    },
+    ServiceExt,
 };
-use server_state::AppState;
-use std::net::Ipv4Addr;
+use server_state::{sweep_sessions, AppState};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
 use tokio::sync::Mutex;
-use tracing_subscriber::{self, EnvFilter};
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
+use uuid::Uuid;
 
-mod error;
+mod approval;
+mod audit;
+mod auth;
+mod codec;
+mod config;
+mod console;
+mod lease;
+mod metrics;
+mod policy;
 mod rbx_studio_server;
+mod replay;
+mod request_queue;
+mod resources;
+mod selection;
 mod server_state;
+mod stdio_proxy;
+mod ticket;
 mod tools;
 
-fn install_plugin() {
-    let plugin_bytes = include_bytes!(concat!(env!("OUT_DIR"), "/MCPStudioPlugin.rbxm"));
+const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Parser)]
+#[command(version, about = "Roblox Studio MCP Server")]
+struct Args {
+    /// Serve MCP over stdin/stdout. The plugin websocket keeps listening in the background; if
+    /// another instance already owns the port, stdio is proxied to that instance instead.
+    #[arg(long)]
+    stdio: bool,
+
+    /// TOML config file [default: config.toml in the config directory, if it exists]. Flags and
+    /// `RBX_MCP_*` environment variables override it.
+    #[arg(long, env = "RBX_MCP_CONFIG", value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    #[command(flatten)]
+    settings: Settings,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Commands that talk to an already running server instead of starting one.
+#[derive(Subcommand)]
+enum Command {
+    /// List tool calls waiting for approval.
+    Approvals,
+    /// Let a waiting tool call run.
+    Approve { id: Uuid },
+    /// Refuse a waiting tool call.
+    Reject {
+        id: Uuid,
+        /// Explanation passed on to the agent.
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Re-run the tool calls recorded in an audit log against a Studio.
+    Replay {
+        /// Audit log to replay [default: the configured audit log].
+        log: Option<PathBuf>,
+        /// Studio to run the calls in; may be left out when only one is connected.
+        #[arg(long, value_name = "STUDIO_ID")]
+        studio: Option<Uuid>,
+        /// Only replay calls made by this MCP session id.
+        #[arg(long, value_name = "SESSION_ID")]
+        session: Option<String>,
+        /// Stop at the first call that fails.
+        #[arg(long)]
+        stop_on_error: bool,
+        /// List the calls that would run without running them.
+        #[arg(long)]
+        dry_run: bool,
+        /// Show how each new response differs from the recorded one.
+        #[arg(long)]
+        diff: bool,
+    },
+}
+
+/// Installs the bundled plugin with the token and server URL filled in. The bundle is an XML model
+/// so they can be substituted as text; a binary one (from `PREBUILT_PLUGIN`) is installed unchanged.
+fn install_plugin(token: &str, plugin_url: &str) {
+    let bundled = include_bytes!(concat!(env!("OUT_DIR"), "/MCPStudioPlugin.rbxmx"));
+    let (plugin_bytes, file_name, stale_name) = match std::str::from_utf8(bundled) {
+        Ok(xml) if xml.contains(PLUGIN_TOKEN_PLACEHOLDER) => (
+            xml.replace(PLUGIN_TOKEN_PLACEHOLDER, token)
+                .replace(PLUGIN_URL_PLACEHOLDER, plugin_url)
+                .into_bytes(),
+            "MCPStudioPlugin.rbxmx",
+            "MCPStudioPlugin.rbxm",
+        ),
+        _ => {
+            tracing::warn!(
+                "The bundled plugin has no auth token placeholder; it will be rejected until it is rebuilt"
+            );
+            (
+                bundled.to_vec(),
+                "MCPStudioPlugin.rbxm",
+                "MCPStudioPlugin.rbxmx",
+            )
+        }
+    };
     let studio = match roblox_install::RobloxStudio::locate() {
         Ok(s) => s,
         Err(e) => {
@@ -30,7 +139,9 @@ fn install_plugin() {
         tracing::warn!("Could not create plugins directory: {e}");
         return;
     }
-    let output = plugins.join("MCPStudioPlugin.rbxm");
+    // Studio would load both copies if an install in the other format were left behind
+    let _ = std::fs::remove_file(plugins.join(stale_name));
+    let output = plugins.join(file_name);
     if let Err(e) = std::fs::write(&output, plugin_bytes) {
         tracing::warn!("Could not write plugin: {e}");
         return;
@@ -41,35 +152,187 @@ fn install_plugin() {
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .with_target(false)
-        .with_thread_ids(true)
+    let args = Args::parse();
+    let (config_path, config_required) = match args.config {
+        Some(path) => (path, true),
+        None => (config::default_config_path()?, false),
+    };
+    let overrides = args.settings;
+    let settings = config::load_file(&config_path, config_required)?.layered(overrides.clone());
+
+    // Logs go to stderr so stdout stays reserved for MCP messages in --stdio mode
+    let (log_filter, log_filter_handle) =
+        reload::Layer::new(EnvFilter::try_new(settings.log_level())?);
+    tracing_subscriber::registry()
+        .with(log_filter)
+        .with(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_target(false)
+                .with_thread_ids(true),
+        )
         .init();
 
-    install_plugin();
+    let token = load_or_create_token()?;
+    if let Some(command) = args.command {
+        let base_url = settings.mcp_base_url();
+        return match command {
+            Command::Approvals => approval::print_pending(&base_url, &token).await,
+            Command::Approve { id } => {
+                approval::send_decision(&base_url, &token, id, true, None).await
+            }
+            Command::Reject { id, reason } => {
+                approval::send_decision(&base_url, &token, id, false, reason).await
+            }
+            Command::Replay {
+                log,
+                studio,
+                session,
+                stop_on_error,
+                dry_run,
+                diff,
+            } => {
+                let log = log
+                    .or_else(|| settings.audit.path())
+                    .ok_or_else(|| color_eyre::eyre::eyre!("no audit log to replay"))?;
+                // The server resolves the path, so it must not depend on our working directory
+                let log = std::path::absolute(log)?;
+                let args = replay::ReplayArgs {
+                    log: Some(log.to_string_lossy().into_owned()),
+                    session_id: session,
+                    stop_on_error,
+                    dry_run,
+                    diff,
+                };
+                replay::replay_cli(&base_url, &token, args, studio).await
+            }
+        };
+    }
+    install_plugin(&token, &settings.plugin_url());
 
-    let app_state = Arc::new(Mutex::new(AppState::new()));
+    let bind_address = settings.bind_address();
+    let (plugin_port, mcp_port) = (settings.plugin_port(), settings.mcp_port());
+    let plugin_listener = match TcpListener::bind((bind_address, plugin_port)).await {
+        Ok(listener) => listener,
+        Err(e) if args.stdio && e.kind() == std::io::ErrorKind::AddrInUse => {
+            tracing::info!(
+                "Port {plugin_port} is in use, proxying stdio to the running MCP server"
+            );
+            return stdio_proxy::run(settings.mcp_url(), token).await;
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mcp_listener = if mcp_port == plugin_port {
+        None
+    } else {
+        Some(TcpListener::bind((bind_address, mcp_port)).await?)
+    };
+    if !bind_address.is_loopback() {
+        tracing::warn!("Listening on {bind_address}, which is reachable from other machines");
+    }
+
+    let app_state = Arc::new(Mutex::new(AppState::new(&settings)));
+
+    let session_manager = Arc::new(LocalSessionManager {
+        session_config: SessionConfig {
+            keep_alive: Some(settings.session_idle_timeout()),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    tokio::spawn({
+        let state = app_state.clone();
+        let session_manager = session_manager.clone();
+        async move {
+            let mut ticker = tokio::time::interval(SESSION_SWEEP_INTERVAL);
+            loop {
+                ticker.tick().await;
+                sweep_sessions(&state, &session_manager).await;
+            }
+        }
+    });
 
     let mcp_state = app_state.clone();
     let mcp_service = StreamableHttpService::new(
         move || Ok(RBXStudioServer::new(mcp_state.clone())),
-        Arc::new(LocalSessionManager::default()),
+        session_manager,
         StreamableHttpServerConfig::default(),
     );
 
-    let app = axum::Router::new()
-        .route("/ws", get(ws_handler))
+    let auth = Arc::new(AuthConfig::new(
+        token,
+        token_path()?,
+        bind_address,
+        &[plugin_port, mcp_port],
+        settings.allowed_origins.clone(),
+    ));
+    let auth_layer = axum::middleware::from_fn_with_state(auth.clone(), require_auth);
+    let plugin_routes = axum::Router::new()
+        .route(WS_PATH, get(ws_handler))
+        .with_state(app_state.clone());
+    let mcp_routes = axum::Router::new()
         .nest_service("/mcp", mcp_service)
-        .with_state(app_state);
+        .route("/approvals", get(approval::list_approvals))
+        .route("/approvals/{id}/approve", post(approval::approve))
+        .route("/approvals/{id}/reject", post(approval::reject))
+        .route("/replay", post(replay::replay_handler))
+        .route("/metrics", get(metrics::metrics_handler))
+        .with_state(app_state.clone());
+    let servers = match mcp_listener {
+        None => vec![(
+            plugin_listener,
+            plugin_routes.merge(mcp_routes).layer(auth_layer),
+        )],
+        Some(mcp_listener) => vec![
+            (plugin_listener, plugin_routes.layer(auth_layer.clone())),
+            (mcp_listener, mcp_routes.layer(auth_layer)),
+        ],
+    };
 
-    let listener =
-        tokio::net::TcpListener::bind((Ipv4Addr::new(127, 0, 0, 1), STUDIO_PLUGIN_PORT)).await?;
-    tracing::info!("MCP server listening on http://127.0.0.1:{STUDIO_PLUGIN_PORT}");
-    tracing::info!("  WebSocket endpoint: ws://127.0.0.1:{STUDIO_PLUGIN_PORT}/ws");
-    tracing::info!("  MCP endpoint: http://127.0.0.1:{STUDIO_PLUGIN_PORT}/mcp");
+    config::watch(
+        config_path,
+        config_required,
+        overrides,
+        settings.clone(),
+        ReloadTargets {
+            state: app_state.clone(),
+            auth,
+            log_filter: log_filter_handle,
+        },
+    );
+
+    tracing::info!("MCP server listening on {bind_address}");
+    tracing::info!("  WebSocket endpoint: {}", settings.plugin_url());
+    tracing::info!("  MCP endpoint: {}", settings.mcp_url());
+
+    let serving = try_join_all(
+        servers
+            .into_iter()
+            .map(|(listener, app)| async move { axum::serve(listener, app).await }),
+    );
+    if !args.stdio {
+        serving.await?;
+        return Ok(());
+    }
+
+    tokio::spawn(async move {
+        if let Err(e) = serving.await {
+            tracing::error!("HTTP server stopped: {e}");
+        }
+    });
 
-    axum::serve(listener, app).await?;
+    tracing::info!("  MCP over stdio");
+    let server = RBXStudioServer::new(app_state.clone());
+    let session_id = server.connection_session_id.clone();
+    app_state
+        .lock()
+        .await
+        .open_connections
+        .insert(session_id.clone());
+    let service = server.serve(stdio()).await?;
+    let closed = service.waiting().await;
+    app_state.lock().await.end_session(&session_id);
+    closed?;
 
     Ok(())
 }
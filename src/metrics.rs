@@ -0,0 +1,272 @@
+//! Prometheus metrics, served in the text exposition format at `/metrics`. `dispatch` counts tool
+//! calls and their latency, `handle_studio_connection` counts websocket connections, and the
+//! gauges are read from `AppState` when scraped.
+
+use crate::server_state::PackedState;
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds, in seconds, of the tool call latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// How a Studio's websocket ended, the `reason` label of `rbx_mcp_studio_disconnects_total`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DisconnectReason {
+    /// The socket closed or failed.
+    Closed,
+    /// The Studio stopped answering pings and was evicted.
+    Unresponsive,
+    /// The Studio reconnected before the old socket noticed it was dead.
+    Replaced,
+}
+
+impl DisconnectReason {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Unresponsive => "unresponsive",
+            Self::Replaced => "replaced",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    /// By tool and `place_id`, `None` when the call never reached a Studio.
+    tool_calls: BTreeMap<(String, Option<u64>), ToolCallStats>,
+    studio_connections: u64,
+    studio_reconnects: u64,
+    studio_disconnects: BTreeMap<DisconnectReason, u64>,
+}
+
+#[derive(Default)]
+struct ToolCallStats {
+    calls: u64,
+    errors: u64,
+    /// Calls per bucket of `LATENCY_BUCKETS`, not cumulative; slower calls only count in `calls`.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    seconds: f64,
+}
+
+/// Values read from `AppState` at scrape time.
+pub struct Gauges {
+    pub connected_studios: usize,
+    pub reconnecting_studios: usize,
+    pub sessions: usize,
+    pub pending_requests: usize,
+}
+
+impl Metrics {
+    pub fn record_tool_call(
+        &self,
+        tool: &str,
+        place_id: Option<u64>,
+        duration: Duration,
+        is_error: bool,
+    ) {
+        let mut counters = self.inner.lock().unwrap();
+        let stats = counters
+            .tool_calls
+            .entry((tool.to_string(), place_id))
+            .or_default();
+        let seconds = duration.as_secs_f64();
+        stats.calls += 1;
+        stats.errors += u64::from(is_error);
+        stats.seconds += seconds;
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|le| seconds <= *le) {
+            stats.buckets[bucket] += 1;
+        }
+    }
+
+    /// A Studio registered; `reconnect` when it took back a `studio_id` it held before.
+    pub fn record_studio_connected(&self, reconnect: bool) {
+        let mut counters = self.inner.lock().unwrap();
+        if reconnect {
+            counters.studio_reconnects += 1;
+        } else {
+            counters.studio_connections += 1;
+        }
+    }
+
+    pub fn record_studio_disconnected(&self, reason: DisconnectReason) {
+        let mut counters = self.inner.lock().unwrap();
+        *counters.studio_disconnects.entry(reason).or_default() += 1;
+    }
+
+    pub fn render(&self, gauges: &Gauges) -> String {
+        let counters = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        describe(
+            &mut out,
+            "rbx_mcp_tool_calls_total",
+            "counter",
+            "Tool calls sent to Studio.",
+        );
+        for ((tool, place_id), stats) in &counters.tool_calls {
+            let labels = tool_labels(tool, *place_id);
+            let _ = writeln!(out, "rbx_mcp_tool_calls_total{{{labels}}} {}", stats.calls);
+        }
+        describe(
+            &mut out,
+            "rbx_mcp_tool_errors_total",
+            "counter",
+            "Tool calls sent to Studio that ended in an error, timeout or cancellation.",
+        );
+        for ((tool, place_id), stats) in &counters.tool_calls {
+            let labels = tool_labels(tool, *place_id);
+            let _ = writeln!(
+                out,
+                "rbx_mcp_tool_errors_total{{{labels}}} {}",
+                stats.errors
+            );
+        }
+        describe(
+            &mut out,
+            "rbx_mcp_tool_call_duration_seconds",
+            "histogram",
+            "Time from sending a tool call to Studio until its result.",
+        );
+        for ((tool, place_id), stats) in &counters.tool_calls {
+            let labels = tool_labels(tool, *place_id);
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "rbx_mcp_tool_call_duration_seconds_bucket{{{labels},le=\"{le}\"}} {cumulative}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "rbx_mcp_tool_call_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                stats.calls
+            );
+            let _ = writeln!(
+                out,
+                "rbx_mcp_tool_call_duration_seconds_sum{{{labels}}} {}",
+                stats.seconds
+            );
+            let _ = writeln!(
+                out,
+                "rbx_mcp_tool_call_duration_seconds_count{{{labels}}} {}",
+                stats.calls
+            );
+        }
+
+        describe(
+            &mut out,
+            "rbx_mcp_studio_connections_total",
+            "counter",
+            "Studios that connected with a new studio_id.",
+        );
+        let _ = writeln!(
+            out,
+            "rbx_mcp_studio_connections_total {}",
+            counters.studio_connections
+        );
+        describe(
+            &mut out,
+            "rbx_mcp_studio_reconnects_total",
+            "counter",
+            "Studios that reconnected and kept their studio_id.",
+        );
+        let _ = writeln!(
+            out,
+            "rbx_mcp_studio_reconnects_total {}",
+            counters.studio_reconnects
+        );
+        describe(
+            &mut out,
+            "rbx_mcp_studio_disconnects_total",
+            "counter",
+            "Studio websockets that ended, by reason.",
+        );
+        for (reason, count) in &counters.studio_disconnects {
+            let _ = writeln!(
+                out,
+                "rbx_mcp_studio_disconnects_total{{reason=\"{}\"}} {count}",
+                reason.label()
+            );
+        }
+
+        describe(
+            &mut out,
+            "rbx_mcp_studios",
+            "gauge",
+            "Listed Studios, by whether their websocket is connected or they are in the reconnect grace window.",
+        );
+        let _ = writeln!(
+            out,
+            "rbx_mcp_studios{{status=\"connected\"}} {}",
+            gauges.connected_studios
+        );
+        let _ = writeln!(
+            out,
+            "rbx_mcp_studios{{status=\"reconnecting\"}} {}",
+            gauges.reconnecting_studios
+        );
+        describe(&mut out, "rbx_mcp_sessions", "gauge", "Live MCP sessions.");
+        let _ = writeln!(out, "rbx_mcp_sessions {}", gauges.sessions);
+        describe(
+            &mut out,
+            "rbx_mcp_pending_requests",
+            "gauge",
+            "Tool calls waiting for a Studio to answer.",
+        );
+        let _ = writeln!(out, "rbx_mcp_pending_requests {}", gauges.pending_requests);
+        out
+    }
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// `place_id` is empty for calls that never reached a Studio.
+fn tool_labels(tool: &str, place_id: Option<u64>) -> String {
+    let place_id = place_id.map(|id| id.to_string()).unwrap_or_default();
+    format!("tool=\"{}\",place_id=\"{place_id}\"", escape(tool))
+}
+
+/// Escapes a label value as the exposition format requires.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+pub async fn metrics_handler(State(state): State<PackedState>) -> impl IntoResponse {
+    let s = state.lock().await;
+    let reconnecting_studios = s
+        .connections
+        .values()
+        .filter(|conn| conn.disconnected_at.is_some())
+        .count();
+    let gauges = Gauges {
+        connected_studios: s.connections.len() - reconnecting_studios,
+        reconnecting_studios,
+        sessions: s.sessions.len(),
+        pending_requests: s.output_map.len(),
+    };
+    let body = s.metrics.render(&gauges);
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        body,
+    )
+}
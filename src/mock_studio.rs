@@ -0,0 +1,662 @@
+//! A stand-in for the Studio plugin. It connects to `/ws`, registers the way the plugin does,
+//! answers pings, and replies to tool calls from a script of fixed or recorded responses. It can
+//! also misbehave on purpose: reply slowly, send frames the server cannot decode, stop answering
+//! pings or drop the connection.
+
+use crate::error::Result;
+use crate::wire::{
+    unframe, ChunkMessage, ChunkOutcome, FrameEncoding, OutboundMessage, Reassembler, WireFormat,
+    PLUGIN_BUILD_HASH, PROTOCOL_VERSION,
+};
+use axum::extract::ws::Message as AxumMessage;
+use color_eyre::eyre::eyre;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// The tools the bundled plugin provides.
+pub const PLUGIN_TOOLS: [&str; 5] = [
+    "run_code",
+    "insert_model",
+    "start_stop_play",
+    "run_script_in_play_mode",
+    "get_studio_mode",
+];
+
+/// What the mock registers as. `job_id` and `instance_token` are what the server recognises a
+/// returning Studio by.
+#[derive(Clone, Debug)]
+pub struct MockStudioConfig {
+    /// `ws://host:port/ws`; the token is added as a query parameter, as the plugin does.
+    pub url: String,
+    pub token: String,
+    pub place_id: u64,
+    pub place_name: String,
+    pub game_id: u64,
+    pub job_id: String,
+    pub instance_token: Option<String>,
+    /// Sent like the place's `MCPLabel` attribute.
+    pub label: Option<String>,
+    /// Frame encodings to offer, most preferred first. Empty registers like a plugin that
+    /// predates negotiation.
+    pub encodings: Vec<FrameEncoding>,
+    pub max_frame_size: Option<usize>,
+    pub tools: Vec<String>,
+    pub custom_tools: Vec<MockTool>,
+    /// Messages the mock says it can send unprompted. Empty registers like a plugin that
+    /// predates console streaming.
+    pub events: Vec<String>,
+    /// Server messages the mock says it handles beyond tool calls. Empty registers like a
+    /// plugin that predates endpoint pushes.
+    pub accepts: Vec<String>,
+}
+
+impl MockStudioConfig {
+    pub fn new(url: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            token: token.into(),
+            place_id: 1818,
+            place_name: "Mock Place".to_string(),
+            game_id: 1,
+            job_id: Uuid::new_v4().to_string(),
+            instance_token: Some(Uuid::new_v4().to_string()),
+            label: None,
+            encodings: vec![FrameEncoding::Binary, FrameEncoding::Base64],
+            max_frame_size: None,
+            tools: PLUGIN_TOOLS.iter().map(|tool| tool.to_string()).collect(),
+            custom_tools: Vec::new(),
+            events: vec!["console".to_string()],
+            accepts: vec!["config".to_string()],
+        }
+    }
+}
+
+/// A tool advertised at registration, like a module in the place's `ServerStorage.MCPTools`.
+#[derive(Clone, Debug, Serialize)]
+pub struct MockTool {
+    pub name: String,
+    pub description: Option<String>,
+    pub input_schema: serde_json::Value,
+}
+
+/// How the mock answers one tool call.
+#[derive(Clone, Debug)]
+pub enum Reply {
+    /// `success = true` with this response.
+    Success(serde_json::Value),
+    /// `success = false` with this error text.
+    Failure(String),
+    /// Sends the inner reply after a delay, unless the server cancels the call first.
+    Delayed(Duration, Box<Reply>),
+    /// Sends these `progress` messages right away if the server asked for progress, then
+    /// handles the inner reply.
+    Progress(Vec<String>, Box<Reply>),
+    /// A frame that is not msgpack, in place of the response.
+    Malformed,
+    /// Drops the connection without answering or closing the socket.
+    Disconnect,
+    /// Never answers.
+    Ignore,
+}
+
+/// Something the server sent, in the order it arrived.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockEvent {
+    Call {
+        id: Uuid,
+        tool: String,
+        args: serde_json::Value,
+    },
+    Cancel {
+        id: Uuid,
+    },
+    Ping {
+        seq: u32,
+    },
+}
+
+/// Replies queued with `MockStudio::once` are used first, in order, then the standing reply set
+/// with `MockStudio::on`.
+#[derive(Default)]
+struct Script {
+    once: HashMap<String, VecDeque<Reply>>,
+    standing: HashMap<String, Reply>,
+}
+
+impl Script {
+    fn reply_for(&mut self, tool: &str) -> Reply {
+        if let Some(reply) = self.once.get_mut(tool).and_then(VecDeque::pop_front) {
+            return reply;
+        }
+        self.standing.get(tool).cloned().unwrap_or_else(|| {
+            Reply::Failure(format!(
+                "The mock Studio has no reply scripted for `{tool}`"
+            ))
+        })
+    }
+}
+
+/// State the handle shares with the task serving the socket.
+struct Shared {
+    script: Mutex<Script>,
+    answer_pings: AtomicBool,
+    events: mpsc::UnboundedSender<MockEvent>,
+    /// The websocket URL from the server's last `config` message.
+    endpoint: Mutex<Option<String>>,
+}
+
+/// A connected mock Studio. Dropping it drops the connection.
+pub struct MockStudio {
+    pub studio_id: Uuid,
+    pub format: WireFormat,
+    config: MockStudioConfig,
+    shared: Arc<Shared>,
+    events: mpsc::UnboundedReceiver<MockEvent>,
+    link: Link,
+}
+
+/// One websocket connection and the task serving it.
+struct Link {
+    outgoing: mpsc::UnboundedSender<Message>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[derive(Serialize)]
+struct Registration<'a> {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    place_id: u64,
+    place_name: &'a str,
+    game_id: u64,
+    job_id: &'a str,
+    place_version: u64,
+    creator_id: u64,
+    creator_type: &'static str,
+    instance_token: Option<&'a str>,
+    label: Option<&'a str>,
+    encodings: &'a [FrameEncoding],
+    max_frame_size: Option<usize>,
+    protocol_version: u32,
+    plugin_build: &'static str,
+    tools: Vec<&'a str>,
+    custom_tools: &'a [MockTool],
+    events: &'a [String],
+    accepts: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct RegistrationAck {
+    #[serde(rename = "type")]
+    msg_type: String,
+    studio_id: String,
+    #[serde(default)]
+    encoding: Option<FrameEncoding>,
+    #[serde(default)]
+    max_frame_size: Option<usize>,
+}
+
+/// A tool call (no `type`), ping, cancel or config from the server.
+#[derive(Deserialize)]
+struct ServerMessage {
+    #[serde(rename = "type", default)]
+    msg_type: Option<String>,
+    #[serde(default)]
+    id: Option<Uuid>,
+    #[serde(default)]
+    tool: Option<String>,
+    #[serde(default)]
+    args: serde_json::Value,
+    #[serde(default)]
+    seq: Option<u32>,
+    #[serde(default)]
+    progress: bool,
+    #[serde(default)]
+    url: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ToolResponse<'a> {
+    id: Uuid,
+    success: bool,
+    response: &'a serde_json::Value,
+}
+
+/// An audit log line, as far as replying needs it.
+#[derive(Deserialize)]
+struct RecordedCall {
+    tool: String,
+    success: bool,
+    response: String,
+    #[serde(default)]
+    refused: bool,
+}
+
+impl MockStudio {
+    pub async fn connect(config: MockStudioConfig) -> Result<Self> {
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let shared = Arc::new(Shared {
+            script: Mutex::default(),
+            answer_pings: AtomicBool::new(true),
+            events: events_tx,
+            endpoint: Mutex::default(),
+        });
+        let (studio_id, format, link) = open_link(&config, shared.clone()).await?;
+        Ok(Self {
+            studio_id,
+            format,
+            config,
+            shared,
+            events,
+            link,
+        })
+    }
+
+    /// Answers every call to `tool` with `reply`, once any replies queued with `once` are used.
+    pub fn on(&self, tool: &str, reply: Reply) {
+        let mut script = self.shared.script.lock().unwrap();
+        script.standing.insert(tool.to_string(), reply);
+    }
+
+    /// Answers the next call to `tool` with `reply`.
+    pub fn once(&self, tool: &str, reply: Reply) {
+        let mut script = self.shared.script.lock().unwrap();
+        script
+            .once
+            .entry(tool.to_string())
+            .or_default()
+            .push_back(reply);
+    }
+
+    /// Queues the responses recorded in an audit log, so calls to each tool get that tool's
+    /// recorded answers in order. Refused calls never reached Studio and are left out.
+    pub fn load_recorded(&self, log: &Path) -> Result<usize> {
+        let text = std::fs::read_to_string(log)?;
+        let mut count = 0;
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let call: RecordedCall = serde_json::from_str(line)?;
+            if call.refused {
+                continue;
+            }
+            self.once(&call.tool, recorded_reply(&call));
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Whether to answer the server's pings. A mock that stops answering looks frozen and is
+    /// evicted once the heartbeat timeout passes.
+    pub fn answer_pings(&self, answer: bool) {
+        self.shared.answer_pings.store(answer, Ordering::SeqCst);
+    }
+
+    /// The next thing the server sent, waiting up to `timeout` for it. Pings are left out.
+    pub async fn next_event(&mut self, timeout: Duration) -> Option<MockEvent> {
+        tokio::time::timeout(timeout, async {
+            loop {
+                match self.events.recv().await? {
+                    MockEvent::Ping { .. } => continue,
+                    event => return Some(event),
+                }
+            }
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    /// The websocket URL the server last told the mock to reconnect to, which the plugin would
+    /// dial from its next reconnect on.
+    pub fn endpoint(&self) -> Option<String> {
+        self.shared.endpoint.lock().unwrap().clone()
+    }
+
+    /// Streams Output window entries, given as `(level, message)` and timestamped now, as the
+    /// plugin does for each batch logged. `dropped` counts entries lost before these.
+    pub fn console(&self, entries: &[(&str, &str)], dropped: u64) {
+        let timestamp = chrono::Utc::now().timestamp_millis() as f64 / 1000.0;
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(level, message)| {
+                serde_json::json!({ "level": level, "message": message, "timestamp": timestamp })
+            })
+            .collect();
+        let event =
+            serde_json::json!({ "type": "console", "entries": entries, "dropped": dropped });
+        let frames =
+            OutboundMessage::encode(&event, None).and_then(|message| self.format.frames(message));
+        for frame in frames.unwrap_or_default() {
+            self.send_raw(to_tungstenite(frame));
+        }
+    }
+
+    /// Sends a frame as is, such as a `Message::Text` that is not base64.
+    pub fn send_raw(&self, frame: Message) {
+        let _ = self.link.outgoing.send(frame);
+    }
+
+    /// False once the server has closed the connection, or it was dropped.
+    pub fn is_connected(&self) -> bool {
+        !self.link.task.is_finished()
+    }
+
+    /// Drops the connection without a close frame, as a crashed Studio would.
+    pub fn disconnect(&mut self) {
+        self.link.task.abort();
+    }
+
+    /// Connects again with the same `job_id` and instance token, as the plugin's reconnect loop
+    /// does. The server hands back the previous `studio_id` within its grace window.
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.link.task.abort();
+        let (studio_id, format, link) = open_link(&self.config, self.shared.clone()).await?;
+        self.studio_id = studio_id;
+        self.format = format;
+        self.link = link;
+        Ok(())
+    }
+}
+
+/// A recorded failure holds the tool result's content; Studio's error was its text.
+fn recorded_reply(call: &RecordedCall) -> Reply {
+    let response = serde_json::from_str(&call.response)
+        .unwrap_or_else(|_| serde_json::Value::String(call.response.clone()));
+    if call.success {
+        return Reply::Success(response);
+    }
+    let text = response
+        .as_array()
+        .and_then(|content| content.first())
+        .and_then(|item| item.get("text"))
+        .and_then(|text| text.as_str())
+        .map(str::to_string)
+        .unwrap_or(call.response.clone());
+    Reply::Failure(text)
+}
+
+/// Registers over a new websocket and starts serving it.
+async fn open_link(
+    config: &MockStudioConfig,
+    shared: Arc<Shared>,
+) -> Result<(Uuid, WireFormat, Link)> {
+    let url = format!("{}?token={}", config.url, config.token);
+    let (socket, _) = tokio_tungstenite::connect_async(url).await?;
+    let (mut sink, mut stream) = socket.split();
+
+    // Like the plugin, list custom tools among the tools it can run
+    let tools = config
+        .tools
+        .iter()
+        .map(String::as_str)
+        .chain(config.custom_tools.iter().map(|tool| tool.name.as_str()))
+        .collect();
+    let registration = Registration {
+        msg_type: "register",
+        place_id: config.place_id,
+        place_name: &config.place_name,
+        game_id: config.game_id,
+        job_id: &config.job_id,
+        place_version: 1,
+        creator_id: 1,
+        creator_type: "User",
+        instance_token: config.instance_token.as_deref(),
+        label: config.label.as_deref(),
+        encodings: &config.encodings,
+        max_frame_size: config.max_frame_size,
+        protocol_version: PROTOCOL_VERSION,
+        plugin_build: PLUGIN_BUILD_HASH,
+        tools,
+        custom_tools: &config.custom_tools,
+        events: &config.events,
+        accepts: &config.accepts,
+    };
+    // Registration and its ack always use the legacy framing
+    send(
+        &mut sink,
+        WireFormat::LEGACY,
+        OutboundMessage::encode(&registration, None)?,
+    )
+    .await?;
+    let ack = loop {
+        let frame = stream
+            .next()
+            .await
+            .ok_or_else(|| eyre!("the server closed the connection during registration"))??;
+        if let Some(body) = unframe(&to_axum(frame)) {
+            break rmp_serde::from_slice::<RegistrationAck>(&body?)?;
+        }
+    };
+    if ack.msg_type != "registered" {
+        return Err(eyre!("expected a registration ack, got `{}`", ack.msg_type).into());
+    }
+    let studio_id: Uuid = ack
+        .studio_id
+        .parse()
+        .map_err(|e| eyre!("invalid studio_id in the registration ack: {e}"))?;
+    let format = WireFormat {
+        encoding: ack.encoding.unwrap_or(FrameEncoding::Base64),
+        max_frame_size: ack.max_frame_size,
+    };
+
+    let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+    let task = tokio::spawn(serve(
+        sink,
+        stream,
+        format,
+        shared,
+        outgoing.clone(),
+        outgoing_rx,
+    ));
+    Ok((studio_id, format, Link { outgoing, task }))
+}
+
+/// Answers the server until the connection drops or a `Reply::Disconnect` ends it.
+async fn serve(
+    mut sink: SplitSink<Socket, Message>,
+    mut stream: SplitStream<Socket>,
+    format: WireFormat,
+    shared: Arc<Shared>,
+    outgoing: mpsc::UnboundedSender<Message>,
+    mut outgoing_rx: mpsc::UnboundedReceiver<Message>,
+) {
+    let mut reassembler = Reassembler::default();
+    let mut delayed: HashMap<Uuid, JoinHandle<()>> = HashMap::new();
+    loop {
+        let frame = tokio::select! {
+            frame = stream.next() => frame,
+            Some(frame) = outgoing_rx.recv() => {
+                if sink.send(frame).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+        let frame = match frame {
+            Some(Ok(frame)) => frame,
+            _ => break,
+        };
+        let Some(Ok(body)) = unframe(&to_axum(frame)) else {
+            continue;
+        };
+        let Some(body) = reassemble(&mut reassembler, body) else {
+            continue;
+        };
+        let Ok(message) = rmp_serde::from_slice::<ServerMessage>(&body) else {
+            continue;
+        };
+
+        match message.msg_type.as_deref() {
+            Some("ping") => {
+                let seq = message.seq.unwrap_or(0);
+                let _ = shared.events.send(MockEvent::Ping { seq });
+                if shared.answer_pings.load(Ordering::SeqCst) {
+                    let pong = serde_json::json!({ "type": "pong", "seq": seq });
+                    if let Ok(pong) = OutboundMessage::encode(&pong, None) {
+                        if send(&mut sink, format, pong).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            Some("cancel") => {
+                let Some(id) = message.id else {
+                    continue;
+                };
+                if let Some(reply) = delayed.remove(&id) {
+                    reply.abort();
+                }
+                let _ = shared.events.send(MockEvent::Cancel { id });
+            }
+            Some("config") => {
+                *shared.endpoint.lock().unwrap() = message.url;
+            }
+            Some(_) => {}
+            None => {
+                let (Some(id), Some(tool)) = (message.id, message.tool) else {
+                    continue;
+                };
+                let mut reply = shared.script.lock().unwrap().reply_for(&tool);
+                let _ = shared.events.send(MockEvent::Call {
+                    id,
+                    tool,
+                    args: message.args,
+                });
+                if let Reply::Progress(messages, inner) = reply {
+                    for frame in messages
+                        .iter()
+                        .filter(|_| message.progress)
+                        .flat_map(|text| progress_frames(format, id, text))
+                    {
+                        if sink.send(frame).await.is_err() {
+                            break;
+                        }
+                    }
+                    reply = *inner;
+                }
+                match reply {
+                    Reply::Disconnect => break,
+                    Reply::Delayed(delay, reply) => {
+                        let outgoing = outgoing.clone();
+                        let task = tokio::spawn(async move {
+                            tokio::time::sleep(delay).await;
+                            for frame in reply_frames(format, id, &reply) {
+                                let _ = outgoing.send(frame);
+                            }
+                        });
+                        delayed.retain(|_, task| !task.is_finished());
+                        delayed.insert(id, task);
+                    }
+                    reply => {
+                        for frame in reply_frames(format, id, &reply) {
+                            if sink.send(frame).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for (_, reply) in delayed {
+        reply.abort();
+    }
+}
+
+/// The frames of a `progress` message for the call `id`.
+fn progress_frames(format: WireFormat, id: Uuid, message: &str) -> Vec<Message> {
+    let progress = serde_json::json!({ "type": "progress", "id": id, "message": message });
+    OutboundMessage::encode(&progress, Some(id))
+        .and_then(|message| format.frames(message))
+        .map(|frames| frames.into_iter().map(to_tungstenite).collect())
+        .unwrap_or_default()
+}
+
+/// The frames that carry `reply` to the call `id`.
+fn reply_frames(format: WireFormat, id: Uuid, reply: &Reply) -> Vec<Message> {
+    let (success, response) = match reply {
+        Reply::Success(response) => (true, response.clone()),
+        Reply::Failure(error) => (false, serde_json::Value::String(error.clone())),
+        Reply::Malformed => return vec![Message::Binary(vec![0xc1, 0xc1, 0xc1].into())],
+        // A delayed reply inside a delayed reply waits only once, and progress is only sent
+        // for a top-level `Progress`
+        Reply::Delayed(_, reply) | Reply::Progress(_, reply) => {
+            return reply_frames(format, id, reply)
+        }
+        Reply::Disconnect | Reply::Ignore => return Vec::new(),
+    };
+    let response = ToolResponse {
+        id,
+        success,
+        response: &response,
+    };
+    OutboundMessage::encode(&response, Some(id))
+        .and_then(|message| format.frames(message))
+        .map(|frames| frames.into_iter().map(to_tungstenite).collect())
+        .unwrap_or_default()
+}
+
+async fn send(
+    sink: &mut SplitSink<Socket, Message>,
+    format: WireFormat,
+    message: OutboundMessage,
+) -> Result<()> {
+    for frame in format.frames(message)? {
+        sink.send(to_tungstenite(frame)).await?;
+    }
+    Ok(())
+}
+
+/// Passes ordinary messages through and collects chunks until their message is complete.
+fn reassemble(reassembler: &mut Reassembler, body: Vec<u8>) -> Option<Vec<u8>> {
+    let Ok(chunk) = rmp_serde::from_slice::<ChunkMessage>(&body) else {
+        return Some(body);
+    };
+    if chunk.msg_type != "chunk" {
+        return Some(body);
+    }
+    match reassembler.push(chunk) {
+        Ok(ChunkOutcome::Complete(body)) => Some(body),
+        Ok(ChunkOutcome::Partial { .. }) | Err(_) => None,
+    }
+}
+
+// `wire` frames axum's websocket messages; the mock is a tungstenite client
+
+fn to_tungstenite(message: AxumMessage) -> Message {
+    match message {
+        AxumMessage::Binary(bytes) => Message::Binary(bytes),
+        AxumMessage::Text(text) => Message::Text(text.as_str().into()),
+        AxumMessage::Ping(bytes) => Message::Ping(bytes),
+        AxumMessage::Pong(bytes) => Message::Pong(bytes),
+        AxumMessage::Close(_) => Message::Close(None),
+    }
+}
+
+fn to_axum(message: Message) -> AxumMessage {
+    match message {
+        Message::Binary(bytes) => AxumMessage::Binary(bytes),
+        Message::Text(text) => AxumMessage::Text(text.as_str().into()),
+        Message::Ping(bytes) => AxumMessage::Ping(bytes),
+        Message::Pong(bytes) => AxumMessage::Pong(bytes),
+        Message::Close(_) | Message::Frame(_) => AxumMessage::Close(None),
+    }
+}
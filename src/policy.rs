@@ -0,0 +1,370 @@
+//! Which tools an agent may see and call. The `[policy]` table sets a global allow list, deny
+//! list and read-only switch; `[[policy.rules]]` entries override them for particular places,
+//! games, MCP sessions or clients.
+
+use rmcp::model::Tool;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Tools that only change which Studio the calling session talks to, or give up its own hold
+/// on one. They change no place, so read-only mode still allows them, though they are not
+/// annotated read-only. `allow` and `deny` apply to them as to any tool.
+const SESSION_TOOLS: [&str; 2] = ["set_studio", "release_lease"];
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, clap::Args)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicySettings {
+    /// Only expose tools annotated read-only.
+    #[arg(long, env = "RBX_MCP_READ_ONLY", value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub read_only: Option<bool>,
+
+    /// Tool, `prefix_*` glob or annotation (`@read_only`, `@destructive`, `@idempotent`,
+    /// `@open_world`) that agents may use; anything unmatched is refused. May be repeated.
+    #[arg(
+        long = "allow-tool",
+        env = "RBX_MCP_ALLOW_TOOLS",
+        value_name = "PATTERN",
+        value_delimiter = ','
+    )]
+    pub allow: Vec<ToolPattern>,
+
+    /// Tool, glob or annotation that agents may not use. May be repeated.
+    #[arg(
+        long = "deny-tool",
+        env = "RBX_MCP_DENY_TOOLS",
+        value_name = "PATTERN",
+        value_delimiter = ','
+    )]
+    pub deny: Vec<ToolPattern>,
+
+    /// Leave refused tools out of `tools/list` [default: true].
+    #[arg(long, env = "RBX_MCP_HIDE_DENIED", value_name = "BOOL")]
+    pub hide_denied: Option<bool>,
+
+    /// Overrides for particular Studios and sessions; config file only.
+    #[arg(skip)]
+    pub rules: Vec<PolicyRule>,
+}
+
+/// A `[[policy.rules]]` entry. Every scope field it sets must match; each policy field it sets
+/// replaces the value from the global policy or an earlier rule.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyRule {
+    pub place_id: Option<u64>,
+    pub game_id: Option<u64>,
+    /// `mcp-session-id` of an HTTP session, or the id `list_sessions` shows for a stdio one.
+    pub session: Option<String>,
+    /// Client name from the MCP `initialize` request, such as `claude-code`.
+    pub client: Option<String>,
+    pub read_only: Option<bool>,
+    pub allow: Option<Vec<ToolPattern>>,
+    pub deny: Option<Vec<ToolPattern>>,
+}
+
+/// Who is asking and which Studio the call would go to.
+pub struct PolicyContext<'a> {
+    pub session_id: &'a str,
+    pub client: Option<&'a str>,
+    /// `(place_id, game_id)` of the session's Studio, when it has one.
+    pub studio: Option<(u64, u64)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ToolPattern {
+    Name(String),
+    Prefix(String),
+    Annotation(Annotation),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Annotation {
+    ReadOnly,
+    Destructive,
+    Idempotent,
+    OpenWorld,
+}
+
+impl PolicySettings {
+    /// `Err` carries the reason `tool` is refused in `context`.
+    pub fn evaluate(&self, tool: &Tool, context: &PolicyContext) -> Result<(), String> {
+        let mut read_only = self.read_only.unwrap_or(false);
+        let mut allow = &self.allow;
+        let mut deny = &self.deny;
+        for rule in self.rules.iter().filter(|rule| rule.applies_to(context)) {
+            read_only = rule.read_only.unwrap_or(read_only);
+            allow = rule.allow.as_ref().unwrap_or(allow);
+            deny = rule.deny.as_ref().unwrap_or(deny);
+        }
+
+        if let Some(pattern) = deny.iter().find(|pattern| pattern.matches(tool)) {
+            return Err(format!("it matches the deny pattern `{pattern}`"));
+        }
+        if !allow.is_empty() && !allow.iter().any(|pattern| pattern.matches(tool)) {
+            return Err("it is not on the allow list".to_string());
+        }
+        if read_only && !Annotation::ReadOnly.of(tool) && !SESSION_TOOLS.contains(&&*tool.name) {
+            return Err("the server is in read-only mode".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn hide_denied(&self) -> bool {
+        self.hide_denied.unwrap_or(true)
+    }
+}
+
+impl PolicyRule {
+    fn applies_to(&self, context: &PolicyContext) -> bool {
+        let place_id = context.studio.map(|(place_id, _)| place_id);
+        let game_id = context.studio.map(|(_, game_id)| game_id);
+        (self.place_id.is_none() || self.place_id == place_id)
+            && (self.game_id.is_none() || self.game_id == game_id)
+            && self
+                .session
+                .as_deref()
+                .is_none_or(|session| session == context.session_id)
+            && self
+                .client
+                .as_deref()
+                .is_none_or(|client| Some(client) == context.client)
+    }
+}
+
+impl ToolPattern {
+    pub fn matches(&self, tool: &Tool) -> bool {
+        match self {
+            Self::Name(name) => tool.name == *name,
+            Self::Prefix(prefix) => tool.name.starts_with(prefix.as_str()),
+            Self::Annotation(annotation) => annotation.of(tool),
+        }
+    }
+}
+
+impl Annotation {
+    const NAMES: [(&str, Annotation); 4] = [
+        ("read_only", Self::ReadOnly),
+        ("destructive", Self::Destructive),
+        ("idempotent", Self::Idempotent),
+        ("open_world", Self::OpenWorld),
+    ];
+
+    /// Missing hints take the defaults from the MCP spec, so a tool that says nothing about
+    /// itself (such as a plugin tool) counts as destructive, open-world and not read-only.
+    fn of(self, tool: &Tool) -> bool {
+        let annotations = tool.annotations.as_ref();
+        match self {
+            Self::ReadOnly => annotations.and_then(|a| a.read_only_hint).unwrap_or(false),
+            Self::Destructive => annotations.and_then(|a| a.destructive_hint).unwrap_or(true),
+            Self::Idempotent => annotations.and_then(|a| a.idempotent_hint).unwrap_or(false),
+            Self::OpenWorld => annotations.and_then(|a| a.open_world_hint).unwrap_or(true),
+        }
+    }
+}
+
+impl FromStr for ToolPattern {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if let Some(name) = value.strip_prefix('@') {
+            return Annotation::NAMES
+                .iter()
+                .find(|(known, _)| *known == name)
+                .map(|(_, annotation)| Self::Annotation(*annotation))
+                .ok_or_else(|| {
+                    format!("unknown annotation `@{name}`; expected @read_only, @destructive, @idempotent or @open_world")
+                });
+        }
+        match value.strip_suffix('*') {
+            Some(prefix) if !prefix.contains('*') => Ok(Self::Prefix(prefix.to_string())),
+            None if !value.is_empty() && !value.contains('*') => Ok(Self::Name(value.to_string())),
+            _ => Err(format!(
+                "invalid tool pattern `{value}`; `*` may only end a pattern"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for ToolPattern {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for ToolPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) => f.write_str(name),
+            Self::Prefix(prefix) => write!(f, "{prefix}*"),
+            Self::Annotation(annotation) => {
+                let (name, _) = Annotation::NAMES
+                    .iter()
+                    .find(|(_, known)| known == annotation)
+                    .unwrap();
+                write!(f, "@{name}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::ToolAnnotations;
+
+    fn tool(name: &'static str, read_only: Option<bool>) -> Tool {
+        let tool = Tool::new(name, "", serde_json::Map::new());
+        match read_only {
+            Some(read_only) => tool.annotate(ToolAnnotations::new().read_only(read_only)),
+            None => tool,
+        }
+    }
+
+    fn patterns(values: &[&str]) -> Vec<ToolPattern> {
+        values.iter().map(|value| value.parse().unwrap()).collect()
+    }
+
+    fn context(studio: Option<(u64, u64)>) -> PolicyContext<'static> {
+        PolicyContext {
+            session_id: "session",
+            client: Some("claude-code"),
+            studio,
+        }
+    }
+
+    #[test]
+    fn patterns_match_names_prefixes_and_annotations() {
+        let policy = PolicySettings {
+            allow: patterns(&["get_*", "run_code", "@read_only"]),
+            ..Default::default()
+        };
+        let anywhere = context(None);
+        assert!(policy
+            .evaluate(&tool("get_studio_mode", None), &anywhere)
+            .is_ok());
+        assert!(policy.evaluate(&tool("run_code", None), &anywhere).is_ok());
+        assert!(policy
+            .evaluate(&tool("list_studios", Some(true)), &anywhere)
+            .is_ok());
+        assert!(policy
+            .evaluate(&tool("run_code_multi", None), &anywhere)
+            .is_err());
+        assert!(policy
+            .evaluate(&tool("insert_model", Some(false)), &anywhere)
+            .is_err());
+
+        // Unannotated tools count as destructive
+        let policy = PolicySettings {
+            deny: patterns(&["@destructive"]),
+            ..Default::default()
+        };
+        assert!(policy
+            .evaluate(&tool("plugin_tool", None), &anywhere)
+            .is_err());
+        assert!(policy
+            .evaluate(&tool("list_studios", Some(true)), &anywhere)
+            .is_err());
+        let safe = tool("list_studios", Some(true))
+            .annotate(ToolAnnotations::new().read_only(true).destructive(false));
+        assert!(policy.evaluate(&safe, &anywhere).is_ok());
+
+        assert!("run_*_multi".parse::<ToolPattern>().is_err());
+        assert!("@unknown".parse::<ToolPattern>().is_err());
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let policy = PolicySettings {
+            allow: patterns(&["run_*"]),
+            deny: patterns(&["run_code"]),
+            ..Default::default()
+        };
+        let reason = policy
+            .evaluate(&tool("run_code", None), &context(None))
+            .unwrap_err();
+        assert!(reason.contains("deny pattern `run_code`"), "{reason}");
+        assert!(policy
+            .evaluate(&tool("run_script_in_play_mode", None), &context(None))
+            .is_ok());
+    }
+
+    #[test]
+    fn rules_override_the_global_policy_for_their_scope() {
+        let policy = PolicySettings {
+            deny: patterns(&["run_code"]),
+            rules: vec![
+                PolicyRule {
+                    place_id: Some(1818),
+                    deny: Some(Vec::new()),
+                    ..Default::default()
+                },
+                PolicyRule {
+                    game_id: Some(7),
+                    read_only: Some(true),
+                    ..Default::default()
+                },
+                PolicyRule {
+                    client: Some("inspector".to_string()),
+                    deny: Some(patterns(&["*"])),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let run_code = tool("run_code", None);
+        assert!(policy.evaluate(&run_code, &context(None)).is_err());
+        assert!(policy
+            .evaluate(&run_code, &context(Some((1818, 1))))
+            .is_ok());
+        assert!(policy.evaluate(&run_code, &context(Some((2, 1)))).is_err());
+
+        // Later rules build on earlier ones: place 1818 in game 7 is allowed run_code but
+        // read-only
+        let reason = policy
+            .evaluate(&run_code, &context(Some((1818, 7))))
+            .unwrap_err();
+        assert!(reason.contains("read-only"), "{reason}");
+        assert!(policy
+            .evaluate(&tool("list_studios", Some(true)), &context(Some((1818, 7))))
+            .is_ok());
+
+        let inspector = PolicyContext {
+            client: Some("inspector"),
+            ..context(Some((1818, 1)))
+        };
+        assert!(policy
+            .evaluate(&tool("list_studios", Some(true)), &inspector)
+            .is_err());
+    }
+
+    #[test]
+    fn read_only_mode_still_allows_session_tools() {
+        let policy = PolicySettings {
+            read_only: Some(true),
+            ..Default::default()
+        };
+        for name in SESSION_TOOLS {
+            assert!(policy
+                .evaluate(&tool(name, Some(false)), &context(None))
+                .is_ok());
+        }
+        assert!(policy
+            .evaluate(&tool("set_studio_label", Some(false)), &context(None))
+            .is_err());
+
+        // They are exempt from read-only mode only, not from the deny list
+        let policy = PolicySettings {
+            read_only: Some(true),
+            deny: patterns(&["set_studio"]),
+            ..Default::default()
+        };
+        assert!(policy
+            .evaluate(&tool("set_studio", Some(false)), &context(None))
+            .is_err());
+    }
+}
@@ -1,55 +1,349 @@
+use crate::approval::request_approval;
+use crate::console::ConsoleBuffer;
 use crate::error::Result;
+use crate::lease::StudioLeases;
+use crate::metrics::DisconnectReason;
+use crate::policy::PolicyContext;
+use crate::request_queue::RequestQueue;
+use crate::resources::{self, ResourceEvent, StudioResource};
 use crate::server_state::{
-    value_to_mcp_string, PackedState, RegistrationMessage, RunCommandResponse, StudioConnection,
+    msgpack_to_error_text, msgpack_to_json, AppState, OutboundQueue, PackedState, PluginMessage,
+    RegistrationMessage, RunCommandResponse, StudioConnection, StudioQueue,
+    CONNECTION_SESSION_PREFIX,
+};
+use crate::wire::{
+    unframe, ChunkMessage, ChunkOutcome, OutboundMessage, Reassembler, WireFormat,
+    PLUGIN_BUILD_HASH, PROTOCOL_VERSION,
 };
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
 use axum::extract::State;
 use axum::response::IntoResponse;
-use base64::Engine;
+use futures_util::stream::SplitSink;
 use futures_util::{SinkExt, StreamExt};
 use rmcp::{
-    handler::server::router::tool::ToolRouter,
-    model::{Implementation, ProtocolVersion, ServerCapabilities, ServerInfo},
-    tool_handler, ServerHandler,
+    handler::server::{router::tool::ToolRouter, tool::ToolCallContext},
+    model::{
+        CallToolRequestParams, CallToolResult, Content, Implementation, JsonObject,
+        ListResourceTemplatesResult, ListResourcesResult, ListToolsResult, PaginatedRequestParams,
+        ProtocolVersion, ReadResourceRequestParams, ReadResourceResult,
+        ResourceUpdatedNotificationParam, ServerCapabilities, ServerInfo, SubscribeRequestParams,
+        Tool, UnsubscribeRequestParams,
+    },
+    service::{NotificationContext, RequestContext},
+    ErrorData, RoleServer, ServerHandler,
 };
+use rmpv::Value as MsgpackValue;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
-pub const STUDIO_PLUGIN_PORT: u16 = 44756;
-
-const B64: base64::engine::general_purpose::GeneralPurpose =
-    base64::engine::general_purpose::STANDARD;
-
-pub fn ws_encode<T: serde::Serialize>(value: &T) -> Result<String> {
-    let msgpack_bytes = rmp_serde::to_vec_named(value)?;
-    Ok(B64.encode(&msgpack_bytes))
-}
-
-pub fn ws_decode<T: serde::de::DeserializeOwned>(b64_text: &str) -> Result<T> {
-    let bytes = B64.decode(b64_text)?;
-    Ok(rmp_serde::from_slice(&bytes)?)
-}
-
 #[derive(Clone)]
 pub struct RBXStudioServer {
     pub(crate) state: PackedState,
+    /// Session id used when requests carry no `mcp-session-id` header.
+    pub(crate) connection_session_id: String,
     tool_router: ToolRouter<Self>,
+    /// Resource URIs this MCP session subscribed to.
+    subscriptions: Arc<Mutex<HashSet<String>>>,
 }
 
 impl RBXStudioServer {
     pub fn new(state: PackedState) -> Self {
         Self {
             state,
+            connection_session_id: format!("{CONNECTION_SESSION_PREFIX}{}", Uuid::new_v4()),
             tool_router: Self::build_tool_router(),
+            subscriptions: Arc::default(),
+        }
+    }
+
+    /// The built-in or plugin-advertised tool called `name`.
+    pub(crate) async fn find_tool(&self, name: &str) -> Option<Tool> {
+        if let Some(tool) = self.tool_router.get(name) {
+            return Some(tool.clone());
         }
+        let plugin_tools = self.state.lock().await.plugin_tools();
+        plugin_tools
+            .into_iter()
+            .find(|tool| tool.name == name)
+            .map(|tool| tool.to_tool())
+    }
+
+    /// Asks the tool policy whether this session may call `tool` in `studio_id` (by default
+    /// its own Studio), logging the decision.
+    async fn check_policy(
+        &self,
+        context: &RequestContext<RoleServer>,
+        studio_id: Option<Uuid>,
+        tool: &Tool,
+    ) -> std::result::Result<(), String> {
+        let session_id = self.extract_mcp_session_id(context);
+        let s = self.state.lock().await;
+        let decision = s
+            .policy
+            .evaluate(tool, &policy_context(&s, context, &session_id, studio_id));
+        match &decision {
+            Ok(()) => tracing::info!("Policy allowed `{}` for session {session_id}", tool.name),
+            Err(reason) => tracing::warn!(
+                "Policy refused `{}` for session {session_id}: {reason}",
+                tool.name
+            ),
+        }
+        decision
+    }
+
+    /// Runs the tool policy and any required approval for a call to `tool` in `studio_id`, or
+    /// in the session's Studio when that is `None`. A refusal is audited and returned as the
+    /// result to send back instead of running the call.
+    pub(crate) async fn authorize(
+        &self,
+        context: &RequestContext<RoleServer>,
+        studio_id: Option<Uuid>,
+        tool: &Tool,
+        arguments: &JsonObject,
+    ) -> Option<CallToolResult> {
+        let started = Instant::now();
+        let session_id = self.extract_mcp_session_id(context);
+        let refusal = match self.check_policy(context, studio_id, tool).await {
+            Err(reason) => format!(
+                "`{}` is not allowed by the server's tool policy: {reason}.",
+                tool.name
+            ),
+            Ok(()) => request_approval(
+                &self.state,
+                context,
+                &session_id,
+                studio_id,
+                tool,
+                arguments,
+            )
+            .await
+            .err()
+            .map(|reason| format!("`{}` was not approved: {reason}.", tool.name))?,
+        };
+        Some(
+            self.refuse(context, studio_id, tool, arguments, started, refusal)
+                .await,
+        )
+    }
+
+    /// Like `authorize`, but only the tool policy, for the calls of a fan-out that was approved
+    /// as a whole.
+    pub(crate) async fn authorize_policy(
+        &self,
+        context: &RequestContext<RoleServer>,
+        studio_id: Option<Uuid>,
+        tool: &Tool,
+        arguments: &JsonObject,
+    ) -> Option<CallToolResult> {
+        let started = Instant::now();
+        let reason = self.check_policy(context, studio_id, tool).await.err()?;
+        let refusal = format!(
+            "`{}` is not allowed by the server's tool policy: {reason}.",
+            tool.name
+        );
+        Some(
+            self.refuse(context, studio_id, tool, arguments, started, refusal)
+                .await,
+        )
+    }
+
+    async fn refuse(
+        &self,
+        context: &RequestContext<RoleServer>,
+        studio_id: Option<Uuid>,
+        tool: &Tool,
+        arguments: &JsonObject,
+        started: Instant,
+        refusal: String,
+    ) -> CallToolResult {
+        let session_id = self.extract_mcp_session_id(context);
+        let result = CallToolResult::error(vec![Content::text(refusal)]);
+        let arguments = serde_json::Value::Object(arguments.clone());
+        let s = self.state.lock().await;
+        let target = s.audit_target(&session_id, studio_id);
+        s.audit
+            .record_refusal(&session_id, target, &tool.name, arguments, started, &result);
+        result
     }
 }
 
-#[tool_handler]
+fn policy_context<'a>(
+    state: &AppState,
+    context: &'a RequestContext<RoleServer>,
+    session_id: &'a str,
+    studio_id: Option<Uuid>,
+) -> PolicyContext<'a> {
+    PolicyContext {
+        session_id,
+        client: context
+            .peer
+            .peer_info()
+            .map(|info| info.client_info.name.as_str()),
+        studio: state
+            .call_studio(session_id, studio_id)
+            .map(|(_, conn)| (conn.place_id, conn.game_id)),
+    }
+}
+
+// Tool listing and calls are written out rather than generated with `#[tool_handler]` so
+// plugin-advertised tools can sit alongside the built-in router
 impl ServerHandler for RBXStudioServer {
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> std::result::Result<CallToolResult, ErrorData> {
+        // Records the client before the policy looks up the session's Studio
+        self.resolve_session(&context).await;
+        if let Some(tool) = self.find_tool(&request.name).await {
+            let arguments = request.arguments.clone().unwrap_or_default();
+            if let Some(refusal) = self.authorize(&context, None, &tool, &arguments).await {
+                return Ok(refusal);
+            }
+        }
+        if !self.tool_router.has_route(&request.name) && self.has_plugin_tool(&request.name).await {
+            let args = request.arguments.unwrap_or_default();
+            return self.call_plugin_tool(&context, &request.name, args).await;
+        }
+        let tcc = ToolCallContext::new(self, request, context);
+        self.tool_router.call(tcc).await
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        context: RequestContext<RoleServer>,
+    ) -> std::result::Result<ListToolsResult, ErrorData> {
+        let mut tools = self.tool_router.list_all();
+        let plugin_tools = self.state.lock().await.plugin_tools();
+        for tool in plugin_tools {
+            if self.tool_router.has_route(&tool.name) {
+                tracing::warn!(
+                    "Ignoring plugin tool `{}`, which shadows a built-in",
+                    tool.name
+                );
+                continue;
+            }
+            tools.push(tool.to_tool());
+        }
+        let session_id = self.extract_mcp_session_id(&context);
+        let s = self.state.lock().await;
+        if s.policy.hide_denied() {
+            let policy_context = policy_context(&s, &context, &session_id, None);
+            tools.retain(|tool| s.policy.evaluate(tool, &policy_context).is_ok());
+        }
+        Ok(ListToolsResult {
+            tools,
+            meta: None,
+            next_cursor: None,
+        })
+    }
+
+    fn get_tool(&self, name: &str) -> Option<Tool> {
+        // Only built-ins: plugin tools have no output schema for rmcp to validate against
+        self.tool_router.get(name).cloned()
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> std::result::Result<ListResourcesResult, ErrorData> {
+        let resources = resources::list(&*self.state.lock().await);
+        Ok(ListResourcesResult::with_all_items(resources))
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> std::result::Result<ListResourceTemplatesResult, ErrorData> {
+        Ok(ListResourceTemplatesResult::with_all_items(
+            resources::templates(),
+        ))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParams,
+        context: RequestContext<RoleServer>,
+    ) -> std::result::Result<ReadResourceResult, ErrorData> {
+        self.read_studio_resource(&context, &request.uri).await
+    }
+
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> std::result::Result<(), ErrorData> {
+        self.find_resource(&request.uri).await?;
+        self.subscriptions.lock().await.insert(request.uri);
+        Ok(())
+    }
+
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> std::result::Result<(), ErrorData> {
+        self.subscriptions.lock().await.remove(&request.uri);
+        Ok(())
+    }
+
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        let (mut tool_changes, mut resource_events) = {
+            let s = self.state.lock().await;
+            (
+                s.tool_list_changed.subscribe(),
+                s.resource_events.subscribe(),
+            )
+        };
+        let subscriptions = self.subscriptions.clone();
+        let peer = context.peer;
+        // Ends at the first change after the session closes
+        tokio::spawn(async move {
+            loop {
+                let sent = tokio::select! {
+                    change = tool_changes.recv() => match change {
+                        Ok(()) | Err(RecvError::Lagged(_)) => peer.notify_tool_list_changed().await,
+                        Err(RecvError::Closed) => break,
+                    },
+                    event = resource_events.recv() => match event {
+                        Ok(ResourceEvent::ListChanged) => peer.notify_resource_list_changed().await,
+                        Ok(ResourceEvent::Updated(uri)) => {
+                            if !subscriptions.lock().await.contains(&uri) {
+                                continue;
+                            }
+                            peer.notify_resource_updated(ResourceUpdatedNotificationParam { uri })
+                                .await
+                        }
+                        // Missed events may have changed anything
+                        Err(RecvError::Lagged(_)) => peer.notify_resource_list_changed().await,
+                        Err(RecvError::Closed) => break,
+                    },
+                };
+                if peer.is_transport_closed() || sent.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::LATEST,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_tool_list_changed()
+                .enable_resources()
+                .enable_resources_list_changed()
+                .enable_resources_subscribe()
+                .build(),
             server_info: Implementation {
                 name: "Roblox_Studio".to_string(),
                 version: env!("CARGO_PKG_VERSION").to_string(),
@@ -67,12 +361,17 @@ After calling run_script_in_play_mode, the datamodel status will be reset to sto
 MULTI-STUDIO: Multiple studios may be connected. Each agent session is isolated.
 - Call list_studios to see all connected studios with their studio_id and metadata.
 - Call get_studio to check which studio your session is currently targeting.
-- Call set_studio(studio_id=X) to bind your session to a studio.
+- Call set_studio(studio_id=X) to bind your session to a studio. X may also be a studio's label, place name or place_id.
+- Call set_studio_label to name a studio (for example 'main') so it is easy to select again.
 - All subsequent calls route to the selected studio automatically.
 - For cross-studio operations, call set_studio to switch before each action.
-- When exactly one studio is connected, selection is automatic.
-- Studios are identified by studio_id (server-assigned UUID per WebSocket connection). A reconnecting studio gets a new studio_id.
-- If get_studio returns nothing, the selected studio disconnected -- use list_studios and set_studio to pick a new one."
+- To run the same code in several studios at once, call run_code_multi with studio_ids, a place_id or game_id, or all=true instead.
+- When exactly one studio is connected, selection is automatic. The server config may also auto-select a studio for new sessions; call get_studio to see which.
+- Studios are identified by studio_id (server-assigned UUID). A Studio that reconnects within a short grace window keeps its studio_id and session bindings; while it shows status `reconnecting`, calls to it are queued.
+- If other agents may be working in the same studio, call acquire_lease before making changes and release_lease when done. A call refused because the studio is locked by another session can be retried after acquire_lease with wait_secs.
+- Calls that change a studio run one at a time per studio, in arrival order. If a call seems slow to start, call list_pending_requests to see what is ahead of it.
+- If get_studio returns nothing, the selected studio disconnected -- use list_studios and set_studio to pick a new one.
+- Each studio's details, console output and mode are also resources (studio://{studio_id}/info, /console, /mode). Subscribe to them to be notified of new output or a disconnect instead of polling."
                     .to_string(),
             ),
         }
@@ -90,132 +389,521 @@ async fn handle_studio_connection(socket: WebSocket, state: PackedState) {
     let (mut ws_sender, mut ws_receiver) = socket.split();
 
     let registration = match ws_receiver.next().await {
-        Some(Ok(Message::Text(text))) => match ws_decode::<RegistrationMessage>(&text) {
-            Ok(reg) => reg,
+        Some(Ok(message)) => match decode_registration(&message) {
+            Ok(reg) if reg.msg_type == "register" => reg,
+            Ok(reg) => {
+                tracing::error!("Expected registration message, got type {:?}", reg.msg_type);
+                return;
+            }
             Err(e) => {
                 tracing::error!("Invalid registration message: {e}");
                 return;
             }
         },
         other => {
-            tracing::error!("Expected text registration message, got: {other:?}");
+            tracing::error!("Expected registration message, got: {other:?}");
             return;
         }
     };
 
-    let studio_id = Uuid::new_v4();
-    tracing::info!(
-        "Studio connected: {} (place_id={}, place_name={})",
-        studio_id,
-        registration.place_id,
-        registration.place_name
-    );
+    let format = WireFormat::negotiate(&registration.encodings, registration.max_frame_size);
+    if registration.protocol_version != Some(PROTOCOL_VERSION)
+        || registration.plugin_build.as_deref() != Some(PLUGIN_BUILD_HASH)
+    {
+        tracing::warn!(
+            "Studio plugin in {:?} is out of date (protocol {:?}, build {:?}; server bundles protocol {PROTOCOL_VERSION}, build {PLUGIN_BUILD_HASH})",
+            registration.place_name,
+            registration.protocol_version,
+            registration.plugin_build,
+        );
+    }
+    let link = register_studio(&state, registration).await;
+    let studio_id = link.studio_id;
+    let (heartbeat, metrics) = {
+        let s = state.lock().await;
+        (s.heartbeat, s.metrics.clone())
+    };
+    metrics.record_studio_connected(link.reconnected);
 
-    let ack = ws_encode(&serde_json::json!({
-        "type": "registered",
-        "studio_id": studio_id.to_string()
-    }));
-    match ack {
-        Ok(ack_text) => {
-            if ws_sender
-                .send(Message::Text(ack_text.into()))
-                .await
-                .is_err()
-            {
-                tracing::error!("Failed to send registration ack to studio {studio_id}");
-                return;
-            }
-        }
+    // The ack still uses the legacy framing; both sides switch to `format` after it
+    let ack = OutboundMessage::encode(
+        &serde_json::json!({
+            "type": "registered",
+            "studio_id": studio_id.to_string(),
+            "heartbeat_interval": heartbeat.interval.as_secs_f64(),
+            "heartbeat_max_missed": heartbeat.max_missed,
+            "encoding": format.encoding,
+            "max_frame_size": format.max_frame_size,
+            "protocol_version": PROTOCOL_VERSION,
+            // Plugin events this server wants
+            "events": ["console"],
+        }),
+        None,
+    );
+    let ack_sent = match ack {
+        Ok(ack) => send_message(&mut ws_sender, WireFormat::LEGACY, ack).await,
         Err(e) => {
             tracing::error!("Failed to encode registration ack: {e}");
-            return;
+            false
         }
+    };
+    if !ack_sent {
+        tracing::error!("Failed to send registration ack to studio {studio_id}");
+        metrics.record_studio_disconnected(DisconnectReason::Closed);
+        end_link(&state, &link, "Studio disconnected").await;
+        return;
     }
 
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
-
     {
-        let mut s = state.lock().await;
-        s.connections.insert(
-            studio_id,
-            StudioConnection {
-                sender: tx,
-                place_id: registration.place_id,
-                place_name: registration.place_name,
-                game_id: registration.game_id,
-                job_id: registration.job_id,
-                place_version: registration.place_version,
-                creator_id: registration.creator_id,
-                creator_type: registration.creator_type,
-                connected_at: chrono::Utc::now(),
-            },
-        );
+        // Where the plugin should reconnect may differ from where it dialed, after a reload
+        // that moves the server or an earlier push that never took effect
+        let s = state.lock().await;
+        if let Some(conn) = s.connections.get(&studio_id) {
+            s.send_config(conn);
+        }
     }
 
-    let state_for_sender = state.clone();
+    let queue = link.queue.clone();
+    let link_shutdown = link.shutdown.clone();
+    let link_id = link.link_id;
+    let sender_state = state.clone();
     let sender_task = tokio::spawn(async move {
-        while let Some(b64_text) = rx.recv().await {
-            if ws_sender
-                .send(Message::Text(b64_text.into()))
-                .await
-                .is_err()
-            {
+        // Waits here until the previous link for this Studio has let go of the queue
+        let mut queue = queue.lock().await;
+        loop {
+            let next = tokio::select! {
+                message = queue.peek() => message.map(|message| message.id),
+                _ = link_shutdown.cancelled() => None,
+            };
+            let Some(request_id) = next else {
+                break;
+            };
+            let message = {
+                let mut s = sender_state.lock().await;
+                let owns_studio = s
+                    .connections
+                    .get(&studio_id)
+                    .is_some_and(|conn| conn.link_id == link_id && conn.disconnected_at.is_none());
+                if !owns_studio {
+                    // Taken over or disconnected: leave the message for the next link
+                    break;
+                }
+                // Marked with the message taken under the same lock, so a takeover or
+                // disconnect from here on fails the request rather than losing it
+                if let Some(pending) = request_id.and_then(|id| s.output_map.get_mut(&id)) {
+                    pending.sent_on = Some(link_id);
+                }
+                queue.take()
+            };
+            let Some(message) = message else {
+                break;
+            };
+            if !send_message(&mut ws_sender, format, message).await {
                 break;
             }
         }
-        let _ = state_for_sender;
     });
 
-    while let Some(msg) = ws_receiver.next().await {
-        match msg {
-            Ok(Message::Text(text)) => match ws_decode::<RunCommandResponse>(&text) {
-                Ok(response) => {
-                    let mut s = state.lock().await;
-                    if let Some(pending) = s.output_map.remove(&response.id) {
-                        let result = if response.success {
-                            Ok(value_to_mcp_string(response.response))
-                        } else {
-                            Err(
-                                color_eyre::eyre::eyre!(value_to_mcp_string(response.response))
-                                    .into(),
-                            )
-                        };
-                        let _ = pending.sender.send(result);
-                    } else {
-                        tracing::warn!("Received response for unknown request ID: {}", response.id);
-                    }
+    let mut ticker = tokio::time::interval(heartbeat.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut last_seen = Instant::now();
+    let mut ping_seq: u32 = 0;
+    let mut ping_sent_at: Option<(u32, Instant)> = None;
+    let mut reassembler = Reassembler::default();
+
+    let (disconnect, disconnect_reason) = loop {
+        tokio::select! {
+            msg = ws_receiver.next() => match msg {
+                Some(Ok(Message::Close(_))) | None => {
+                    break (DisconnectReason::Closed, "Studio disconnected".to_string());
+                }
+                Some(Err(e)) => {
+                    tracing::warn!("WebSocket error from studio {studio_id}: {e}");
+                    break (DisconnectReason::Closed, "Studio disconnected".to_string());
                 }
-                Err(e) => {
-                    tracing::warn!("Failed to decode studio message: {e}");
+                Some(Ok(message)) => {
+                    last_seen = Instant::now();
+                    match unframe(&message) {
+                        Some(Ok(body)) => {
+                            let body = match reassemble(&state, &mut reassembler, body).await {
+                                Some(body) => body,
+                                None => continue,
+                            };
+                            match rmpv::decode::read_value(&mut body.as_slice()) {
+                                Ok(value) if message_type(&value).is_some() => {
+                                    handle_plugin_message(&state, studio_id, value, &mut ping_sent_at)
+                                        .await;
+                                }
+                                Ok(value) => handle_tool_response(&state, value).await,
+                                Err(e) => tracing::warn!("Failed to decode studio message: {e}"),
+                            }
+                        }
+                        Some(Err(e)) => tracing::warn!("Failed to decode studio frame: {e}"),
+                        None => {}
+                    }
                 }
             },
-            Ok(Message::Close(_)) => break,
-            Err(e) => {
-                tracing::warn!("WebSocket error from studio {studio_id}: {e}");
-                break;
+            _ = ticker.tick() => {
+                let silent_for = last_seen.elapsed();
+                if silent_for > heartbeat.timeout() {
+                    tracing::warn!(
+                        "Studio {studio_id} unresponsive: no heartbeat for {:.1}s, evicting",
+                        silent_for.as_secs_f64()
+                    );
+                    break (DisconnectReason::Unresponsive, format!(
+                        "Studio unresponsive: no heartbeat for {:.0}s. It may be frozen or asleep. Call `list_studios` to see available studios.",
+                        silent_for.as_secs_f64()
+                    ));
+                }
+                ping_seq = ping_seq.wrapping_add(1);
+                match OutboundMessage::encode(&serde_json::json!({ "type": "ping", "seq": ping_seq }), None) {
+                    Ok(ping) => {
+                        ping_sent_at = Some((ping_seq, Instant::now()));
+                        let _ = link.sender.send(ping);
+                    }
+                    Err(e) => tracing::error!("Failed to encode ping: {e}"),
+                }
+            }
+            _ = link.shutdown.cancelled() => {
+                tracing::info!("Studio {studio_id} replaced its connection");
+                break (DisconnectReason::Replaced, String::new());
             }
-            _ => {}
         }
-    }
+    };
 
-    {
-        let mut s = state.lock().await;
-        s.connections.remove(&studio_id);
-        let pending_ids: Vec<Uuid> = s
-            .output_map
+    sender_task.abort();
+    let _ = sender_task.await;
+    metrics.record_studio_disconnected(disconnect);
+    end_link(&state, &link, &disconnect_reason).await;
+}
+
+/// The websocket currently serving a Studio.
+struct StudioLink {
+    studio_id: Uuid,
+    link_id: Uuid,
+    sender: tokio::sync::mpsc::UnboundedSender<OutboundMessage>,
+    queue: StudioQueue,
+    shutdown: CancellationToken,
+    /// The Studio took back the `studio_id` it held before.
+    reconnected: bool,
+}
+
+/// Adds a Studio to `AppState.connections`, or hands a returning Studio (same `job_id` and
+/// instance token) its previous `studio_id`, session bindings and queued requests.
+async fn register_studio(state: &PackedState, registration: RegistrationMessage) -> StudioLink {
+    let mut s = state.lock().await;
+    let link_id = Uuid::new_v4();
+    let shutdown = CancellationToken::new();
+    let now = chrono::Utc::now();
+
+    let returning = registration.instance_token.as_ref().and_then(|token| {
+        s.connections
             .iter()
-            .filter(|(_, req)| req.connection_id == studio_id)
+            .find(|(_, conn)| {
+                conn.instance_token.as_ref() == Some(token) && conn.job_id == registration.job_id
+            })
             .map(|(id, _)| *id)
-            .collect();
-        for id in pending_ids {
-            if let Some(pending) = s.output_map.remove(&id) {
-                let _ = pending
-                    .sender
-                    .send(Err(color_eyre::eyre::eyre!("Studio disconnected").into()));
+    });
+
+    if let Some(studio_id) = returning {
+        let conn = s.connections.get_mut(&studio_id).unwrap();
+        let replaced_link = match conn.disconnected_at.take() {
+            Some(_) => None,
+            None => {
+                // The old socket has not noticed it is dead yet; stop it so it releases the queue
+                conn.link_shutdown.cancel();
+                Some(conn.link_id)
             }
+        };
+        tracing::info!(
+            "Studio reconnected: {} (place_id={}, place_name={})",
+            studio_id,
+            registration.place_id,
+            registration.place_name
+        );
+        conn.link_id = link_id;
+        conn.link_shutdown = shutdown.clone();
+        // A label set with `set_studio_label` outlives the plugin's
+        conn.label = conn.label.take().or(registration.label);
+        conn.place_id = registration.place_id;
+        conn.place_name = registration.place_name;
+        conn.game_id = registration.game_id;
+        conn.place_version = registration.place_version;
+        conn.creator_id = registration.creator_id;
+        conn.creator_type = registration.creator_type;
+        conn.last_heartbeat = now;
+        conn.latency_ms = None;
+        conn.protocol_version = registration.protocol_version;
+        conn.plugin_build = registration.plugin_build;
+        conn.tools = registration.tools;
+        conn.custom_tools = registration.custom_tools;
+        conn.streams_console = registration.events.iter().any(|event| event == "console");
+        conn.accepts_config = registration
+            .accepts
+            .iter()
+            .any(|message| message == "config");
+        let sender = conn.sender.clone();
+        let queue = conn.queue.clone();
+        if let Some(old_link_id) = replaced_link {
+            s.fail_link_requests(
+                old_link_id,
+                "Studio reconnected before answering, so the answer was lost. The request may or may not have run; check before retrying it.",
+            );
         }
+        let link = StudioLink {
+            studio_id,
+            link_id,
+            sender,
+            queue,
+            shutdown,
+            reconnected: true,
+        };
+        s.notify_tool_list_changed();
+        s.notify_resource_updated(studio_id, StudioResource::Info);
+        return link;
     }
 
-    sender_task.abort();
-    tracing::info!("Studio disconnected: {studio_id}");
+    let studio_id = Uuid::new_v4();
+    tracing::info!(
+        "Studio connected: {} (place_id={}, place_name={})",
+        studio_id,
+        registration.place_id,
+        registration.place_name
+    );
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<OutboundMessage>();
+    let queue: StudioQueue = Arc::new(Mutex::new(OutboundQueue::new(rx)));
+    s.connections.insert(
+        studio_id,
+        StudioConnection {
+            sender: tx.clone(),
+            queue: queue.clone(),
+            link_id,
+            link_shutdown: shutdown.clone(),
+            instance_token: registration.instance_token,
+            label: registration.label,
+            disconnected_at: None,
+            place_id: registration.place_id,
+            place_name: registration.place_name,
+            game_id: registration.game_id,
+            job_id: registration.job_id,
+            place_version: registration.place_version,
+            creator_id: registration.creator_id,
+            creator_type: registration.creator_type,
+            connected_at: now,
+            last_heartbeat: now,
+            latency_ms: None,
+            protocol_version: registration.protocol_version,
+            plugin_build: registration.plugin_build,
+            tools: registration.tools,
+            custom_tools: registration.custom_tools,
+            streams_console: registration.events.iter().any(|event| event == "console"),
+            accepts_config: registration
+                .accepts
+                .iter()
+                .any(|message| message == "config"),
+            console: ConsoleBuffer::default(),
+            leases: StudioLeases::default(),
+            requests: RequestQueue::default(),
+        },
+    );
+    s.notify_tool_list_changed();
+    s.notify_resource_list_changed();
+    StudioLink {
+        studio_id,
+        link_id,
+        sender: tx,
+        queue,
+        shutdown,
+        reconnected: false,
+    }
+}
+
+/// Tears down a websocket link. Requests already sent over it fail with `reason`. Studios that
+/// can be recognised on reconnect stay listed for the grace window, queueing new requests.
+async fn end_link(state: &PackedState, link: &StudioLink, reason: &str) {
+    let studio_id = link.studio_id;
+    let mut s = state.lock().await;
+    let grace = s.reconnect_grace;
+    let Some(conn) = s.connections.get_mut(&studio_id) else {
+        return;
+    };
+    if conn.link_id != link.link_id {
+        // A newer link owns this Studio now
+        return;
+    }
+
+    if conn.instance_token.is_none() || grace.is_zero() {
+        s.connections.remove(&studio_id);
+        s.notify_studio_removed(studio_id);
+        s.fail_pending_requests(studio_id, reason);
+        tracing::info!("Studio disconnected: {studio_id}");
+        return;
+    }
+
+    conn.disconnected_at = Some(chrono::Utc::now());
+    // Requests still queued wait for the Studio to come back; the expiry below fails them if
+    // it does not
+    s.fail_link_requests(link.link_id, reason);
+    s.notify_resource_updated(studio_id, StudioResource::Info);
+    tracing::info!(
+        "Studio disconnected: {studio_id}, holding its studio_id for {}s",
+        grace.as_secs()
+    );
+
+    let state = state.clone();
+    let link_id = link.link_id;
+    tokio::spawn(async move {
+        tokio::time::sleep(grace).await;
+        let mut s = state.lock().await;
+        let expired = s
+            .connections
+            .get(&studio_id)
+            .is_some_and(|conn| conn.link_id == link_id && conn.disconnected_at.is_some());
+        if expired {
+            s.connections.remove(&studio_id);
+            s.notify_studio_removed(studio_id);
+            s.fail_pending_requests(
+                studio_id,
+                &format!(
+                    "Studio disconnected and did not reconnect within {}s. Call `list_studios` to see available studios.",
+                    grace.as_secs()
+                ),
+            );
+            tracing::info!("Studio {studio_id} did not reconnect, removed");
+        }
+    });
+}
+
+fn decode_registration(message: &Message) -> Result<RegistrationMessage> {
+    let body = unframe(message)
+        .ok_or_else(|| color_eyre::eyre::eyre!("expected a data frame, got {message:?}"))??;
+    Ok(rmp_serde::from_slice(&body)?)
+}
+
+/// Frames `message` for `format` and writes it out. Returns false once the socket is unusable.
+async fn send_message(
+    ws_sender: &mut SplitSink<WebSocket, Message>,
+    format: WireFormat,
+    message: OutboundMessage,
+) -> bool {
+    let frames = match format.frames(message) {
+        Ok(frames) => frames,
+        Err(e) => {
+            tracing::error!("Failed to frame message for studio: {e}");
+            return true;
+        }
+    };
+    for frame in frames {
+        if ws_sender.send(frame).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Passes ordinary messages through. Chunks are collected until their message is complete,
+/// recording progress on the pending request they answer.
+async fn reassemble(
+    state: &PackedState,
+    reassembler: &mut Reassembler,
+    body: Vec<u8>,
+) -> Option<Vec<u8>> {
+    let is_chunk = rmpv::decode::read_value(&mut body.as_slice())
+        .is_ok_and(|value| message_type(&value) == Some("chunk"));
+    if !is_chunk {
+        return Some(body);
+    }
+    let chunk = match rmp_serde::from_slice::<ChunkMessage>(&body) {
+        Ok(chunk) => chunk,
+        Err(e) => {
+            tracing::warn!("Invalid chunk from studio: {e}");
+            return None;
+        }
+    };
+    let id = chunk.id;
+    match reassembler.push(chunk) {
+        Ok(ChunkOutcome::Partial { received, count }) => {
+            tracing::debug!("Received chunk {received}/{count} of {id}");
+            if let Some(pending) = state.lock().await.output_map.get_mut(&id) {
+                pending.chunks = Some((received, count));
+            }
+            None
+        }
+        Ok(ChunkOutcome::Complete(body)) => Some(body),
+        Err(e) => {
+            tracing::warn!("Dropping chunked message from studio: {e}");
+            None
+        }
+    }
+}
+
+fn message_type(value: &MsgpackValue) -> Option<&str> {
+    value
+        .as_map()?
+        .iter()
+        .find(|(key, _)| key.as_str() == Some("type"))?
+        .1
+        .as_str()
+}
+
+async fn handle_plugin_message(
+    state: &PackedState,
+    studio_id: Uuid,
+    value: MsgpackValue,
+    ping_sent_at: &mut Option<(u32, Instant)>,
+) {
+    let message = match rmpv::ext::from_value::<PluginMessage>(value) {
+        Ok(message) => message,
+        Err(e) => {
+            tracing::warn!("Unknown message from studio {studio_id}: {e}");
+            return;
+        }
+    };
+
+    let mut s = state.lock().await;
+    let capacity = s.console_buffer;
+    let Some(conn) = s.connections.get_mut(&studio_id) else {
+        return;
+    };
+    conn.last_heartbeat = chrono::Utc::now();
+    match message {
+        PluginMessage::Heartbeat => {}
+        PluginMessage::Pong { seq } => match ping_sent_at.take() {
+            Some((sent_seq, sent_at)) if sent_seq == seq => {
+                conn.latency_ms = Some(sent_at.elapsed().as_secs_f64() * 1000.0);
+            }
+            other => *ping_sent_at = other,
+        },
+        PluginMessage::Console { entries, dropped } => {
+            conn.console.push(entries, dropped, capacity);
+            s.notify_resource_updated(studio_id, StudioResource::Console);
+        }
+        PluginMessage::Progress { id, message } => {
+            if let Some(progress) = s.output_map.get(&id).and_then(|p| p.progress.as_ref()) {
+                let _ = progress.send(message);
+            }
+        }
+    }
+}
+
+async fn handle_tool_response(state: &PackedState, value: MsgpackValue) {
+    let response = match rmpv::ext::from_value::<RunCommandResponse>(value) {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("Failed to decode studio message: {e}");
+            return;
+        }
+    };
+
+    let mut s = state.lock().await;
+    if let Some(pending) = s.output_map.remove(&response.id) {
+        let result = if response.success {
+            Ok(msgpack_to_json(response.response))
+        } else {
+            Err(color_eyre::eyre::eyre!(msgpack_to_error_text(response.response)).into())
+        };
+        let _ = pending.sender.send(result);
+    } else {
+        tracing::warn!("Received response for unknown request ID: {}", response.id);
+    }
 }
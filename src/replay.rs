@@ -0,0 +1,387 @@
+//! Re-runs tool calls recorded in the audit log against a Studio, through `dispatch` like any
+//! other call. Agents reach it as the `replay` tool, which applies the tool policy and approval
+//! to every call as if the agent had made it; operators use `POST /replay` and the `replay`
+//! subcommand, which act on the token holder's authority. Replays only read the server's own
+//! audit log and its rotated copies; the subcommand's dry run reads any file locally.
+
+use crate::audit::{truncate, AuditRecord, Audited};
+use crate::rbx_studio_server::RBXStudioServer;
+use crate::server_state::{dispatch, DispatchOptions, PackedState, SessionState};
+use crate::tools::{
+    GetStudioModeResult, InsertModelResult, RunCodeResult, RunScriptInPlayModeResult,
+    StartStopPlayResult,
+};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use color_eyre::eyre::{eyre, Result};
+use rmcp::model::{CallToolResult, Tool};
+use rmcp::service::RequestContext;
+use rmcp::{schemars, ErrorData, RoleServer};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+#[derive(Debug, Default, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ReplayArgs {
+    #[schemars(
+        description = "Rotated copy of the server's audit log to replay, such as audit.1.jsonl. Defaults to the current audit log."
+    )]
+    pub log: Option<String>,
+    #[schemars(description = "Only replay calls made by this MCP session id")]
+    pub session_id: Option<String>,
+    #[schemars(description = "Stop at the first call that fails")]
+    #[serde(default)]
+    pub stop_on_error: bool,
+    #[schemars(description = "List the calls that would run without running them")]
+    #[serde(default)]
+    pub dry_run: bool,
+    #[schemars(description = "Compare each new response with the recorded one")]
+    #[serde(default)]
+    pub diff: bool,
+}
+
+/// Body of `POST /replay`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ReplayRequest {
+    #[serde(flatten)]
+    pub args: ReplayArgs,
+    /// Studio to run the calls in; may be left out when only one is connected.
+    pub studio_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ReplayReport {
+    #[schemars(description = "The replayed calls in log order")]
+    pub calls: Vec<ReplayedCall>,
+    #[schemars(
+        description = "Records left out: other sessions, refused calls and tools the server answers itself"
+    )]
+    pub skipped: usize,
+    #[schemars(description = "True if stop_on_error ended the replay early")]
+    pub stopped: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ReplayedCall {
+    #[schemars(description = "Line of the record in the log, from 1")]
+    pub line: usize,
+    pub tool: String,
+    pub arguments: serde_json::Value,
+    #[schemars(description = "Whether the recorded call succeeded")]
+    pub recorded_success: bool,
+    #[schemars(description = "Whether the call succeeded this time; absent in a dry run")]
+    pub success: Option<bool>,
+    #[schemars(description = "The new response as JSON text, cut to the audit response limit")]
+    pub response: Option<String>,
+    #[schemars(description = "With diff: whether the new response matches the recorded one")]
+    pub matches: Option<bool>,
+    #[schemars(description = "With diff: unified diff from the recorded to the new response")]
+    pub diff: Option<String>,
+}
+
+/// Who asked for the replay, which decides whether each call is checked before it runs.
+pub enum Caller<'a> {
+    Operator,
+    Agent(&'a RBXStudioServer, &'a RequestContext<RoleServer>),
+}
+
+/// Reads the records of `log` that a replay would run, with their line numbers, and how many
+/// were left out.
+pub fn load(
+    log: &Path,
+    session_id: Option<&str>,
+) -> std::result::Result<(Vec<(usize, AuditRecord)>, usize), String> {
+    let text = std::fs::read_to_string(log)
+        .map_err(|e| format!("Could not read {}: {e}", log.display()))?;
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: AuditRecord = serde_json::from_str(line).map_err(|e| {
+            format!(
+                "Line {} of {} is not an audit record: {e}",
+                index + 1,
+                log.display()
+            )
+        })?;
+        // Calls the server answered itself describe session routing at the time, not anything
+        // that happened in a place
+        if record.refused || record.local || session_id.is_some_and(|id| id != record.session_id) {
+            skipped += 1;
+            continue;
+        }
+        records.push((index + 1, record));
+    }
+    Ok((records, skipped))
+}
+
+/// Replays the calls in the log `args` names against the Studio `session` resolves to.
+pub async fn run(
+    state: &PackedState,
+    caller: Caller<'_>,
+    session_id: &str,
+    session: &SessionState,
+    args: &ReplayArgs,
+) -> std::result::Result<ReplayReport, String> {
+    let log = audit_log(state, args.log.as_deref()).await?;
+    let (records, skipped) = load(&log, args.session_id.as_deref())?;
+    let response_limit = state.lock().await.audit.response_limit();
+    let ct = match &caller {
+        Caller::Operator => CancellationToken::new(),
+        Caller::Agent(_, context) => context.ct.clone(),
+    };
+
+    let mut report = ReplayReport {
+        calls: Vec::new(),
+        skipped,
+        stopped: false,
+    };
+    for (line, record) in records {
+        let mut call = ReplayedCall {
+            line,
+            tool: record.tool.clone(),
+            arguments: record.arguments.clone(),
+            recorded_success: record.success,
+            success: None,
+            response: None,
+            matches: None,
+            diff: None,
+        };
+        if args.dry_run {
+            report.calls.push(call);
+            continue;
+        }
+        if ct.is_cancelled() {
+            report.stopped = true;
+            break;
+        }
+
+        let refusal = match &caller {
+            Caller::Operator => None,
+            Caller::Agent(server, context) => {
+                let tool = server.find_tool(&record.tool).await.unwrap_or_else(|| {
+                    // No longer advertised: judge it like an unannotated plugin tool
+                    Tool::new(record.tool.clone(), "", Arc::new(Default::default()))
+                });
+                let arguments = record.arguments.as_object().cloned().unwrap_or_default();
+                server.authorize(context, None, &tool, &arguments).await
+            }
+        };
+        let result = match refusal {
+            Some(refusal) => Ok(refusal),
+            None => {
+                let options = DispatchOptions {
+                    // Give slow calls at least twice the time they took when recorded
+                    min_timeout: Some(Duration::from_millis(record.duration_ms.saturating_mul(2))),
+                    ..DispatchOptions::new(ct.clone())
+                };
+                redispatch(state, session_id, session, &record, options).await
+            }
+        };
+
+        let (success, response) = result.outcome();
+        let response = response.to_string();
+        if args.diff {
+            let (matches, diff) = compare(&record, &response);
+            call.matches = Some(matches);
+            call.diff = diff;
+        }
+        call.success = Some(success);
+        call.response = Some(truncate(response, response_limit).0);
+        report.calls.push(call);
+        if !success && args.stop_on_error {
+            report.stopped = true;
+            break;
+        }
+    }
+    Ok(report)
+}
+
+/// Sends a recorded call again. Each built-in tool's answer is read as that tool's result type,
+/// so a call that failed inside Studio, such as code that threw, replays as a failure; plugin
+/// tools have no declared shape and pass through as they are.
+async fn redispatch(
+    state: &PackedState,
+    session_id: &str,
+    session: &SessionState,
+    record: &AuditRecord,
+    options: DispatchOptions,
+) -> std::result::Result<CallToolResult, ErrorData> {
+    let (tool, args) = (record.tool.as_str(), &record.arguments);
+    match tool {
+        "run_code" => {
+            dispatch::<RunCodeResult>(state, session_id, session, tool, args, options).await
+        }
+        "run_script_in_play_mode" => {
+            dispatch::<RunScriptInPlayModeResult>(state, session_id, session, tool, args, options)
+                .await
+        }
+        "insert_model" => {
+            dispatch::<InsertModelResult>(state, session_id, session, tool, args, options).await
+        }
+        "start_stop_play" => {
+            dispatch::<StartStopPlayResult>(state, session_id, session, tool, args, options).await
+        }
+        "get_studio_mode" => {
+            dispatch::<GetStudioModeResult>(state, session_id, session, tool, args, options).await
+        }
+        _ => dispatch::<serde_json::Value>(state, session_id, session, tool, args, options).await,
+    }
+}
+
+/// The log to replay: the server's audit log, or one of its rotated copies named by path or
+/// file name. Nothing else is read, so callers cannot use a replay to read other files.
+async fn audit_log(state: &PackedState, log: Option<&str>) -> std::result::Result<PathBuf, String> {
+    let logs = state.lock().await.audit.logs();
+    let Some(log) = log else {
+        return logs
+            .into_iter()
+            .next()
+            .ok_or_else(|| "There is no audit log to replay.".to_string());
+    };
+    let named = |path: &PathBuf| {
+        path.as_path() == Path::new(log) || path.file_name() == Some(OsStr::new(log))
+    };
+    match logs.iter().find(|path| named(path)) {
+        Some(path) => Ok(path.clone()),
+        None => Err(format!(
+            "`log` must be the audit log or one of its rotated copies: {}",
+            logs.iter()
+                .filter_map(|path| path.file_name())
+                .map(|name| name.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Whether `response` matches what `record` got, and a unified diff when it does not. A
+/// truncated recording is compared with the same length of the new response.
+fn compare(record: &AuditRecord, response: &str) -> (bool, Option<String>) {
+    let (recorded, response) = if record.response_truncated {
+        let length = record.response.chars().count();
+        (
+            record.response.clone(),
+            truncate(response.to_string(), length).0,
+        )
+    } else {
+        (pretty(&record.response), pretty(response))
+    };
+    if recorded == response {
+        return (true, None);
+    }
+    let diff = TextDiff::from_lines(&recorded, &response)
+        .unified_diff()
+        .context_radius(2)
+        .header("recorded", "replayed")
+        .to_string();
+    (false, Some(diff))
+}
+
+fn pretty(json: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(json) {
+        Ok(value) => serde_json::to_string_pretty(&value).unwrap_or_default() + "\n",
+        Err(_) => format!("{json}\n"),
+    }
+}
+
+pub async fn replay_handler(
+    State(state): State<PackedState>,
+    Json(request): Json<ReplayRequest>,
+) -> Response {
+    let session_id = format!("replay-{}", Uuid::new_v4());
+    let session = SessionState {
+        selected_studio_id: request.studio_id,
+        ..SessionState::new()
+    };
+    match run(
+        &state,
+        Caller::Operator,
+        &session_id,
+        &session,
+        &request.args,
+    )
+    .await
+    {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+/// `rbx-studio-mcp replay`. A dry run reads the log here; anything else asks the running server.
+pub async fn replay_cli(
+    base_url: &str,
+    token: &str,
+    args: ReplayArgs,
+    studio_id: Option<Uuid>,
+) -> Result<()> {
+    if args.dry_run {
+        let log = args
+            .log
+            .as_ref()
+            .ok_or_else(|| eyre!("no audit log to replay"))?;
+        let (records, skipped) =
+            load(Path::new(log), args.session_id.as_deref()).map_err(|e| eyre!(e))?;
+        for (line, record) in &records {
+            println!("{line:>6}  {}  {}", record.tool, record.arguments);
+        }
+        println!("{} calls, {skipped} records skipped", records.len());
+        return Ok(());
+    }
+
+    let response = reqwest::Client::new()
+        .post(format!("{base_url}/replay"))
+        .bearer_auth(token)
+        .json(&ReplayRequest { args, studio_id })
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(eyre!("server answered {status}: {body}"));
+    }
+    let report: ReplayReport = response.json().await?;
+    let mut failed = 0;
+    for call in &report.calls {
+        let outcome = |success: bool| if success { "ok" } else { "failed" };
+        let success = call.success.unwrap_or(false);
+        failed += usize::from(!success);
+        let matches = match call.matches {
+            Some(true) => "  same response",
+            Some(false) => "  different response",
+            None => "",
+        };
+        println!(
+            "{:>6}  {}  {} (recorded {}){matches}",
+            call.line,
+            call.tool,
+            outcome(success),
+            outcome(call.recorded_success)
+        );
+        if let Some(diff) = &call.diff {
+            print!("{diff}");
+        } else if !success {
+            println!("        {}", call.response.as_deref().unwrap_or_default());
+        }
+    }
+    println!(
+        "{} calls, {failed} failed, {} records skipped{}",
+        report.calls.len(),
+        report.skipped,
+        if report.stopped {
+            ", stopped early"
+        } else {
+            ""
+        }
+    );
+    Ok(())
+}
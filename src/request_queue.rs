@@ -0,0 +1,125 @@
+//! Per-Studio ordering of tool calls. Calls to tools that are not annotated read-only wait in
+//! FIFO order until fewer than `studio_concurrency` of them are running in the Studio, so
+//! concurrent edits do not interleave in the plugin. Read-only calls start straight away. Both
+//! are listed by `list_pending_requests` until they finish.
+
+use crate::ticket::{Ticket, TicketEntry};
+use rmcp::model::Tool;
+use rmcp::schemars;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Whether calls to `tool` skip the queue. Tools without the hint count as mutating, as in the
+/// MCP defaults.
+pub fn is_read_only(tool: &Tool) -> bool {
+    tool.annotations
+        .as_ref()
+        .and_then(|a| a.read_only_hint)
+        .unwrap_or(false)
+}
+
+#[derive(Clone, Debug)]
+struct QueuedCall {
+    request_id: Uuid,
+    tool: String,
+    session_id: String,
+    read_only: bool,
+    queued_at: chrono::DateTime<chrono::Utc>,
+    started_at: Option<chrono::DateTime<chrono::Utc>>,
+    ticket: TicketEntry,
+}
+
+#[derive(Serialize, schemars::JsonSchema)]
+pub struct QueuedCallInfo {
+    #[schemars(description = "Id of the request, as sent to the plugin")]
+    pub request_id: String,
+    pub tool: String,
+    #[schemars(description = "Session that made the call")]
+    pub session_id: String,
+    #[schemars(description = "Whether the tool is read-only and so skips the queue")]
+    pub read_only: bool,
+    #[schemars(description = "ISO 8601 timestamp of when the call reached the server")]
+    pub queued_at: String,
+    #[schemars(description = "ISO 8601 timestamp of when the call was sent to Studio")]
+    pub started_at: Option<String>,
+}
+
+/// Calls to one Studio that are waiting or running, in arrival order.
+#[derive(Clone, Debug, Default)]
+pub struct RequestQueue {
+    calls: Vec<QueuedCall>,
+}
+
+impl RequestQueue {
+    /// Adds a call that has not started yet.
+    pub fn push(
+        &mut self,
+        request_id: Uuid,
+        tool: &str,
+        session_id: &str,
+        read_only: bool,
+        ticket: &Ticket,
+    ) {
+        self.prune();
+        self.calls.push(QueuedCall {
+            request_id,
+            tool: tool.to_string(),
+            session_id: session_id.to_string(),
+            read_only,
+            queued_at: chrono::Utc::now(),
+            started_at: None,
+            ticket: ticket.entry(),
+        });
+    }
+
+    /// Marks `request_id` as running if its turn has come: it is read-only, or it is the first
+    /// mutating call still waiting and fewer than `concurrency` mutating calls are running.
+    pub fn try_start(&mut self, request_id: Uuid, concurrency: usize) -> bool {
+        self.prune();
+        let running = self.mutating().filter(|c| c.started_at.is_some()).count();
+        let next = self.mutating().find(|c| c.started_at.is_none());
+        let Some(call) = self.calls.iter().find(|c| c.request_id == request_id) else {
+            return false;
+        };
+        let may_start = call.started_at.is_some()
+            || call.read_only
+            || (running < concurrency && next.is_some_and(|next| next.request_id == request_id));
+        if may_start {
+            let call = self
+                .calls
+                .iter_mut()
+                .find(|c| c.request_id == request_id)
+                .unwrap();
+            call.started_at.get_or_insert_with(chrono::Utc::now);
+        }
+        may_start
+    }
+
+    /// Calls that have been sent to Studio and calls still waiting, each in arrival order.
+    pub fn list(&self) -> (Vec<QueuedCallInfo>, Vec<QueuedCallInfo>) {
+        let (running, waiting): (Vec<_>, Vec<_>) = self
+            .live()
+            .map(|call| QueuedCallInfo {
+                request_id: call.request_id.to_string(),
+                tool: call.tool.clone(),
+                session_id: call.session_id.clone(),
+                read_only: call.read_only,
+                queued_at: call.queued_at.to_rfc3339(),
+                started_at: call.started_at.map(|at| at.to_rfc3339()),
+            })
+            .partition(|call| call.started_at.is_some());
+        (running, waiting)
+    }
+
+    fn live(&self) -> impl Iterator<Item = &QueuedCall> {
+        self.calls.iter().filter(|c| c.ticket.is_live())
+    }
+
+    fn mutating(&self) -> impl Iterator<Item = &QueuedCall> {
+        self.calls.iter().filter(|c| !c.read_only)
+    }
+
+    fn prune(&mut self) {
+        self.calls.retain(|c| c.ticket.is_live());
+    }
+}
@@ -0,0 +1,242 @@
+//! Connected Studios as MCP resources: `studio://{studio_id}/info`, `/console` and `/mode`.
+//! `info` is answered from `AppState.connections`. `console` and `mode` run the matching tool
+//! for that Studio, so they are subject to the same policy, approval and audit as the tool itself;
+//! `console` shows the Studio's whole console buffer.
+
+use crate::rbx_studio_server::RBXStudioServer;
+use crate::request_queue::is_read_only;
+use crate::server_state::{dispatch, AppState, DispatchOptions, SessionState};
+use crate::tools::{GetConsoleOutputArgs, GetConsoleOutputResult, GetStudioModeResult};
+use rmcp::model::{
+    AnnotateAble, CallToolResult, RawResource, RawResourceTemplate, ReadResourceResult, Resource,
+    ResourceContents, ResourceTemplate,
+};
+use rmcp::service::RequestContext;
+use rmcp::{ErrorData, RoleServer};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+const SCHEME: &str = "studio://";
+
+/// Tools after which a Studio's `mode` resource has changed.
+const MODE_CHANGING_TOOLS: [&str; 2] = ["start_stop_play", "run_script_in_play_mode"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StudioResource {
+    Info,
+    Console,
+    Mode,
+}
+
+impl StudioResource {
+    pub const ALL: [StudioResource; 3] = [Self::Info, Self::Console, Self::Mode];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Console => "console",
+            Self::Mode => "mode",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::Info => "Place, plugin and connection details of a connected Studio",
+            Self::Console => "Buffered Output window messages of a Studio, one per line",
+            Self::Mode => "Whether a Studio is stopped or playtesting",
+        }
+    }
+
+    /// The tool whose result the resource shows, if it is not answered from server state.
+    fn tool(self) -> Option<&'static str> {
+        match self {
+            Self::Info => None,
+            Self::Console => Some("get_console_output"),
+            Self::Mode => Some("get_studio_mode"),
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            Self::Console => "text/plain",
+            Self::Info | Self::Mode => "application/json",
+        }
+    }
+
+    pub fn uri(self, studio_id: Uuid) -> String {
+        format!("{SCHEME}{studio_id}/{}", self.name())
+    }
+
+    /// Splits a `studio://{studio_id}/{name}` URI.
+    pub fn parse(uri: &str) -> Option<(Uuid, Self)> {
+        let (studio_id, name) = uri.strip_prefix(SCHEME)?.split_once('/')?;
+        let resource = Self::ALL.into_iter().find(|r| r.name() == name)?;
+        Some((studio_id.parse().ok()?, resource))
+    }
+}
+
+/// Something MCP sessions subscribed to resources should hear about.
+#[derive(Clone, Debug)]
+pub enum ResourceEvent {
+    /// A Studio connected or went away for good.
+    ListChanged,
+    Updated(String),
+}
+
+pub fn changes_mode(tool: &str) -> bool {
+    MODE_CHANGING_TOOLS.contains(&tool)
+}
+
+/// One entry per resource of every connected Studio, ordered by connection time.
+pub fn list(state: &AppState) -> Vec<Resource> {
+    let mut connections: Vec<_> = state.connections.iter().collect();
+    connections.sort_by_key(|(_, conn)| conn.connected_at);
+    connections
+        .into_iter()
+        .flat_map(|(studio_id, conn)| {
+            StudioResource::ALL.map(|resource| {
+                RawResource {
+                    title: Some(format!("{} {}", conn.place_name, resource.name())),
+                    description: Some(resource.description().to_string()),
+                    mime_type: Some(resource.mime_type().to_string()),
+                    ..RawResource::new(resource.uri(*studio_id), resource.name())
+                }
+                .no_annotation()
+            })
+        })
+        .collect()
+}
+
+pub fn templates() -> Vec<ResourceTemplate> {
+    StudioResource::ALL
+        .map(|resource| {
+            RawResourceTemplate {
+                uri_template: format!("{SCHEME}{{studio_id}}/{}", resource.name()),
+                name: resource.name().to_string(),
+                title: None,
+                description: Some(resource.description().to_string()),
+                mime_type: Some(resource.mime_type().to_string()),
+                icons: None,
+            }
+            .no_annotation()
+        })
+        .to_vec()
+}
+
+fn not_found(uri: &str) -> ErrorData {
+    ErrorData::resource_not_found(
+        format!("No resource {uri}. Call `list_studios` to see connected studios."),
+        None,
+    )
+}
+
+impl RBXStudioServer {
+    /// Checks that `uri` names a resource of a connected Studio.
+    pub(crate) async fn find_resource(
+        &self,
+        uri: &str,
+    ) -> Result<(Uuid, StudioResource), ErrorData> {
+        let (studio_id, resource) = StudioResource::parse(uri).ok_or_else(|| not_found(uri))?;
+        if !self.state.lock().await.connections.contains_key(&studio_id) {
+            return Err(not_found(uri));
+        }
+        Ok((studio_id, resource))
+    }
+
+    pub(crate) async fn read_studio_resource(
+        &self,
+        context: &RequestContext<RoleServer>,
+        uri: &str,
+    ) -> Result<ReadResourceResult, ErrorData> {
+        let (studio_id, resource) = self.find_resource(uri).await?;
+        let text = match resource {
+            StudioResource::Info => {
+                let s = self.state.lock().await;
+                let conn = s
+                    .connections
+                    .get(&studio_id)
+                    .ok_or_else(|| not_found(uri))?;
+                serde_json::to_string_pretty(&conn.to_info(studio_id))
+                    .map_err(|e| ErrorData::internal_error(e.to_string(), None))?
+            }
+            StudioResource::Console => {
+                let result = self.run_for_resource(context, studio_id, resource).await?;
+                let output: GetConsoleOutputResult = parse_result(result)?;
+                let messages: Vec<String> = output.entries.into_iter().map(|e| e.message).collect();
+                messages.join("\n")
+            }
+            StudioResource::Mode => {
+                let result = self.run_for_resource(context, studio_id, resource).await?;
+                let mode: GetStudioModeResult = parse_result(result)?;
+                serde_json::to_string_pretty(&mode)
+                    .map_err(|e| ErrorData::internal_error(e.to_string(), None))?
+            }
+        };
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: uri.to_string(),
+                mime_type: Some(resource.mime_type().to_string()),
+                text,
+                meta: None,
+            }],
+        })
+    }
+
+    /// Runs the tool behind `resource` for `studio_id` on behalf of this session, as if it had
+    /// called the tool there.
+    async fn run_for_resource(
+        &self,
+        context: &RequestContext<RoleServer>,
+        studio_id: Uuid,
+        resource: StudioResource,
+    ) -> Result<CallToolResult, ErrorData> {
+        let tool = resource
+            .tool()
+            .ok_or_else(|| ErrorData::internal_error("Not a tool resource", None))?;
+        let definition = self
+            .find_tool(tool)
+            .await
+            .ok_or_else(|| ErrorData::internal_error(format!("No tool `{tool}`"), None))?;
+        let arguments = Default::default();
+        if let Some(refusal) = self
+            .authorize(context, Some(studio_id), &definition, &arguments)
+            .await
+        {
+            return Ok(refusal);
+        }
+        if resource == StudioResource::Console {
+            // The whole buffer, which the server already holds
+            let args = GetConsoleOutputArgs {
+                limit: Some(usize::MAX),
+                ..Default::default()
+            };
+            return self.read_console(context, Some(studio_id), &args).await;
+        }
+        let session_id = self.extract_mcp_session_id(context);
+        let session = SessionState {
+            selected_studio_id: Some(studio_id),
+            ..SessionState::new()
+        };
+        let options = DispatchOptions {
+            read_only: is_read_only(&definition),
+            ..DispatchOptions::new(context.ct.clone())
+        };
+        dispatch::<serde_json::Value>(&self.state, &session_id, &session, tool, &(), options).await
+    }
+}
+
+/// The structured content of a successful tool result, or its text as an error.
+fn parse_result<R: DeserializeOwned>(result: CallToolResult) -> Result<R, ErrorData> {
+    if result.is_error == Some(true) {
+        let text = result
+            .content
+            .iter()
+            .filter_map(|content| content.as_text())
+            .map(|content| content.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        return Err(ErrorData::internal_error(text, None));
+    }
+    let value = result.structured_content.unwrap_or_default();
+    serde_json::from_value(value).map_err(|e| ErrorData::internal_error(e.to_string(), None))
+}
@@ -0,0 +1,115 @@
+//! Finding Studios by something friendlier than their `studio_id`: a label, the place name or
+//! the place id. `set_studio` uses it, and `[[auto_select]]` rules use it to pick a Studio for
+//! sessions that have not selected one.
+
+use crate::server_state::{AppState, SessionState, StudioConnection};
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// An `[[auto_select]]` entry: sessions that have not called `set_studio` use the Studio it
+/// singles out. Every field it sets must match. Rules are tried in order.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoSelectRule {
+    pub label: Option<String>,
+    pub place_id: Option<u64>,
+    pub game_id: Option<u64>,
+    pub place_name: Option<String>,
+    /// Only for sessions of this client, by the name from the MCP `initialize` request.
+    pub client: Option<String>,
+}
+
+impl AutoSelectRule {
+    fn matches(&self, conn: &StudioConnection) -> bool {
+        (self.label.is_none() || self.label == conn.label)
+            && self.place_id.is_none_or(|id| id == conn.place_id)
+            && self.game_id.is_none_or(|id| id == conn.game_id)
+            && self
+                .place_name
+                .as_ref()
+                .is_none_or(|name| name.eq_ignore_ascii_case(&conn.place_name))
+    }
+
+    fn applies_to(&self, session: &SessionState) -> bool {
+        self.client.is_none() || self.client == session.client
+    }
+}
+
+/// The Studio the first applicable rule singles out. A rule that matches no Studio, or
+/// several, is skipped.
+pub fn auto_select(state: &AppState, session: &SessionState) -> Option<Uuid> {
+    state
+        .auto_select
+        .iter()
+        .filter(|rule| rule.applies_to(session))
+        .find_map(|rule| {
+            let mut matches = state
+                .connections
+                .iter()
+                .filter(|(_, conn)| rule.matches(conn));
+            match (matches.next(), matches.next()) {
+                (Some((studio_id, _)), None) => Some(*studio_id),
+                _ => None,
+            }
+        })
+}
+
+/// Studios that `query` names: a `studio_id`, or else a label, or else a place name (ignoring
+/// case), or else a place id. The first kind that matches anything wins.
+pub fn find_studios(state: &AppState, query: &str) -> Vec<Uuid> {
+    if let Ok(studio_id) = query.parse::<Uuid>() {
+        return if state.connections.contains_key(&studio_id) {
+            vec![studio_id]
+        } else {
+            Vec::new()
+        };
+    }
+    let place_id = query.parse::<u64>().ok();
+    let by: [&dyn Fn(&StudioConnection) -> bool; 3] = [
+        &|conn| conn.label.as_deref() == Some(query),
+        &|conn| conn.place_name.eq_ignore_ascii_case(query),
+        &|conn| Some(conn.place_id) == place_id,
+    ];
+    for matches in by {
+        let mut found: Vec<_> = state
+            .connections
+            .iter()
+            .filter(|(_, conn)| matches(conn))
+            .collect();
+        if !found.is_empty() {
+            found.sort_by_key(|(_, conn)| conn.connected_at);
+            return found.into_iter().map(|(studio_id, _)| *studio_id).collect();
+        }
+    }
+    Vec::new()
+}
+
+/// The one Studio `query` names, as for `find_studios`. `Err` explains to the agent why there
+/// is none.
+pub fn find_studio(state: &AppState, query: &str) -> Result<Uuid, String> {
+    match find_studios(state, query)[..] {
+        [studio_id] => Ok(studio_id),
+        [] => Err(format!(
+            "No studio matches \"{query}\". Call `list_studios` to see available studios.\nConnected:\n{}",
+            describe_studios(state)
+        )),
+        _ => Err(format!(
+            "Several studios match \"{query}\". Pass a studio_id or label instead.\nConnected:\n{}",
+            describe_studios(state)
+        )),
+    }
+}
+
+/// One line per connected Studio for error messages, naming its label when it has one.
+pub fn describe_studios(state: &AppState) -> String {
+    let mut connections: Vec<_> = state.connections.iter().collect();
+    connections.sort_by_key(|(_, conn)| conn.connected_at);
+    connections
+        .into_iter()
+        .map(|(studio_id, conn)| match &conn.label {
+            Some(label) => format!("  {studio_id} - {} [{label}]", conn.place_name),
+            None => format!("  {studio_id} - {}", conn.place_name),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
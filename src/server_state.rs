@@ -1,19 +1,82 @@
+use crate::approval::{ApprovalSettings, PendingApproval};
+use crate::audit::{AuditLog, AuditTarget};
+use crate::codec::{RobloxValue, ROBLOX_VALUE_EXT};
+use crate::config::Settings;
+use crate::console::{ConsoleBuffer, PluginConsoleEntry};
 use crate::error::Result;
+use crate::lease::{Lease, LeaseInfo, StudioLeases};
+use crate::metrics::Metrics;
+use crate::policy::PolicySettings;
+use crate::request_queue::RequestQueue;
+use crate::resources::{changes_mode, ResourceEvent, StudioResource};
+use crate::selection::{auto_select, describe_studios, AutoSelectRule};
+use crate::ticket::Ticket;
+use crate::wire::{FrameEncoding, OutboundMessage, PLUGIN_BUILD_HASH, PROTOCOL_VERSION};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use rmcp::transport::streamable_http_server::session::local::LocalSessionManager;
 use rmcp::{
     model::{CallToolResult, Content},
     schemars, ErrorData,
 };
 use rmpv::Value as MsgpackValue;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, Mutex, Notify};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+/// Outbound message queue for one Studio. It outlives individual websocket links, so requests
+/// dispatched while the plugin is reconnecting are delivered once it is back.
+pub type StudioQueue = Arc<Mutex<OutboundQueue>>;
+
+/// Receiving end of a Studio's outbound queue. A link looks at the next message first and only
+/// takes it once it is sure to write it, so a message the link gives up on stays for the next.
+#[derive(Debug)]
+pub struct OutboundQueue {
+    rx: mpsc::UnboundedReceiver<OutboundMessage>,
+    next: Option<OutboundMessage>,
+}
+
+impl OutboundQueue {
+    pub fn new(rx: mpsc::UnboundedReceiver<OutboundMessage>) -> Self {
+        Self { rx, next: None }
+    }
+
+    /// Waits for the next message without taking it. Cancelling the wait loses nothing.
+    pub async fn peek(&mut self) -> Option<&OutboundMessage> {
+        if self.next.is_none() {
+            self.next = Some(self.rx.recv().await?);
+        }
+        self.next.as_ref()
+    }
+
+    /// Takes the message `peek` returned.
+    pub fn take(&mut self) -> Option<OutboundMessage> {
+        self.next.take()
+    }
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct StudioConnection {
     #[serde(skip)]
-    pub sender: mpsc::UnboundedSender<String>,
+    pub sender: mpsc::UnboundedSender<OutboundMessage>,
+    #[serde(skip)]
+    pub queue: StudioQueue,
+    /// Identifies the websocket currently serving this Studio; changes on every reconnect.
+    #[serde(skip)]
+    pub link_id: Uuid,
+    /// Cancelled when a newer websocket from the same Studio takes over.
+    #[serde(skip)]
+    pub link_shutdown: CancellationToken,
+    /// Plugin-generated token that, together with `job_id`, recognises a returning Studio.
+    pub instance_token: Option<String>,
+    /// Friendly name for `set_studio` and `[[auto_select]]` rules, from the plugin or
+    /// `set_studio_label`.
+    pub label: Option<String>,
+    /// Set while the Studio is inside its reconnect grace window.
+    pub disconnected_at: Option<chrono::DateTime<chrono::Utc>>,
     pub place_id: u64,
     pub place_name: String,
     pub game_id: u64,
@@ -22,12 +85,55 @@ pub struct StudioConnection {
     pub creator_id: u64,
     pub creator_type: String,
     pub connected_at: chrono::DateTime<chrono::Utc>,
+    pub last_heartbeat: chrono::DateTime<chrono::Utc>,
+    pub latency_ms: Option<f64>,
+    /// Plugin protocol version; `None` for plugins that predate capability negotiation.
+    pub protocol_version: Option<u32>,
+    pub plugin_build: Option<String>,
+    /// Tools the plugin can run; nothing is routed to a Studio whose plugin sent no list.
+    pub tools: Option<Vec<String>>,
+    /// Tools beyond the built-in set that the plugin describes itself, listed as MCP tools.
+    pub custom_tools: Vec<PluginTool>,
+    /// Whether the plugin streams its console output; older plugins keep it to themselves.
+    pub streams_console: bool,
+    /// Whether the plugin takes `config` messages telling it where to reconnect.
+    pub accepts_config: bool,
+    /// Console output received so far. Kept across reconnects, like the `studio_id`.
+    #[serde(skip)]
+    pub console: ConsoleBuffer,
+    /// Sessions' claims on this Studio. Kept across reconnects, like the `studio_id`.
+    #[serde(skip)]
+    pub leases: StudioLeases,
+    /// Tool calls waiting for or holding a turn in this Studio.
+    #[serde(skip)]
+    pub requests: RequestQueue,
 }
 
 impl StudioConnection {
+    /// Explains why `tool` cannot be sent to this Studio, if it cannot.
+    pub fn unsupported_tool(&self, tool: &str) -> Option<String> {
+        let problem = match (self.protocol_version, &self.tools) {
+            (None, _) | (_, None) => "predates protocol versioning".to_string(),
+            (Some(version), _) if version != PROTOCOL_VERSION => format!(
+                "speaks protocol version {version} but this server speaks {PROTOCOL_VERSION}"
+            ),
+            (_, Some(tools)) if !tools.iter().any(|t| t == tool) => {
+                "does not provide this tool".to_string()
+            }
+            _ => return None,
+        };
+        Some(format!(
+            "Cannot run `{tool}` in Studio \"{}\": its MCP plugin {problem}. Ask the user to update \
+             the plugin by restarting this MCP server, which reinstalls the bundled plugin, and \
+             then restarting Studio.",
+            self.place_name
+        ))
+    }
+
     pub fn to_info(&self, studio_id: Uuid) -> StudioInfo {
         StudioInfo {
             studio_id: studio_id.to_string(),
+            label: self.label.clone(),
             place_id: self.place_id,
             place_name: self.place_name.clone(),
             game_id: self.game_id,
@@ -36,6 +142,20 @@ impl StudioConnection {
             creator_id: self.creator_id,
             creator_type: self.creator_type.clone(),
             connected_at: self.connected_at.to_rfc3339(),
+            last_heartbeat: self.last_heartbeat.to_rfc3339(),
+            latency_ms: self.latency_ms,
+            protocol_version: self.protocol_version,
+            plugin_build: self.plugin_build.clone(),
+            plugin_up_to_date: self.protocol_version == Some(PROTOCOL_VERSION)
+                && self.plugin_build.as_deref() == Some(PLUGIN_BUILD_HASH),
+            tools: self.tools.clone().unwrap_or_default(),
+            leases: self.leases.active().map(Lease::to_info).collect(),
+            status: if self.disconnected_at.is_some() {
+                "reconnecting"
+            } else {
+                "connected"
+            }
+            .to_string(),
         }
     }
 }
@@ -44,6 +164,8 @@ impl StudioConnection {
 pub struct StudioInfo {
     #[schemars(description = "Unique studio connection identifier")]
     pub studio_id: String,
+    #[schemars(description = "Friendly name that set_studio accepts in place of the studio_id")]
+    pub label: Option<String>,
     #[schemars(description = "Numeric place identifier")]
     pub place_id: u64,
     #[schemars(description = "Name of the Roblox place")]
@@ -60,13 +182,65 @@ pub struct StudioInfo {
     pub creator_type: String,
     #[schemars(description = "ISO 8601 timestamp of when the studio connected")]
     pub connected_at: String,
+    #[schemars(description = "ISO 8601 timestamp of the last heartbeat received from the studio")]
+    pub last_heartbeat: String,
+    #[schemars(description = "Round-trip time of the last server ping in milliseconds")]
+    pub latency_ms: Option<f64>,
+    #[schemars(description = "Plugin protocol version, absent for outdated plugins")]
+    pub protocol_version: Option<u32>,
+    #[schemars(description = "Hash of the plugin sources the Studio is running")]
+    pub plugin_build: Option<String>,
+    #[schemars(
+        description = "Whether the plugin matches the one bundled with this server; if false, some tools may be refused"
+    )]
+    pub plugin_up_to_date: bool,
+    #[schemars(description = "Tools the plugin can run")]
+    pub tools: Vec<String>,
+    #[schemars(description = "Sessions holding a lease on this studio, from acquire_lease")]
+    pub leases: Vec<LeaseInfo>,
+    #[schemars(
+        description = "`connected`, or `reconnecting` while the plugin is briefly away; requests sent meanwhile are queued"
+    )]
+    pub status: String,
+}
+
+/// A tool a plugin advertises at registration, defined by a `Tools/*` module or a ModuleScript
+/// in the place's `ServerStorage.MCPTools` folder.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PluginTool {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// JSON schema for the arguments; anything but an object is treated as "no arguments".
+    #[serde(default)]
+    pub input_schema: serde_json::Value,
+}
+
+impl PluginTool {
+    pub fn to_tool(&self) -> rmcp::model::Tool {
+        let mut input_schema = match &self.input_schema {
+            serde_json::Value::Object(schema) => schema.clone(),
+            _ => serde_json::Map::new(),
+        };
+        input_schema
+            .entry("type")
+            .or_insert_with(|| "object".into());
+        // Empty Luau tables encode as arrays
+        if input_schema.get("properties") == Some(&serde_json::json!([])) {
+            input_schema.insert("properties".into(), serde_json::json!({}));
+        }
+        rmcp::model::Tool::new(
+            self.name.clone(),
+            self.description.clone().unwrap_or_default(),
+            input_schema,
+        )
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct RegistrationMessage {
-    // Discriminator for future message types (e.g. "register", "heartbeat")
+    // Always "register"; later messages are `PluginMessage`s or tool responses
     #[serde(rename = "type")]
-    #[allow(dead_code)]
     pub msg_type: String,
     pub place_id: u64,
     pub place_name: String,
@@ -75,44 +249,412 @@ pub struct RegistrationMessage {
     pub place_version: u64,
     pub creator_id: u64,
     pub creator_type: String,
+    // Absent on plugins that predate reconnect support
+    #[serde(default)]
+    pub instance_token: Option<String>,
+    // The place's `MCPLabel` attribute on ServerStorage, if it has one
+    #[serde(default)]
+    pub label: Option<String>,
+    // Frame encodings the plugin accepts, most preferred first; empty on older plugins
+    #[serde(default)]
+    pub encodings: Vec<FrameEncoding>,
+    #[serde(default)]
+    pub max_frame_size: Option<usize>,
+    // Capabilities; absent on plugins that predate protocol versioning
+    #[serde(default)]
+    pub protocol_version: Option<u32>,
+    #[serde(default)]
+    pub plugin_build: Option<String>,
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+    #[serde(default)]
+    pub custom_tools: Vec<PluginTool>,
+    // Events the plugin sends once the ack asks for them; absent on older plugins
+    #[serde(default)]
+    pub events: Vec<String>,
+    // Server messages the plugin handles beyond tool calls, pings and cancels; absent on older
+    // plugins, which reject anything else
+    #[serde(default)]
+    pub accepts: Vec<String>,
+}
+
+/// Control messages from the plugin. Tool responses carry no `type` and decode as
+/// `RunCommandResponse` instead.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PluginMessage {
+    Heartbeat,
+    Pong {
+        seq: u32,
+    },
+    /// New Output window entries, sent as they are logged to servers that ask for them in the
+    /// ack. `dropped` counts entries the plugin discarded while it could not send.
+    Console {
+        #[serde(default)]
+        entries: Vec<PluginConsoleEntry>,
+        #[serde(default)]
+        dropped: u64,
+    },
+    /// Interim status of the request `id`, sent only for requests that ask for progress.
+    Progress {
+        id: Uuid,
+        message: String,
+    },
+}
+
+/// Session ids handed to callers without an `mcp-session-id` header (stdio, or HTTP clients
+/// that never negotiated a session). These live for one MCP connection.
+pub const CONNECTION_SESSION_PREFIX: &str = "connection-";
+
+pub fn is_connection_session(mcp_session_id: &str) -> bool {
+    mcp_session_id.starts_with(CONNECTION_SESSION_PREFIX)
 }
 
 #[derive(Clone)]
 pub struct SessionState {
     pub selected_studio_id: Option<Uuid>,
+    /// Client name from the MCP `initialize` request, for `[[auto_select]]` rules.
+    pub client: Option<String>,
     // Future: clipboard field for cross-studio copy/paste
-    #[allow(dead_code)]
     pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_activity: chrono::DateTime<chrono::Utc>,
 }
 
 impl SessionState {
     pub fn new() -> Self {
+        let now = chrono::Utc::now();
         Self {
             selected_studio_id: None,
-            created_at: chrono::Utc::now(),
+            client: None,
+            created_at: now,
+            last_activity: now,
         }
     }
+
+    pub fn to_info(&self, session_id: &str) -> SessionInfo {
+        SessionInfo {
+            session_id: session_id.to_string(),
+            transport: if is_connection_session(session_id) {
+                "connection"
+            } else {
+                "http"
+            }
+            .to_string(),
+            selected_studio_id: self.selected_studio_id.map(|id| id.to_string()),
+            created_at: self.created_at.to_rfc3339(),
+            last_activity: self.last_activity.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Serialize, schemars::JsonSchema)]
+pub struct SessionInfo {
+    #[schemars(description = "MCP session id, or a per-connection id for header-less clients")]
+    pub session_id: String,
+    #[schemars(description = "`http` for mcp-session-id sessions, `connection` otherwise")]
+    pub transport: String,
+    #[schemars(description = "studio_id bound with set_studio, if any")]
+    pub selected_studio_id: Option<String>,
+    #[schemars(description = "ISO 8601 timestamp of when the session was first seen")]
+    pub created_at: String,
+    #[schemars(description = "ISO 8601 timestamp of the session's last tool call")]
+    pub last_activity: String,
 }
 
 pub struct PendingRequest {
-    pub sender: mpsc::UnboundedSender<Result<String>>,
+    pub sender: mpsc::UnboundedSender<Result<serde_json::Value>>,
     pub connection_id: Uuid,
+    /// Chunks of a large response received so far, and how many there will be.
+    pub chunks: Option<(u32, u32)>,
+    /// Where the plugin's progress messages for this request go, if the client wants them.
+    pub progress: Option<mpsc::UnboundedSender<String>>,
+    /// The link that took the request off the Studio's queue to send it, once one has.
+    pub sent_on: Option<Uuid>,
+}
+
+/// How long `dispatch` waits for Studio to answer before cancelling the request.
+#[derive(Clone, Debug)]
+pub struct ToolTimeouts {
+    pub default: Duration,
+    pub per_tool: HashMap<String, Duration>,
+}
+
+impl ToolTimeouts {
+    pub fn for_tool(&self, tool: &str) -> Duration {
+        self.per_tool.get(tool).copied().unwrap_or(self.default)
+    }
+}
+
+impl Default for ToolTimeouts {
+    fn default() -> Self {
+        Self {
+            default: Duration::from_secs(120),
+            per_tool: HashMap::new(),
+        }
+    }
+}
+
+/// Liveness settings for Studio websockets, pushed to the plugin in the registration ack so
+/// both sides ping at the same rate.
+#[derive(Clone, Copy, Debug)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    /// Consecutive silent intervals after which a Studio is evicted as unresponsive.
+    pub max_missed: u32,
+}
+
+impl HeartbeatConfig {
+    pub fn timeout(&self) -> Duration {
+        self.interval * self.max_missed
+    }
 }
 
 pub struct AppState {
     pub connections: HashMap<Uuid, StudioConnection>,
     pub sessions: HashMap<String, SessionState>,
+    /// Connection sessions whose transport is still open, such as the `--stdio` one. The idle
+    /// sweep leaves them alone; they end when the connection closes.
+    pub open_connections: HashSet<String>,
     pub output_map: HashMap<Uuid, PendingRequest>,
+    pub tool_timeouts: ToolTimeouts,
+    pub heartbeat: HeartbeatConfig,
+    /// How long a disconnected Studio keeps its `studio_id` and queued requests.
+    pub reconnect_grace: Duration,
+    pub session_idle_timeout: Duration,
+    /// Console entries kept per Studio.
+    pub console_buffer: usize,
+    /// Mutating tool calls each Studio runs at once.
+    pub studio_concurrency: usize,
+    /// The websocket URL plugins should dial, pushed to them when a reload changes it.
+    pub plugin_url: String,
+    pub policy: PolicySettings,
+    /// Rules picking the Studio of sessions that have not called `set_studio`.
+    pub auto_select: Vec<AutoSelectRule>,
+    pub approval: ApprovalSettings,
+    /// Tool calls waiting in the approval queue, by approval id.
+    pub approvals: HashMap<Uuid, PendingApproval>,
+    pub audit: Arc<AuditLog>,
+    pub metrics: Arc<Metrics>,
+    /// Fires when Studios come or go, so MCP sessions can send `tools/list_changed`.
+    pub tool_list_changed: broadcast::Sender<()>,
+    /// Fires when Studios come or go or their resources change, for resource subscribers.
+    pub resource_events: broadcast::Sender<ResourceEvent>,
+    /// Wakes sessions waiting for a lease when leases are released or a Studio goes away.
+    pub lease_changed: Arc<Notify>,
+    /// Wakes queued tool calls when a call finishes or a Studio goes away.
+    pub request_queue_changed: Arc<Notify>,
 }
 
 pub type PackedState = Arc<Mutex<AppState>>;
 
 impl AppState {
-    pub fn new() -> Self {
-        Self {
+    pub fn new(settings: &Settings) -> Self {
+        let mut state = Self {
             connections: HashMap::new(),
             sessions: HashMap::new(),
+            open_connections: HashSet::new(),
             output_map: HashMap::new(),
+            tool_timeouts: ToolTimeouts::default(),
+            heartbeat: settings.heartbeat(),
+            reconnect_grace: Duration::ZERO,
+            session_idle_timeout: Duration::ZERO,
+            console_buffer: 0,
+            studio_concurrency: 1,
+            plugin_url: String::new(),
+            policy: PolicySettings::default(),
+            auto_select: Vec::new(),
+            approval: ApprovalSettings::default(),
+            approvals: HashMap::new(),
+            audit: Arc::new(AuditLog::new(&settings.audit)),
+            metrics: Arc::new(Metrics::default()),
+            tool_list_changed: broadcast::channel(16).0,
+            resource_events: broadcast::channel(64).0,
+            lease_changed: Arc::new(Notify::new()),
+            request_queue_changed: Arc::new(Notify::new()),
+        };
+        state.apply_settings(settings);
+        state
+    }
+
+    /// Forgets `session_id` with its Studio selection, leases and places in lease queues.
+    pub fn end_session(&mut self, session_id: &str) {
+        self.open_connections.remove(session_id);
+        if self.sessions.remove(session_id).is_some() {
+            tracing::info!("Agent session ended: {session_id}");
+        }
+        let mut released = false;
+        for conn in self.connections.values_mut() {
+            released |= conn.leases.forget(session_id);
+        }
+        if released {
+            self.lease_changed.notify_waiters();
+        }
+    }
+
+    /// Takes on reloaded settings. Connected Studios keep the heartbeat they registered with.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.tool_timeouts = settings.tool_timeouts();
+        self.heartbeat = settings.heartbeat();
+        self.reconnect_grace = settings.reconnect_grace();
+        self.session_idle_timeout = settings.session_idle_timeout();
+        self.console_buffer = settings.console_buffer();
+        if settings.studio_concurrency() != self.studio_concurrency {
+            self.studio_concurrency = settings.studio_concurrency();
+            self.request_queue_changed.notify_waiters();
+        }
+        if settings.plugin_url() != self.plugin_url {
+            self.plugin_url = settings.plugin_url();
+            if !self.connections.is_empty() {
+                tracing::info!(
+                    "Telling connected Studios to reconnect to {} once the server restarts",
+                    self.plugin_url
+                );
+            }
+            for conn in self.connections.values() {
+                self.send_config(conn);
+            }
+        }
+        self.auto_select = settings.auto_select();
+        self.approval = settings.approval.clone();
+        self.audit.configure(&settings.audit);
+        if settings.policy != self.policy {
+            self.policy = settings.policy.clone();
+            self.notify_tool_list_changed();
+        }
+    }
+
+    /// Tells a plugin which websocket URL to dial from its next reconnect on, so a Studio left
+    /// open across a change of address finds the server once it restarts there.
+    pub fn send_config(&self, conn: &StudioConnection) {
+        if !conn.accepts_config {
+            return;
+        }
+        let config = serde_json::json!({ "type": "config", "url": self.plugin_url });
+        match OutboundMessage::encode(&config, None) {
+            Ok(message) => {
+                let _ = conn.sender.send(message);
+            }
+            Err(e) => tracing::error!("Failed to encode config message: {e}"),
+        }
+    }
+
+    /// Plugin-advertised tools across all connected Studios. When several Studios advertise the
+    /// same name, the one that connected first describes it.
+    pub fn plugin_tools(&self) -> Vec<PluginTool> {
+        let mut connections: Vec<&StudioConnection> = self.connections.values().collect();
+        connections.sort_by_key(|conn| conn.connected_at);
+        let mut tools: Vec<PluginTool> = Vec::new();
+        for tool in connections.iter().flat_map(|conn| &conn.custom_tools) {
+            if !tools.iter().any(|t| t.name == tool.name) {
+                tools.push(tool.clone());
+            }
+        }
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        tools
+    }
+
+    /// The Studio a session's calls go to: its selection, or else the one `default_studio`
+    /// picks.
+    pub fn session_studio(&self, session_id: &str) -> Option<(Uuid, &StudioConnection)> {
+        let session = self.sessions.get(session_id);
+        let studio_id = match session.and_then(|s| s.selected_studio_id) {
+            Some(studio_id) => studio_id,
+            None => self.default_studio(session)?,
+        };
+        let (studio_id, conn) = self.connections.get_key_value(&studio_id)?;
+        Some((*studio_id, conn))
+    }
+
+    /// The Studio of a session that has not selected one: the first `[[auto_select]]` rule
+    /// that singles one out, or else the only one connected.
+    pub fn default_studio(&self, session: Option<&SessionState>) -> Option<Uuid> {
+        let no_session = SessionState::new();
+        if let Some(studio_id) = auto_select(self, session.unwrap_or(&no_session)) {
+            return Some(studio_id);
+        }
+        match self.connections.len() {
+            1 => self.connections.keys().next().copied(),
+            _ => None,
+        }
+    }
+
+    /// The Studio a call goes to: `studio_id` when the caller names one, otherwise the
+    /// session's.
+    pub fn call_studio(
+        &self,
+        session_id: &str,
+        studio_id: Option<Uuid>,
+    ) -> Option<(Uuid, &StudioConnection)> {
+        match studio_id {
+            Some(studio_id) => self
+                .connections
+                .get_key_value(&studio_id)
+                .map(|(studio_id, conn)| (*studio_id, conn)),
+            None => self.session_studio(session_id),
+        }
+    }
+
+    /// What the audit log records as the Studio of a call the server answers itself.
+    pub fn audit_target(&self, session_id: &str, studio_id: Option<Uuid>) -> AuditTarget {
+        match self.call_studio(session_id, studio_id) {
+            Some((studio_id, conn)) => AuditTarget {
+                studio_id: Some(studio_id),
+                place_id: Some(conn.place_id),
+            },
+            None => AuditTarget::default(),
+        }
+    }
+
+    pub fn notify_tool_list_changed(&self) {
+        // No receivers just means no MCP session is listening
+        let _ = self.tool_list_changed.send(());
+    }
+
+    /// Tells MCP sessions that a Studio is gone for good.
+    pub fn notify_studio_removed(&self, studio_id: Uuid) {
+        self.notify_tool_list_changed();
+        self.notify_resource_list_changed();
+        for resource in StudioResource::ALL {
+            self.notify_resource_updated(studio_id, resource);
+        }
+        self.lease_changed.notify_waiters();
+        self.request_queue_changed.notify_waiters();
+    }
+
+    pub fn notify_resource_list_changed(&self) {
+        let _ = self.resource_events.send(ResourceEvent::ListChanged);
+    }
+
+    pub fn notify_resource_updated(&self, studio_id: Uuid, resource: StudioResource) {
+        let _ = self
+            .resource_events
+            .send(ResourceEvent::Updated(resource.uri(studio_id)));
+    }
+
+    /// Fails every request still waiting on `studio_id` with `reason`.
+    pub fn fail_pending_requests(&mut self, studio_id: Uuid, reason: &str) {
+        self.fail_requests_where(|req| req.connection_id == studio_id, reason);
+    }
+
+    /// Fails the requests already sent over the link `link_id`, whose answers that link can no
+    /// longer deliver. Requests still in the Studio's queue wait for the next link.
+    pub fn fail_link_requests(&mut self, link_id: Uuid, reason: &str) {
+        self.fail_requests_where(|req| req.sent_on == Some(link_id), reason);
+    }
+
+    fn fail_requests_where(&mut self, failed: impl Fn(&PendingRequest) -> bool, reason: &str) {
+        let pending_ids: Vec<Uuid> = self
+            .output_map
+            .iter()
+            .filter(|(_, req)| failed(req))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in pending_ids {
+            if let Some(pending) = self.output_map.remove(&id) {
+                let _ = pending
+                    .sender
+                    .send(Err(color_eyre::eyre::eyre!(reason.to_string()).into()));
+            }
         }
     }
 }
@@ -122,6 +664,52 @@ struct WireMessage<'a, T: Serialize> {
     tool: &'a str,
     args: &'a T,
     id: Uuid,
+    /// Asks the plugin for `progress` messages; omitted when false so older plugins see the
+    /// message shape they know.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    progress: bool,
+}
+
+/// Tells the plugin to abandon an in-flight request; no response is expected.
+#[derive(Serialize)]
+struct CancelMessage {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    id: Uuid,
+}
+
+/// The shape a Studio tool answers with.
+pub trait StudioResult: DeserializeOwned {
+    /// Whether the answer reports that the tool itself failed, such as code that threw. The
+    /// call is then marked as an error, with the answer still in `structured_content`.
+    fn failed(&self) -> bool {
+        false
+    }
+}
+
+impl StudioResult for serde_json::Value {}
+
+/// Per-call knobs for `dispatch`.
+pub struct DispatchOptions {
+    /// Cancelled when the MCP client sends `notifications/cancelled` for this call.
+    pub ct: CancellationToken,
+    /// Lower bound on the wait, for tools whose arguments carry their own timeout.
+    pub min_timeout: Option<Duration>,
+    /// Receives the plugin's progress messages until the call finishes.
+    pub progress: Option<mpsc::UnboundedSender<String>>,
+    /// Whether the tool is annotated read-only, so the call skips the Studio's queue.
+    pub read_only: bool,
+}
+
+impl DispatchOptions {
+    pub fn new(ct: CancellationToken) -> Self {
+        Self {
+            ct,
+            min_timeout: None,
+            progress: None,
+            read_only: false,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -131,55 +719,150 @@ pub struct RunCommandResponse {
     pub id: Uuid,
 }
 
-pub fn value_to_mcp_string(value: MsgpackValue) -> String {
+/// Converts a plugin response to JSON without losing information.
+///
+/// Maps become objects when every key is a string or integer; anything else becomes
+/// `{"$map": [[key, value], ...]}`. Binary data becomes `{"$binary": <base64>}`, Roblox datatypes
+/// go through `roblox_value_to_json`, and other extension values become
+/// `{"$ext": <type>, "data": <base64>}`. Non-finite floats, which JSON cannot represent, become
+/// the strings `"NaN"`, `"Infinity"` and `"-Infinity"`.
+pub fn msgpack_to_json(value: MsgpackValue) -> serde_json::Value {
+    use serde_json::{json, Value};
     match value {
-        MsgpackValue::Nil => "null".to_string(),
-        MsgpackValue::Boolean(b) => b.to_string(),
-        MsgpackValue::Integer(i) => i.to_string(),
-        MsgpackValue::F32(f) => float_to_string(f as f64),
-        MsgpackValue::F64(f) => float_to_string(f),
-        MsgpackValue::String(s) => s.into_str().unwrap_or_default(),
-        MsgpackValue::Binary(b) => b
-            .iter()
-            .map(|byte| format!("{byte:02x}"))
-            .collect::<Vec<_>>()
-            .join(""),
-        MsgpackValue::Array(arr) => {
-            let items: Vec<String> = arr.into_iter().map(value_to_mcp_string).collect();
-            format!("[{}]", items.join(", "))
-        }
+        MsgpackValue::Nil => Value::Null,
+        MsgpackValue::Boolean(b) => Value::Bool(b),
+        MsgpackValue::Integer(i) => i
+            .as_i64()
+            .map(Value::from)
+            .or_else(|| i.as_u64().map(Value::from))
+            .unwrap_or(Value::Null),
+        MsgpackValue::F32(f) => float_to_json(f.into()),
+        MsgpackValue::F64(f) => float_to_json(f),
+        MsgpackValue::String(s) if s.is_str() => Value::String(s.into_str().unwrap_or_default()),
+        MsgpackValue::String(s) => json!({ "$binary": BASE64.encode(s.into_bytes()) }),
+        MsgpackValue::Binary(b) => json!({ "$binary": BASE64.encode(b) }),
+        MsgpackValue::Array(arr) => Value::Array(arr.into_iter().map(msgpack_to_json).collect()),
         MsgpackValue::Map(pairs) => {
-            let entries: Vec<String> = pairs
-                .into_iter()
-                .map(|(k, v)| format!("{}: {}", value_to_mcp_string(k), value_to_mcp_string(v)))
-                .collect();
-            format!("{{{}}}", entries.join(", "))
+            let object_keys = pairs
+                .iter()
+                .all(|(k, _)| k.is_str() || k.is_i64() || k.is_u64());
+            if object_keys {
+                let object = pairs
+                    .into_iter()
+                    .map(|(k, v)| {
+                        let key = match k {
+                            MsgpackValue::String(s) => s.into_str().unwrap_or_default(),
+                            other => other.to_string(),
+                        };
+                        (key, msgpack_to_json(v))
+                    })
+                    .collect();
+                Value::Object(object)
+            } else {
+                let entries = pairs
+                    .into_iter()
+                    .map(|(k, v)| json!([msgpack_to_json(k), msgpack_to_json(v)]))
+                    .collect::<Vec<_>>();
+                json!({ "$map": entries })
+            }
+        }
+        MsgpackValue::Ext(ROBLOX_VALUE_EXT, data) => match RobloxValue::from_ext(&data) {
+            Ok(value) => roblox_value_to_json(&value),
+            Err(e) => {
+                tracing::warn!("Failed to decode Roblox value: {e}");
+                json!({ "$ext": ROBLOX_VALUE_EXT, "data": BASE64.encode(data) })
+            }
+        },
+        MsgpackValue::Ext(ext_type, data) => {
+            json!({ "$ext": ext_type, "data": BASE64.encode(data) })
         }
-        MsgpackValue::Ext(_, _) => "<ext>".to_string(),
     }
 }
 
-fn float_to_string(f: f64) -> String {
+/// Renders a Roblox datatype as `{"$type": "Vector3", "value": [1, 2, 3], "display": "Vector3(1, 2, 3)"}`,
+/// where `value` is its `Codec.luau` encoding.
+fn roblox_value_to_json(value: &RobloxValue) -> serde_json::Value {
+    let (type_name, encoded) = match serde_json::to_value(value) {
+        Ok(serde_json::Value::Object(tagged)) => tagged.into_iter().next().unzip(),
+        _ => (None, None),
+    };
+    serde_json::json!({
+        "$type": type_name,
+        "value": encoded,
+        "display": value.to_string(),
+    })
+}
+
+fn float_to_json(f: f64) -> serde_json::Value {
     if f.is_nan() {
-        "NaN".to_string()
+        "NaN".into()
     } else if f == f64::INFINITY {
-        "Infinity".to_string()
+        "Infinity".into()
     } else if f == f64::NEG_INFINITY {
-        "-Infinity".to_string()
+        "-Infinity".into()
     } else {
-        f.to_string()
+        f.into()
     }
 }
 
-pub fn get_or_create_session(state: &mut AppState, mcp_session_id: &str) -> SessionState {
-    state
+/// Plugin errors are usually a message string; anything else is shown as JSON.
+pub fn msgpack_to_error_text(value: MsgpackValue) -> String {
+    match msgpack_to_json(value) {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// Returns the session for `mcp_session_id`, creating it on first use, and records activity.
+pub fn touch_session<'a>(state: &'a mut AppState, mcp_session_id: &str) -> &'a mut SessionState {
+    let session = state
         .sessions
         .entry(mcp_session_id.to_string())
-        .or_insert_with(SessionState::new)
-        .clone()
+        .or_insert_with(|| {
+            tracing::info!("Agent session started: {mcp_session_id}");
+            SessionState::new()
+        });
+    session.last_activity = chrono::Utc::now();
+    session
+}
+
+pub fn get_or_create_session(state: &mut AppState, mcp_session_id: &str) -> SessionState {
+    touch_session(state, mcp_session_id).clone()
+}
+
+/// Drops sessions whose MCP session has closed (HTTP sessions tracked by rmcp's session
+/// manager) or that have been idle longer than `idle_timeout` (per-connection sessions).
+pub async fn sweep_sessions(state: &PackedState, session_manager: &LocalSessionManager) {
+    let live_http_sessions: HashSet<String> = session_manager
+        .sessions
+        .read()
+        .await
+        .keys()
+        .map(|id| id.to_string())
+        .collect();
+    let now = chrono::Utc::now();
+    let mut s = state.lock().await;
+    let idle_timeout = s.session_idle_timeout;
+    let ended_sessions: Vec<String> = s
+        .sessions
+        .iter()
+        .filter(|(id, session)| {
+            if s.open_connections.contains(*id) {
+                false
+            } else if is_connection_session(id) {
+                (now - session.last_activity).to_std().unwrap_or_default() > idle_timeout
+            } else {
+                !live_http_sessions.contains(*id)
+            }
+        })
+        .map(|(id, _)| id.clone())
+        .collect();
+    for session_id in ended_sessions {
+        s.end_session(&session_id);
+    }
 }
 
-fn resolve_studio_id(
+pub fn resolve_studio_id(
     state: &AppState,
     session: &SessionState,
 ) -> std::result::Result<Uuid, CallToolResult> {
@@ -193,50 +876,82 @@ fn resolve_studio_id(
         ))]));
     }
 
-    match state.connections.len() {
-        0 => Err(CallToolResult::error(vec![Content::text(
+    if let Some(studio_id) = state.default_studio(Some(session)) {
+        return Ok(studio_id);
+    }
+    if state.connections.is_empty() {
+        return Err(CallToolResult::error(vec![Content::text(
             "No Studio instances connected. Open Roblox Studio with the MCP plugin enabled.",
-        )])),
-        1 => {
-            let studio_id = *state.connections.keys().next().unwrap();
-            Ok(studio_id)
-        }
-        _ => {
-            let studios: Vec<String> = state
-                .connections
-                .iter()
-                .map(|(id, conn)| format!("  {} - {}", id, conn.place_name))
-                .collect();
-            Err(CallToolResult::error(vec![Content::text(format!(
-                "Multiple studios connected. Call `set_studio` with one of these studio_ids first:\n{}",
-                studios.join("\n")
-            ))]))
-        }
+        )]));
     }
+    Err(CallToolResult::error(vec![Content::text(format!(
+        "Multiple studios connected. Call `set_studio` with one of these studio_ids or labels first:\n{}",
+        describe_studios(state)
+    ))]))
 }
 
-pub async fn dispatch<T: Serialize>(
+/// Sends `tool` to the session's Studio and waits for the response, returned as
+/// `structured_content` (with a JSON text copy for older clients) once it parses as `R`.
+pub async fn dispatch<R: StudioResult>(
     state: &PackedState,
+    session_id: &str,
     session: &SessionState,
     tool: &str,
-    args: &T,
+    args: &impl Serialize,
+    options: DispatchOptions,
+) -> std::result::Result<CallToolResult, ErrorData> {
+    let started = Instant::now();
+    let mut target = AuditTarget::default();
+    let result =
+        send_to_studio::<R>(state, session_id, session, tool, args, options, &mut target).await;
+    let arguments = serde_json::to_value(args).unwrap_or_default();
+    let (audit, metrics) = {
+        let s = state.lock().await;
+        (s.audit.clone(), s.metrics.clone())
+    };
+    let succeeded = matches!(&result, Ok(result) if result.is_error != Some(true));
+    metrics.record_tool_call(tool, target.place_id, started.elapsed(), !succeeded);
+    if let Some(studio_id) = target.studio_id.filter(|_| succeeded && changes_mode(tool)) {
+        let s = state.lock().await;
+        s.notify_resource_updated(studio_id, StudioResource::Mode);
+    }
+    audit.record(session_id, target, tool, arguments, started, &result);
+    result
+}
+
+/// Sends `tool` to the session's Studio and waits for the answer, noting in `target` which
+/// Studio it went to.
+async fn send_to_studio<R: StudioResult>(
+    state: &PackedState,
+    session_id: &str,
+    session: &SessionState,
+    tool: &str,
+    args: &impl Serialize,
+    options: DispatchOptions,
+    target: &mut AuditTarget,
 ) -> std::result::Result<CallToolResult, ErrorData> {
     let id = Uuid::new_v4();
-    let command = WireMessage { tool, args, id };
+    let command = WireMessage {
+        tool,
+        args,
+        id,
+        progress: options.progress.is_some(),
+    };
     tracing::debug!("Running command: {tool} (id={id})");
 
-    let b64_text = crate::rbx_studio_server::ws_encode(&command)
-        .map_err(|e| ErrorData::internal_error(format!("ws_encode error: {e}"), None))?;
+    let message = OutboundMessage::encode(&command, Some(id))
+        .map_err(|e| ErrorData::internal_error(format!("msgpack encode error: {e}"), None))?;
 
-    let (tx, mut rx) = mpsc::unbounded_channel::<Result<String>>();
+    let (tx, mut rx) = mpsc::unbounded_channel::<Result<serde_json::Value>>();
 
-    let sender = {
+    let (studio_id, ticket) = {
         let mut s = state.lock().await;
         let studio_id = match resolve_studio_id(&s, session) {
             Ok(id) => id,
             Err(error_result) => return Ok(error_result),
         };
-        let conn = match s.connections.get(&studio_id) {
+        let ticket = Ticket::new(s.request_queue_changed.clone());
+        let conn = match s.connections.get_mut(&studio_id) {
             Some(conn) => conn,
             None => {
                 return Ok(CallToolResult::error(vec![Content::text(
@@ -244,26 +959,102 @@ pub async fn dispatch<T: Serialize>(
                 )]));
             }
         };
-        let sender = conn.sender.clone();
-        s.output_map.insert(
-            id,
-            PendingRequest {
-                sender: tx,
-                connection_id: studio_id,
-            },
-        );
-        sender
+        *target = AuditTarget {
+            studio_id: Some(studio_id),
+            place_id: Some(conn.place_id),
+        };
+        if let Some(reason) = conn.unsupported_tool(tool) {
+            return Ok(CallToolResult::error(vec![Content::text(reason)]));
+        }
+        conn.requests
+            .push(id, tool, session_id, options.read_only, &ticket);
+        (studio_id, ticket)
     };
 
-    if let Err(e) = sender.send(b64_text) {
-        let mut s = state.lock().await;
-        s.output_map.remove(&id);
-        return Ok(CallToolResult::error(vec![Content::text(format!(
-            "Studio disconnected: {e}. Call `list_studios` to see available studios.",
-        ))]));
-    }
+    // Wait for this call's turn in the Studio's queue
+    let (sender, timeout) = loop {
+        let queue_changed = ticket.changed();
+        let notified = queue_changed.notified();
+        tokio::pin!(notified);
+        // Listen before checking, so a call finishing in between is not missed
+        notified.as_mut().enable();
+        {
+            let mut s = state.lock().await;
+            let concurrency = s.studio_concurrency;
+            let timeout = s
+                .tool_timeouts
+                .for_tool(tool)
+                .max(options.min_timeout.unwrap_or_default());
+            let Some(conn) = s.connections.get_mut(&studio_id) else {
+                return Ok(CallToolResult::error(vec![Content::text(
+                    "Studio disconnected while the call was queued. Call `list_studios` to see available studios.",
+                )]));
+            };
+            if let Some(lease) = conn.leases.blocker(session_id) {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "Studio \"{}\" is locked by session {} until {}. Wait for it to be released, or call `acquire_lease` with `wait_secs` to queue for it.",
+                    conn.place_name,
+                    lease.session_id,
+                    lease.expires_at.to_rfc3339()
+                ))]));
+            }
+            if conn.requests.try_start(id, concurrency) {
+                let sender = conn.sender.clone();
+                // Enqueue while holding the lock so a concurrent disconnect either fails this
+                // request or leaves it queued for the reconnect, never both
+                if let Err(e) = sender.send(message) {
+                    return Ok(CallToolResult::error(vec![Content::text(format!(
+                        "Studio disconnected: {e}. Call `list_studios` to see available studios.",
+                    ))]));
+                }
+                s.output_map.insert(
+                    id,
+                    PendingRequest {
+                        sender: tx,
+                        connection_id: studio_id,
+                        chunks: None,
+                        progress: options.progress.clone(),
+                        sent_on: None,
+                    },
+                );
+                break (sender, timeout);
+            }
+        }
+        tokio::select! {
+            _ = notified => {}
+            _ = options.ct.cancelled() => {
+                tracing::info!("Command {tool} (id={id}) cancelled by client while queued");
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "Cancelled: `{tool}` was cancelled by the client while waiting for earlier calls to Studio to finish.",
+                ))]));
+            }
+        }
+    };
 
-    let result = rx.recv().await;
+    let result = tokio::select! {
+        result = rx.recv() => result,
+        _ = tokio::time::sleep(timeout) => {
+            tracing::warn!("Command {tool} (id={id}) timed out after {}s", timeout.as_secs());
+            let chunks = abandon_request(state, &sender, id).await;
+            let progress = match chunks {
+                Some((received, count)) => {
+                    format!(" Studio had sent {received} of {count} chunks of its response.")
+                }
+                None => String::new(),
+            };
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "Timed out: `{tool}` did not finish within {}s and was cancelled in Studio.{progress}",
+                timeout.as_secs()
+            ))]));
+        }
+        _ = options.ct.cancelled() => {
+            tracing::info!("Command {tool} (id={id}) cancelled by client");
+            abandon_request(state, &sender, id).await;
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "Cancelled: `{tool}` was cancelled by the client before Studio responded.",
+            ))]));
+        }
+    };
 
     {
         let mut s = state.lock().await;
@@ -281,7 +1072,71 @@ pub async fn dispatch<T: Serialize>(
 
     tracing::debug!("Sending to MCP: {result:?}");
     match result {
-        Ok(r) => Ok(CallToolResult::success(vec![Content::text(r)])),
+        Ok(value) => match serde_json::from_value::<R>(value.clone()) {
+            Ok(parsed) if parsed.failed() => Ok(CallToolResult::structured_error(value)),
+            Ok(_) => Ok(CallToolResult::structured(value)),
+            Err(e) => {
+                tracing::warn!("Unexpected result shape from `{tool}`: {e}");
+                Ok(CallToolResult::error(vec![Content::text(format!(
+                    "Studio returned an unexpected result for `{tool}` ({e}); the plugin may be out of date. Raw result: {value}",
+                ))]))
+            }
+        },
         Err(err) => Ok(CallToolResult::error(vec![Content::text(err.to_string())])),
     }
 }
+
+/// Drops the pending entry so a late response is ignored, and asks the plugin to stop working on
+/// the request. Returns how far a chunked response had got.
+async fn abandon_request(
+    state: &PackedState,
+    sender: &mpsc::UnboundedSender<OutboundMessage>,
+    id: Uuid,
+) -> Option<(u32, u32)> {
+    let pending = state.lock().await.output_map.remove(&id);
+    let cancel = CancelMessage {
+        msg_type: "cancel",
+        id,
+    };
+    match OutboundMessage::encode(&cancel, Some(id)) {
+        Ok(message) => {
+            let _ = sender.send(message);
+        }
+        Err(e) => tracing::error!("Failed to encode cancel message: {e}"),
+    }
+    pending.and_then(|pending| pending.chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn idle_sweep_spares_open_connection_sessions() {
+        let state: PackedState = Arc::new(Mutex::new(AppState::new(&Settings::default())));
+        let idle = SessionState {
+            last_activity: chrono::Utc::now() - chrono::Duration::hours(2),
+            ..SessionState::new()
+        };
+        {
+            let mut s = state.lock().await;
+            s.sessions.insert("connection-open".into(), idle.clone());
+            s.open_connections.insert("connection-open".into());
+            s.sessions.insert("connection-idle".into(), idle);
+            s.sessions
+                .insert("connection-active".into(), SessionState::new());
+            s.sessions.insert("closed-http".into(), SessionState::new());
+        }
+
+        sweep_sessions(&state, &LocalSessionManager::default()).await;
+        let mut s = state.lock().await;
+        let mut left: Vec<&str> = s.sessions.keys().map(String::as_str).collect();
+        left.sort();
+        assert_eq!(left, ["connection-active", "connection-open"]);
+
+        // Closing the connection ends its session
+        s.end_session("connection-open");
+        assert!(!s.sessions.contains_key("connection-open"));
+        assert!(s.open_connections.is_empty());
+    }
+}
@@ -0,0 +1,34 @@
+use color_eyre::eyre::Result;
+use rmcp::transport::{
+    async_rw::AsyncRwTransport, stdio, streamable_http_client::StreamableHttpClientTransportConfig,
+    StreamableHttpClientTransport, Transport,
+};
+
+/// Relays MCP messages between stdin/stdout and the `/mcp` endpoint of a server instance that
+/// already owns the listen port, so stdio clients share its Studio connections.
+pub async fn run(uri: String, token: String) -> Result<()> {
+    let (stdin, stdout) = stdio();
+    let mut local = AsyncRwTransport::new_server(stdin, stdout);
+    let mut upstream = StreamableHttpClientTransport::from_config(
+        StreamableHttpClientTransportConfig::with_uri(uri).auth_header(token),
+    );
+
+    loop {
+        tokio::select! {
+            message = local.receive() => {
+                let Some(message) = message else { break };
+                upstream.send(message).await?;
+            }
+            message = upstream.receive() => {
+                let Some(message) = message else {
+                    tracing::warn!("Upstream MCP server closed the connection");
+                    break;
+                };
+                local.send(message).await?;
+            }
+        }
+    }
+
+    let _ = upstream.close().await;
+    Ok(())
+}
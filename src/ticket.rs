@@ -0,0 +1,54 @@
+//! Places in line for something shared, as used by the lease queue and the request queue. A
+//! waiter holds a `Ticket`, and the line keeps only its `TicketEntry`. Dropping the ticket
+//! lapses the entry and wakes everyone waiting on the line, so a waiter that finishes, gives up
+//! or is cancelled never holds up the ones behind it, without anyone having to remove it.
+
+use std::sync::{Arc, Weak};
+use tokio::sync::Notify;
+
+pub struct Ticket {
+    alive: Option<Arc<()>>,
+    changed: Arc<Notify>,
+}
+
+impl Ticket {
+    /// A ticket whose drop fires `changed`, the line's notifier.
+    pub fn new(changed: Arc<Notify>) -> Self {
+        Self {
+            alive: Some(Arc::new(())),
+            changed,
+        }
+    }
+
+    /// Fires whenever the line may have moved.
+    pub fn changed(&self) -> Arc<Notify> {
+        self.changed.clone()
+    }
+
+    /// What the line keeps for this ticket.
+    pub fn entry(&self) -> TicketEntry {
+        TicketEntry(self.alive.as_ref().map(Arc::downgrade).unwrap_or_default())
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        // Let go of the entry before waking the others, so they see it lapsed
+        self.alive.take();
+        self.changed.notify_waiters();
+    }
+}
+
+/// A ticket's place in a line, which lapses when the ticket is dropped.
+#[derive(Clone, Debug, Default)]
+pub struct TicketEntry(Weak<()>);
+
+impl TicketEntry {
+    pub fn is_live(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+
+    pub fn belongs_to(&self, ticket: &Ticket) -> bool {
+        self.0.ptr_eq(&ticket.entry().0)
+    }
+}
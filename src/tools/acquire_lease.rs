@@ -0,0 +1,153 @@
+use super::prelude::*;
+use crate::lease::{LeaseInfo, LeaseMode, DEFAULT_TTL, MAX_TTL, MAX_WAIT};
+use crate::resources::StudioResource;
+use crate::selection::find_studio;
+use crate::server_state::resolve_studio_id;
+use crate::ticket::Ticket;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct AcquireLeaseArgs {
+    #[schemars(
+        description = "A studio_id, label, place name or place_id to lease. Defaults to this session's studio."
+    )]
+    pub studio_id: Option<String>,
+    #[schemars(
+        description = "`exclusive` (default) keeps other sessions from calling tools in the studio; `shared` only keeps them from taking an exclusive lease"
+    )]
+    pub mode: Option<LeaseMode>,
+    #[schemars(
+        description = "Seconds until the lease lapses unless renewed by calling acquire_lease again (default 300, at most 3600)"
+    )]
+    pub ttl_secs: Option<u64>,
+    #[schemars(
+        description = "Seconds to wait in line if another session holds a conflicting lease (default 0, at most 300)"
+    )]
+    pub wait_secs: Option<u64>,
+}
+
+#[tool_router(router = acquire_lease_route, vis = "pub")]
+impl RBXStudioServer {
+    #[doc = include_str!("acquire_lease.md")]
+    #[tool(
+        output_schema = output_schema::<LeaseInfo>(),
+        annotations(
+        // Locks other sessions out of the studio, so read-only policies refuse it
+        read_only_hint = false,
+        destructive_hint = false,
+        idempotent_hint = true,
+        open_world_hint = false
+        )
+    )]
+    async fn acquire_lease(
+        &self,
+        ctx: RequestContext<RoleServer>,
+        Parameters(args): Parameters<AcquireLeaseArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let started = Instant::now();
+        let result = self.take_lease(&ctx, &args).await;
+        self.audit_local(&ctx, "acquire_lease", &args, started, &result)
+            .await;
+        result
+    }
+}
+
+impl RBXStudioServer {
+    /// The Studio a lease tool names, or this session's, or the error to return.
+    pub(crate) async fn lease_studio(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        studio_id: Option<&str>,
+    ) -> Result<Uuid, CallToolResult> {
+        let session = self.resolve_session(ctx).await;
+        let s = self.state.lock().await;
+        match studio_id {
+            Some(query) => find_studio(&s, query)
+                .map_err(|message| CallToolResult::error(vec![Content::text(message)])),
+            None => resolve_studio_id(&s, &session),
+        }
+    }
+
+    async fn take_lease(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        args: &AcquireLeaseArgs,
+    ) -> Result<CallToolResult, ErrorData> {
+        let studio_id = match self.lease_studio(ctx, args.studio_id.as_deref()).await {
+            Ok(studio_id) => studio_id,
+            Err(error_result) => return Ok(error_result),
+        };
+        let session_id = self.extract_mcp_session_id(ctx);
+        let mode = args.mode.unwrap_or_default();
+        let ttl = args
+            .ttl_secs
+            .map_or(DEFAULT_TTL, Duration::from_secs)
+            .clamp(Duration::from_secs(1), MAX_TTL);
+        let wait = args.wait_secs.map_or(Duration::ZERO, Duration::from_secs);
+        let deadline = tokio::time::Instant::now() + wait.min(MAX_WAIT);
+        let changed = self.state.lock().await.lease_changed.clone();
+        let ticket = Ticket::new(changed.clone());
+
+        loop {
+            // Listen before checking, so a release between the check and the wait is not missed
+            let notified = changed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let wake_at = {
+                let mut s = self.state.lock().await;
+                let Some(conn) = s.connections.get_mut(&studio_id) else {
+                    return Ok(CallToolResult::error(vec![Content::text(
+                        "Studio disconnected. Call `list_studios` to see available studios.",
+                    )]));
+                };
+                let conflict = match conn
+                    .leases
+                    .try_acquire(&session_id, mode, ttl, Some(&ticket))
+                {
+                    Ok(lease) => {
+                        tracing::info!("Session {session_id} leased studio {studio_id} ({mode:?})");
+                        s.notify_resource_updated(studio_id, StudioResource::Info);
+                        let value = serde_json::to_value(lease.to_info())
+                            .map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+                        return Ok(CallToolResult::structured(value));
+                    }
+                    Err(conflict) => conflict,
+                };
+                if tokio::time::Instant::now() >= deadline {
+                    let holder = match conflict {
+                        Some(lease) => format!(
+                            "locked by session {} ({:?} lease until {})",
+                            lease.session_id,
+                            lease.mode,
+                            lease.expires_at.to_rfc3339()
+                        ),
+                        None => "wanted by sessions that asked first".to_string(),
+                    };
+                    return Ok(CallToolResult::error(vec![Content::text(format!(
+                        "Studio \"{}\" is {holder}. Pass `wait_secs` to wait in line for it.",
+                        conn.place_name
+                    ))]));
+                }
+                conn.leases.enqueue(&session_id, &ticket);
+                let next_expiry = conn
+                    .leases
+                    .next_expiry()
+                    .and_then(|at| (at - chrono::Utc::now()).to_std().ok())
+                    .map(|left| tokio::time::Instant::now() + left);
+                next_expiry.map_or(deadline, |at| at.min(deadline))
+            };
+
+            tokio::select! {
+                _ = notified => {}
+                _ = tokio::time::sleep_until(wake_at) => {}
+                _ = ctx.ct.cancelled() => {
+                    return Ok(CallToolResult::error(vec![Content::text(
+                        "Cancelled: the client gave up waiting for the lease.",
+                    )]));
+                }
+            }
+        }
+    }
+}
@@ -1,19 +1,136 @@
 use super::prelude::*;
+use super::LogLevel;
+use crate::console::{ConsoleEntry, ConsoleQuery};
+use crate::server_state::{resolve_studio_id, AppState};
+use regex::Regex;
+use uuid::Uuid;
+
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Debug, Default, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct GetConsoleOutputArgs {
+    #[schemars(
+        description = "Only return entries from this position on. Pass the `next_cursor` of the previous call to get only new output."
+    )]
+    pub cursor: Option<u64>,
+    #[schemars(description = "Only return entries at these levels")]
+    pub levels: Option<Vec<LogLevel>>,
+    #[schemars(description = "Only return entries whose message matches this regular expression")]
+    pub pattern: Option<String>,
+    #[schemars(description = "Most entries to return, at least 1 (default 100)")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct GetConsoleOutputResult {
+    #[schemars(description = "Matching entries, oldest first")]
+    pub entries: Vec<ConsoleEntry>,
+    #[schemars(description = "Pass as `cursor` to get only entries after these")]
+    pub next_cursor: u64,
+    #[schemars(
+        description = "Entries from `cursor` on that were dropped before they could be read, because the buffer was full or Studio was disconnected"
+    )]
+    pub missed: u64,
+}
 
 #[tool_router(router = get_console_output_route, vis = "pub")]
 impl RBXStudioServer {
     #[doc = include_str!("get_console_output.md")]
-    #[tool(annotations(
+    #[tool(
+        output_schema = output_schema::<GetConsoleOutputResult>(),
+        annotations(
         read_only_hint = true,
         destructive_hint = false,
         idempotent_hint = true,
         open_world_hint = false
-    ))]
+        )
+    )]
     async fn get_console_output(
         &self,
+        Parameters(args): Parameters<GetConsoleOutputArgs>,
         ctx: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, ErrorData> {
-        self.dispatch_to_studio(&ctx, "get_console_output", &())
-            .await
+        self.read_console(&ctx, None, &args).await
     }
 }
+
+impl RBXStudioServer {
+    /// Answers `get_console_output` from the console buffer of `studio_id`, or of the session's
+    /// Studio when that is `None`, and audits the call.
+    pub(crate) async fn read_console(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        studio_id: Option<Uuid>,
+        args: &GetConsoleOutputArgs,
+    ) -> Result<CallToolResult, ErrorData> {
+        let started = Instant::now();
+        let session_id = self.extract_mcp_session_id(ctx);
+        let session = self.resolve_session(ctx).await;
+        let result = {
+            let s = self.state.lock().await;
+            query_console(&s, &session, studio_id, args)
+        };
+        let arguments = serde_json::to_value(args).unwrap_or_default();
+        let s = self.state.lock().await;
+        let target = s.audit_target(&session_id, studio_id);
+        s.audit.record_local(
+            &session_id,
+            target,
+            "get_console_output",
+            arguments,
+            started,
+            &result,
+        );
+        result
+    }
+}
+
+fn query_console(
+    state: &AppState,
+    session: &SessionState,
+    studio_id: Option<Uuid>,
+    args: &GetConsoleOutputArgs,
+) -> Result<CallToolResult, ErrorData> {
+    let studio_id = match studio_id {
+        Some(studio_id) => studio_id,
+        None => match resolve_studio_id(state, session) {
+            Ok(studio_id) => studio_id,
+            Err(error_result) => return Ok(error_result),
+        },
+    };
+    let Some(conn) = state.connections.get(&studio_id) else {
+        return Ok(CallToolResult::error(vec![Content::text(
+            "Studio disconnected. Call `list_studios` to see available studios.",
+        )]));
+    };
+    if !conn.streams_console {
+        return Ok(CallToolResult::error(vec![Content::text(format!(
+            "Cannot read the console of Studio \"{}\": its MCP plugin does not stream console output. Ask the user to update the plugin by restarting this MCP server, which reinstalls the bundled plugin, and then restarting Studio.",
+            conn.place_name
+        ))]));
+    }
+    let pattern = match args.pattern.as_deref().map(Regex::new).transpose() {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "Invalid pattern: {e}"
+            ))]))
+        }
+    };
+
+    let page = conn.console.query(&ConsoleQuery {
+        cursor: args.cursor,
+        levels: args.levels.as_deref().unwrap_or_default(),
+        pattern: pattern.as_ref(),
+        // An empty page would move `next_cursor` past entries nobody has read
+        limit: args.limit.unwrap_or(DEFAULT_LIMIT).max(1),
+    });
+    let result = GetConsoleOutputResult {
+        entries: page.entries,
+        next_cursor: page.next_cursor,
+        missed: page.missed,
+    };
+    let value =
+        serde_json::to_value(result).map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+    Ok(CallToolResult::structured(value))
+}
@@ -13,21 +13,28 @@ impl RBXStudioServer {
         &self,
         ctx: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, ErrorData> {
-        let mcp_session_id = Self::extract_mcp_session_id(&ctx);
-        let s = self.state.lock().await;
+        let started = Instant::now();
+        let result = self.describe_selected_studio(&ctx).await;
+        self.audit_local(&ctx, "get_studio", &JsonObject::new(), started, &result)
+            .await;
+        result
+    }
+}
 
-        let session = match s.sessions.get(&mcp_session_id) {
-            Some(session) => session,
-            None => {
-                return Ok(CallToolResult::error(vec![Content::text(
-                    "No studio selected. Call `list_studios` to see available studios, then `set_studio` to select one.",
-                )]));
-            }
-        };
+impl RBXStudioServer {
+    async fn describe_selected_studio(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let mcp_session_id = self.extract_mcp_session_id(ctx);
+        let mut s = self.state.lock().await;
+        let session = get_or_create_session(&mut s, &mcp_session_id);
 
-        let studio_id = match session.selected_studio_id {
+        let selected = session
+            .selected_studio_id
+            .or_else(|| s.default_studio(Some(&session)));
+        let studio_id = match selected {
             Some(id) => id,
-            None if s.connections.len() == 1 => *s.connections.keys().next().unwrap(),
             None => {
                 return Ok(CallToolResult::error(vec![Content::text(
                     "No studio selected. Call `list_studios` to see available studios, then `set_studio` to select one.",
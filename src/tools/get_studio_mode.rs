@@ -1,18 +1,37 @@
 use super::prelude::*;
 
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StudioMode {
+    StartPlay,
+    RunServer,
+    Stop,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct GetStudioModeResult {
+    pub mode: StudioMode,
+}
+
+impl StudioResult for GetStudioModeResult {}
+
 #[tool_router(router = get_studio_mode_route, vis = "pub")]
 impl RBXStudioServer {
     #[doc = include_str!("get_studio_mode.md")]
-    #[tool(annotations(
+    #[tool(
+        output_schema = output_schema::<GetStudioModeResult>(),
+        annotations(
         read_only_hint = true,
         destructive_hint = false,
         idempotent_hint = true,
         open_world_hint = false
-    ))]
+        )
+    )]
     async fn get_studio_mode(
         &self,
         ctx: RequestContext<RoleServer>,
     ) -> Result<CallToolResult, ErrorData> {
-        self.dispatch_to_studio(&ctx, "get_studio_mode", &()).await
+        self.dispatch_to_studio::<GetStudioModeResult>(&ctx, "get_studio_mode", &())
+            .await
     }
 }
@@ -6,10 +6,20 @@ pub struct InsertModelArgs {
     pub query: String,
 }
 
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct InsertModelResult {
+    #[schemars(description = "Name of the inserted instance in workspace")]
+    pub name: String,
+}
+
+impl StudioResult for InsertModelResult {}
+
 #[tool_router(router = insert_model_route, vis = "pub")]
 impl RBXStudioServer {
     #[doc = include_str!("insert_model.md")]
-    #[tool(annotations(
+    #[tool(
+        output_schema = output_schema::<InsertModelResult>(),
+        annotations(
         // Inserts new instances into workspace
         read_only_hint = false,
         // Only adds content, does not destroy existing data
@@ -18,12 +28,14 @@ impl RBXStudioServer {
         idempotent_hint = false,
         // Queries the Roblox marketplace (external service)
         open_world_hint = true
-    ))]
+        )
+    )]
     async fn insert_model(
         &self,
         ctx: RequestContext<RoleServer>,
         Parameters(args): Parameters<InsertModelArgs>,
     ) -> Result<CallToolResult, ErrorData> {
-        self.dispatch_to_studio(&ctx, "insert_model", &args).await
+        self.dispatch_to_studio::<InsertModelResult>(&ctx, "insert_model", &args)
+            .await
     }
 }
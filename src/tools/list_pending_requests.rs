@@ -0,0 +1,72 @@
+use super::prelude::*;
+use crate::request_queue::QueuedCallInfo;
+
+#[derive(Serialize, schemars::JsonSchema)]
+pub struct StudioRequests {
+    pub studio_id: String,
+    pub place_name: String,
+    #[schemars(description = "Calls sent to the studio and not yet answered, oldest first")]
+    pub running: Vec<QueuedCallInfo>,
+    #[schemars(
+        description = "Calls waiting for earlier calls to finish, in the order they will run"
+    )]
+    pub queued: Vec<QueuedCallInfo>,
+}
+
+#[derive(Serialize, schemars::JsonSchema)]
+pub struct ListPendingRequestsResult {
+    #[schemars(
+        description = "Calls to tools not annotated read-only that each studio runs at once"
+    )]
+    pub concurrency: usize,
+    #[schemars(description = "Every connected studio, in the order they connected")]
+    pub studios: Vec<StudioRequests>,
+}
+
+#[tool_router(router = list_pending_requests_route, vis = "pub")]
+impl RBXStudioServer {
+    #[doc = include_str!("list_pending_requests.md")]
+    #[tool(annotations(
+        read_only_hint = true,
+        destructive_hint = false,
+        idempotent_hint = true,
+        open_world_hint = false
+    ))]
+    async fn list_pending_requests(
+        &self,
+        ctx: RequestContext<RoleServer>,
+    ) -> Result<Json<ListPendingRequestsResult>, ErrorData> {
+        let started = Instant::now();
+        let result = {
+            let s = self.state.lock().await;
+            let mut connections: Vec<_> = s.connections.iter().collect();
+            connections.sort_by_key(|(_, conn)| conn.connected_at);
+            let studios = connections
+                .into_iter()
+                .map(|(studio_id, conn)| {
+                    let (running, queued) = conn.requests.list();
+                    StudioRequests {
+                        studio_id: studio_id.to_string(),
+                        place_name: conn.place_name.clone(),
+                        running,
+                        queued,
+                    }
+                })
+                .collect();
+            ListPendingRequestsResult {
+                concurrency: s.studio_concurrency,
+                studios,
+            }
+        };
+        let result = Ok(Json(result));
+        self.audit_local(
+            &ctx,
+            "list_pending_requests",
+            &JsonObject::new(),
+            started,
+            &result,
+        )
+        .await;
+        result
+    }
+}
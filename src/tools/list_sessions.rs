@@ -0,0 +1,42 @@
+use super::prelude::*;
+
+#[derive(Serialize, schemars::JsonSchema)]
+pub struct ListSessionsResult {
+    #[schemars(description = "All live agent sessions")]
+    pub sessions: Vec<SessionInfo>,
+    #[schemars(description = "session_id of the caller")]
+    pub current_session_id: String,
+}
+
+#[tool_router(router = list_sessions_route, vis = "pub")]
+impl RBXStudioServer {
+    #[doc = include_str!("list_sessions.md")]
+    #[tool(annotations(
+        read_only_hint = true,
+        destructive_hint = false,
+        idempotent_hint = true,
+        open_world_hint = false
+    ))]
+    async fn list_sessions(
+        &self,
+        ctx: RequestContext<RoleServer>,
+    ) -> Result<Json<ListSessionsResult>, ErrorData> {
+        let started = Instant::now();
+        let current_session_id = self.extract_mcp_session_id(&ctx);
+        let sessions: Vec<SessionInfo> = {
+            let mut s = self.state.lock().await;
+            touch_session(&mut s, &current_session_id);
+            s.sessions
+                .iter()
+                .map(|(id, session)| session.to_info(id))
+                .collect()
+        };
+        let result = Ok(Json(ListSessionsResult {
+            sessions,
+            current_session_id,
+        }));
+        self.audit_local(&ctx, "list_sessions", &JsonObject::new(), started, &result)
+            .await;
+        result
+    }
+}
@@ -1,5 +1,11 @@
 use super::prelude::*;
 
+#[derive(Serialize, schemars::JsonSchema)]
+pub struct ListStudiosResult {
+    #[schemars(description = "All currently connected Studio instances")]
+    pub studios: Vec<StudioInfo>,
+}
+
 #[tool_router(router = list_studios_route, vis = "pub")]
 impl RBXStudioServer {
     #[doc = include_str!("list_studios.md")]
@@ -9,13 +15,21 @@ impl RBXStudioServer {
         idempotent_hint = true,
         open_world_hint = false
     ))]
-    async fn list_studios(&self) -> Result<Json<Vec<StudioInfo>>, ErrorData> {
-        let s = self.state.lock().await;
-        let studios: Vec<StudioInfo> = s
-            .connections
-            .iter()
-            .map(|(id, conn)| conn.to_info(*id))
-            .collect();
-        Ok(Json(studios))
+    async fn list_studios(
+        &self,
+        ctx: RequestContext<RoleServer>,
+    ) -> Result<Json<ListStudiosResult>, ErrorData> {
+        let started = Instant::now();
+        let studios: Vec<StudioInfo> = {
+            let s = self.state.lock().await;
+            s.connections
+                .iter()
+                .map(|(id, conn)| conn.to_info(*id))
+                .collect()
+        };
+        let result = Ok(Json(ListStudiosResult { studios }));
+        self.audit_local(&ctx, "list_studios", &JsonObject::new(), started, &result)
+            .await;
+        result
     }
 }
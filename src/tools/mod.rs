@@ -1,66 +1,207 @@
+mod acquire_lease;
 mod get_console_output;
 mod get_studio;
 mod get_studio_mode;
 mod insert_model;
+mod list_pending_requests;
+mod list_sessions;
 mod list_studios;
+mod release_lease;
+mod replay;
 mod run_code;
+mod run_code_multi;
 mod run_script_in_play_mode;
 mod set_studio;
+mod set_studio_label;
 mod start_stop_play;
 
+pub(crate) use crate::console::LogLevel;
+pub(crate) use get_console_output::{GetConsoleOutputArgs, GetConsoleOutputResult};
+pub(crate) use get_studio_mode::GetStudioModeResult;
+pub(crate) use insert_model::InsertModelResult;
+pub(crate) use run_code::RunCodeResult;
+pub(crate) use run_script_in_play_mode::RunScriptInPlayModeResult;
+pub(crate) use start_stop_play::StartStopPlayResult;
+
 pub(crate) mod prelude {
+    pub use crate::audit::Audited;
     pub use crate::rbx_studio_server::RBXStudioServer;
-    pub use crate::server_state::{dispatch, get_or_create_session, SessionState, StudioInfo};
+    pub use crate::server_state::{
+        dispatch, get_or_create_session, touch_session, DispatchOptions, SessionInfo, SessionState,
+        StudioInfo, StudioResult,
+    };
     pub use rmcp::{
         handler::server::{
             router::tool::ToolRouter,
             wrapper::{Json, Parameters},
         },
-        model::{CallToolResult, Content},
+        model::{CallToolResult, Content, JsonObject},
         schemars,
         service::RequestContext,
         tool, tool_router, ErrorData, RoleServer,
     };
     pub use serde::{Deserialize, Serialize};
+    pub use std::sync::Arc;
+    pub use std::time::Instant;
+
+    /// `outputSchema` for a tool whose `structured_content` is a `T`.
+    pub fn output_schema<T: schemars::JsonSchema + 'static>() -> Arc<JsonObject> {
+        rmcp::handler::server::tool::schema_for_output::<T>().unwrap_or_else(|e| {
+            panic!(
+                "Invalid output schema for {}: {e}",
+                std::any::type_name::<T>()
+            )
+        })
+    }
 }
 
+use crate::request_queue::is_read_only;
 use prelude::*;
+use rmcp::model::ProgressNotificationParam;
+use tokio::sync::mpsc;
 
 impl RBXStudioServer {
     pub(crate) fn build_tool_router() -> ToolRouter<Self> {
         Self::run_code_route()
+            + Self::run_code_multi_route()
             + Self::insert_model_route()
             + Self::get_console_output_route()
             + Self::get_studio_mode_route()
             + Self::start_stop_play_route()
             + Self::run_script_in_play_mode_route()
             + Self::set_studio_route()
+            + Self::set_studio_label_route()
+            + Self::acquire_lease_route()
+            + Self::release_lease_route()
             + Self::get_studio_route()
             + Self::list_studios_route()
+            + Self::list_sessions_route()
+            + Self::list_pending_requests_route()
+            + Self::replay_route()
     }
 
-    pub(crate) async fn dispatch_to_studio<T: Serialize>(
+    /// Runs `tool` in the session's Studio; a successful response must parse as `R`.
+    pub(crate) async fn dispatch_to_studio<R: StudioResult>(
         &self,
         ctx: &RequestContext<RoleServer>,
         tool: &str,
-        args: &T,
+        args: &impl Serialize,
     ) -> Result<CallToolResult, ErrorData> {
+        let options = DispatchOptions::new(ctx.ct.clone());
+        self.dispatch_to_studio_with::<R>(ctx, tool, args, options)
+            .await
+    }
+
+    /// Like `dispatch_to_studio`, with `options`. The plugin's progress messages are relayed as
+    /// MCP progress notifications when the client sent a progress token.
+    pub(crate) async fn dispatch_to_studio_with<R: StudioResult>(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        tool: &str,
+        args: &impl Serialize,
+        mut options: DispatchOptions,
+    ) -> Result<CallToolResult, ErrorData> {
+        if options.progress.is_none() {
+            options.progress = relay_progress(ctx);
+        }
+        options.read_only = self.find_tool(tool).await.is_some_and(|t| is_read_only(&t));
+        let session_id = self.extract_mcp_session_id(ctx);
         let session = self.resolve_session(ctx).await;
-        dispatch(&self.state, &session, tool, args).await
+        dispatch::<R>(&self.state, &session_id, &session, tool, args, options).await
     }
 
+    /// Writes the audit record for a tool the server answers without asking Studio. The
+    /// session's current Studio, if it has one, is recorded as the target.
+    pub(crate) async fn audit_local(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        tool: &str,
+        args: &impl Serialize,
+        started: Instant,
+        result: &impl Audited,
+    ) {
+        let session_id = self.extract_mcp_session_id(ctx);
+        let arguments = serde_json::to_value(args).unwrap_or_default();
+        let s = self.state.lock().await;
+        let target = s.audit_target(&session_id, None);
+        s.audit
+            .record_local(&session_id, target, tool, arguments, started, result);
+    }
+
+    /// Whether any connected plugin advertises a tool called `name`.
+    pub(crate) async fn has_plugin_tool(&self, name: &str) -> bool {
+        let s = self.state.lock().await;
+        s.connections
+            .values()
+            .any(|conn| conn.custom_tools.iter().any(|tool| tool.name == name))
+    }
+
+    /// Runs a plugin-advertised tool. Its result has no declared shape; anything but an object
+    /// is wrapped as `{"result": ...}` to fit `structured_content`.
+    pub(crate) async fn call_plugin_tool(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        tool: &str,
+        args: JsonObject,
+    ) -> Result<CallToolResult, ErrorData> {
+        let mut result = self
+            .dispatch_to_studio::<serde_json::Value>(ctx, tool, &args)
+            .await?;
+        if let Some(value) = result.structured_content.take() {
+            result.structured_content = Some(match value {
+                serde_json::Value::Object(_) => value,
+                other => serde_json::json!({ "result": other }),
+            });
+        }
+        Ok(result)
+    }
+
+    /// This call's session, created on first use and remembering the client's name.
     pub(crate) async fn resolve_session(&self, ctx: &RequestContext<RoleServer>) -> SessionState {
-        let mcp_session_id = Self::extract_mcp_session_id(ctx);
+        let mcp_session_id = self.extract_mcp_session_id(ctx);
         let mut s = self.state.lock().await;
-        get_or_create_session(&mut s, &mcp_session_id)
+        let session = touch_session(&mut s, &mcp_session_id);
+        if session.client.is_none() {
+            session.client = ctx
+                .peer
+                .peer_info()
+                .map(|info| info.client_info.name.clone());
+        }
+        session.clone()
     }
 
-    pub(crate) fn extract_mcp_session_id(ctx: &RequestContext<RoleServer>) -> String {
+    pub(crate) fn extract_mcp_session_id(&self, ctx: &RequestContext<RoleServer>) -> String {
         ctx.extensions
             .get::<http::request::Parts>()
             .and_then(|parts| parts.headers.get("mcp-session-id"))
             .and_then(|v| v.to_str().ok())
-            .unwrap_or("unknown")
+            .unwrap_or(&self.connection_session_id)
             .to_string()
     }
 }
+
+/// Forwards messages sent on the returned channel to the client as progress notifications for
+/// this request, numbered from 1, until every sender is dropped. `None` if the client sent no
+/// progress token.
+fn relay_progress(ctx: &RequestContext<RoleServer>) -> Option<mpsc::UnboundedSender<String>> {
+    let progress_token = ctx.meta.get_progress_token()?;
+    let peer = ctx.peer.clone();
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        let mut progress = 0.0;
+        while let Some(message) = rx.recv().await {
+            progress += 1.0;
+            let param = ProgressNotificationParam {
+                progress_token: progress_token.clone(),
+                progress,
+                total: None,
+                message: Some(message),
+            };
+            if let Err(e) = peer.notify_progress(param).await {
+                tracing::debug!("Could not send progress notification: {e}");
+                return;
+            }
+        }
+    });
+    Some(tx)
+}
@@ -0,0 +1,63 @@
+use super::prelude::*;
+use crate::resources::StudioResource;
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ReleaseLeaseArgs {
+    #[schemars(
+        description = "A studio_id, label, place name or place_id to release. Defaults to this session's studio."
+    )]
+    pub studio_id: Option<String>,
+}
+
+#[tool_router(router = release_lease_route, vis = "pub")]
+impl RBXStudioServer {
+    #[doc = include_str!("release_lease.md")]
+    #[tool(annotations(
+        // Frees the studio for others; the policy lets it through read-only mode
+        read_only_hint = false,
+        destructive_hint = false,
+        idempotent_hint = true,
+        open_world_hint = false
+    ))]
+    async fn release_lease(
+        &self,
+        ctx: RequestContext<RoleServer>,
+        Parameters(args): Parameters<ReleaseLeaseArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let started = Instant::now();
+        let result = self.give_up_lease(&ctx, &args).await;
+        self.audit_local(&ctx, "release_lease", &args, started, &result)
+            .await;
+        result
+    }
+}
+
+impl RBXStudioServer {
+    async fn give_up_lease(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        args: &ReleaseLeaseArgs,
+    ) -> Result<CallToolResult, ErrorData> {
+        let studio_id = match self.lease_studio(ctx, args.studio_id.as_deref()).await {
+            Ok(studio_id) => studio_id,
+            Err(error_result) => return Ok(error_result),
+        };
+        let session_id = self.extract_mcp_session_id(ctx);
+        let mut s = self.state.lock().await;
+        let released = s
+            .connections
+            .get_mut(&studio_id)
+            .is_some_and(|conn| conn.leases.release(&session_id));
+        if !released {
+            return Ok(CallToolResult::success(vec![Content::text(
+                "This session held no lease on that studio.",
+            )]));
+        }
+        tracing::info!("Session {session_id} released studio {studio_id}");
+        s.lease_changed.notify_waiters();
+        s.notify_resource_updated(studio_id, StudioResource::Info);
+        Ok(CallToolResult::success(vec![Content::text(
+            "Lease released.",
+        )]))
+    }
+}
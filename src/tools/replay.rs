@@ -0,0 +1,36 @@
+use super::prelude::*;
+use crate::replay::{run, Caller, ReplayArgs, ReplayReport};
+
+#[tool_router(router = replay_route, vis = "pub")]
+impl RBXStudioServer {
+    #[doc = include_str!("replay.md")]
+    #[tool(annotations(
+        // Re-runs arbitrary recorded calls, including run_code
+        read_only_hint = false,
+        destructive_hint = true,
+        idempotent_hint = false,
+        open_world_hint = false
+    ))]
+    async fn replay(
+        &self,
+        ctx: RequestContext<RoleServer>,
+        Parameters(args): Parameters<ReplayArgs>,
+    ) -> Result<Json<ReplayReport>, ErrorData> {
+        let started = Instant::now();
+        let session_id = self.extract_mcp_session_id(&ctx);
+        let session = self.resolve_session(&ctx).await;
+        let result = run(
+            &self.state,
+            Caller::Agent(self, &ctx),
+            &session_id,
+            &session,
+            &args,
+        )
+        .await
+        .map(Json)
+        .map_err(|e| ErrorData::invalid_params(e, None));
+        self.audit_local(&ctx, "replay", &args, started, &result)
+            .await;
+        result
+    }
+}
@@ -1,4 +1,5 @@
 use super::prelude::*;
+use super::LogLevel;
 
 #[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
 pub struct RunCodeArgs {
@@ -6,10 +7,36 @@ pub struct RunCodeArgs {
     pub command: String,
 }
 
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct LogEntry {
+    pub level: LogLevel,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct RunCodeResult {
+    #[schemars(description = "print/warn output from the code, in order")]
+    pub logs: Vec<LogEntry>,
+    #[schemars(
+        description = "Values returned by the code; nil is null. Roblox datatypes are objects with `$type`, `value` (their Codec encoding) and `display`, e.g. \"Vector3(1, 2, 3)\""
+    )]
+    pub returned: Vec<serde_json::Value>,
+    #[schemars(description = "Error message if the code failed to compile or threw")]
+    pub error: Option<String>,
+}
+
+impl StudioResult for RunCodeResult {
+    fn failed(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
 #[tool_router(router = run_code_route, vis = "pub")]
 impl RBXStudioServer {
     #[doc = include_str!("run_code.md")]
-    #[tool(annotations(
+    #[tool(
+        output_schema = output_schema::<RunCodeResult>(),
+        annotations(
         // Tool modifies its environment (e.g. arbitrary code execution)
         read_only_hint = false,
         // Arbitrary code can destroy instances or data
@@ -18,12 +45,14 @@ impl RBXStudioServer {
         idempotent_hint = false,
         // Operates entirely within the Studio session
         open_world_hint = false
-    ))]
+    )
+    )]
     async fn run_code(
         &self,
         ctx: RequestContext<RoleServer>,
         Parameters(args): Parameters<RunCodeArgs>,
     ) -> Result<CallToolResult, ErrorData> {
-        self.dispatch_to_studio(&ctx, "run_code", &args).await
+        self.dispatch_to_studio::<RunCodeResult>(&ctx, "run_code", &args)
+            .await
     }
 }
@@ -0,0 +1,241 @@
+use super::prelude::*;
+use super::run_code::{RunCodeArgs, RunCodeResult};
+use futures_util::future::join_all;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct RunCodeMultiArgs {
+    #[schemars(description = "Code to run in every target Studio")]
+    pub command: String,
+    #[schemars(description = "Run in these Studios, by studio_id from list_studios")]
+    pub studio_ids: Option<Vec<String>>,
+    #[schemars(description = "Run in every Studio with this place open")]
+    pub place_id: Option<u64>,
+    #[schemars(description = "Run in every Studio with a place of this experience open")]
+    pub game_id: Option<u64>,
+    #[schemars(description = "Run in every connected Studio")]
+    pub all: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct StudioRunResult {
+    pub studio_id: String,
+    pub place_id: u64,
+    pub place_name: String,
+    #[schemars(description = "Whether the code ran in this Studio without an error")]
+    pub success: bool,
+    #[schemars(description = "What run_code returned, if the code ran")]
+    pub result: Option<RunCodeResult>,
+    #[schemars(
+        description = "Why the code did not run or failed: a refusal, timeout, disconnect or the code's own error"
+    )]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct RunCodeMultiResult {
+    #[schemars(description = "One entry per target Studio, in the order they connected")]
+    pub results: Vec<StudioRunResult>,
+}
+
+#[tool_router(router = run_code_multi_route, vis = "pub")]
+impl RBXStudioServer {
+    #[doc = include_str!("run_code_multi.md")]
+    #[tool(
+        output_schema = output_schema::<RunCodeMultiResult>(),
+        annotations(
+        read_only_hint = false,
+        destructive_hint = true,
+        idempotent_hint = false,
+        open_world_hint = false
+        )
+    )]
+    async fn run_code_multi(
+        &self,
+        ctx: RequestContext<RoleServer>,
+        Parameters(args): Parameters<RunCodeMultiArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let started = Instant::now();
+        let arguments = serde_json::to_value(&args).unwrap_or_default();
+        let result = self.fan_out(&ctx, args).await;
+        // Each Studio's `run_code` has its own record; this one is local so replays skip it
+        self.audit_local(&ctx, "run_code_multi", &arguments, started, &result)
+            .await;
+        result
+    }
+}
+
+/// A Studio `run_code_multi` runs in, as it was when the call started.
+struct Target {
+    studio_id: Uuid,
+    place_id: u64,
+    place_name: String,
+}
+
+impl RBXStudioServer {
+    /// Runs `run_code` in every Studio the arguments select.
+    async fn fan_out(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        args: RunCodeMultiArgs,
+    ) -> Result<CallToolResult, ErrorData> {
+        let targets = match self.fan_out_targets(&args).await {
+            Ok(targets) => targets,
+            Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+        };
+        let run_code = RunCodeArgs {
+            command: args.command,
+        };
+        let results = join_all(
+            targets
+                .into_iter()
+                .map(|target| self.run_code_in(ctx, target, &run_code)),
+        )
+        .await;
+        let value = serde_json::to_value(RunCodeMultiResult { results })
+            .map_err(|e| ErrorData::internal_error(e.to_string(), None))?;
+        Ok(CallToolResult::structured(value))
+    }
+
+    /// The Studios the arguments select, ordered by connection time, or why there are none.
+    async fn fan_out_targets(&self, args: &RunCodeMultiArgs) -> Result<Vec<Target>, String> {
+        let selectors = [
+            args.studio_ids.is_some(),
+            args.place_id.is_some(),
+            args.game_id.is_some(),
+            args.all == Some(true),
+        ];
+        if selectors.into_iter().filter(|given| *given).count() != 1 {
+            return Err(
+                "Pass exactly one of `studio_ids`, `place_id`, `game_id` or `all: true`."
+                    .to_string(),
+            );
+        }
+        let studio_ids = match &args.studio_ids {
+            Some(ids) => Some(
+                ids.iter()
+                    .map(|id| {
+                        id.parse::<Uuid>()
+                            .map_err(|_| format!("Invalid studio_id: {id}"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+
+        let s = self.state.lock().await;
+        if let Some(missing) = studio_ids
+            .iter()
+            .flatten()
+            .find(|id| !s.connections.contains_key(id))
+        {
+            return Err(format!(
+                "No Studio with studio_id {missing}. Call `list_studios` to see available studios."
+            ));
+        }
+        let mut connections: Vec<_> = s
+            .connections
+            .iter()
+            .filter(|(studio_id, conn)| match &studio_ids {
+                Some(ids) => ids.contains(studio_id),
+                None => {
+                    args.place_id.is_none_or(|id| conn.place_id == id)
+                        && args.game_id.is_none_or(|id| conn.game_id == id)
+                }
+            })
+            .collect();
+        if connections.is_empty() {
+            return Err(
+                "No connected Studio matches. Call `list_studios` to see available studios."
+                    .to_string(),
+            );
+        }
+        connections.sort_by_key(|(_, conn)| conn.connected_at);
+        Ok(connections
+            .into_iter()
+            .map(|(studio_id, conn)| Target {
+                studio_id: *studio_id,
+                place_id: conn.place_id,
+                place_name: conn.place_name.clone(),
+            })
+            .collect())
+    }
+
+    async fn run_code_in(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        target: Target,
+        args: &RunCodeArgs,
+    ) -> StudioRunResult {
+        let outcome = self.run_code_as_session(ctx, target.studio_id, args).await;
+        let (success, error) = match &outcome {
+            Ok(output) => (output.error.is_none(), output.error.clone()),
+            Err(message) => (false, Some(message.clone())),
+        };
+        StudioRunResult {
+            studio_id: target.studio_id.to_string(),
+            place_id: target.place_id,
+            place_name: target.place_name,
+            success,
+            result: outcome.ok(),
+            error,
+        }
+    }
+
+    /// Runs `args` in `studio_id` as if this session had called `run_code` there, so the tool
+    /// policy and audit log apply per Studio. Approval was asked for the whole fan-out, in
+    /// `call_tool`.
+    async fn run_code_as_session(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        studio_id: Uuid,
+        args: &RunCodeArgs,
+    ) -> Result<RunCodeResult, String> {
+        let definition = self
+            .find_tool("run_code")
+            .await
+            .ok_or("No tool `run_code`")?;
+        let arguments = serde_json::to_value(args)
+            .ok()
+            .and_then(|value| value.as_object().cloned())
+            .unwrap_or_default();
+        let result = match self
+            .authorize_policy(ctx, Some(studio_id), &definition, &arguments)
+            .await
+        {
+            Some(refusal) => refusal,
+            None => {
+                let session_id = self.extract_mcp_session_id(ctx);
+                let session = SessionState {
+                    selected_studio_id: Some(studio_id),
+                    ..SessionState::new()
+                };
+                let options = DispatchOptions::new(ctx.ct.clone());
+                dispatch::<RunCodeResult>(
+                    &self.state,
+                    &session_id,
+                    &session,
+                    "run_code",
+                    args,
+                    options,
+                )
+                .await
+                .map_err(|e| e.message.to_string())?
+            }
+        };
+        // Code that threw still has its result; only a call that never ran is just text
+        if let Some(value) = result.structured_content {
+            return serde_json::from_value(value).map_err(|e| e.to_string());
+        }
+        if result.is_error == Some(true) {
+            let text: Vec<&str> = result
+                .content
+                .iter()
+                .filter_map(|content| content.as_text())
+                .map(|content| content.text.as_str())
+                .collect();
+            return Err(text.join("\n"));
+        }
+        Err("Studio returned no result".to_string())
+    }
+}
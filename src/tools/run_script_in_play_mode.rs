@@ -1,4 +1,10 @@
 use super::prelude::*;
+use super::LogLevel;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT_SECS: u32 = 100;
+/// Extra time for Studio to start play mode and stop again around the script itself.
+const PLAY_MODE_OVERHEAD_SECS: u32 = 30;
 
 #[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -16,21 +22,70 @@ pub struct RunScriptInPlayModeArgs {
     pub mode: TestMode,
 }
 
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct PlayModeLogEntry {
+    pub level: LogLevel,
+    pub message: String,
+    #[schemars(description = "os.clock() timestamp in the play session")]
+    pub ts: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct RunScriptInPlayModeResult {
+    #[schemars(description = "Whether the script ran to completion without throwing")]
+    pub success: bool,
+    #[schemars(description = "tostring() of the script's return value, on success")]
+    pub value: Option<String>,
+    #[schemars(description = "Error the script threw, on failure")]
+    pub error: Option<String>,
+    #[schemars(description = "Everything logged during the play session")]
+    pub logs: Vec<PlayModeLogEntry>,
+    #[schemars(description = "The warnings and errors from `logs`")]
+    pub errors: Vec<PlayModeLogEntry>,
+    #[schemars(description = "Seconds the script ran for")]
+    pub duration: f64,
+    #[schemars(description = "True if the script was stopped by the timeout")]
+    pub is_timeout: bool,
+}
+
+impl StudioResult for RunScriptInPlayModeResult {
+    fn failed(&self) -> bool {
+        !self.success
+    }
+}
+
 #[tool_router(router = run_script_in_play_mode_route, vis = "pub")]
 impl RBXStudioServer {
     #[doc = include_str!("run_script_in_play_mode.md")]
-    #[tool(annotations(
+    #[tool(
+        output_schema = output_schema::<RunScriptInPlayModeResult>(),
+        annotations(
         read_only_hint = false,
         destructive_hint = false,
         idempotent_hint = false,
         open_world_hint = false
-    ))]
+        )
+    )]
     async fn run_script_in_play_mode(
         &self,
         ctx: RequestContext<RoleServer>,
-        Parameters(args): Parameters<RunScriptInPlayModeArgs>,
+        Parameters(mut args): Parameters<RunScriptInPlayModeArgs>,
     ) -> Result<CallToolResult, ErrorData> {
-        self.dispatch_to_studio(&ctx, "run_script_in_play_mode", &args)
-            .await
+        let script_timeout = *args.timeout.get_or_insert(DEFAULT_TIMEOUT_SECS);
+        let options = DispatchOptions {
+            min_timeout: Some(Duration::from_secs(
+                script_timeout
+                    .saturating_add(PLAY_MODE_OVERHEAD_SECS)
+                    .into(),
+            )),
+            ..DispatchOptions::new(ctx.ct.clone())
+        };
+        self.dispatch_to_studio_with::<RunScriptInPlayModeResult>(
+            &ctx,
+            "run_script_in_play_mode",
+            &args,
+            options,
+        )
+        .await
     }
 }
@@ -1,9 +1,10 @@
 use super::prelude::*;
+use crate::selection::find_studio;
 
-#[derive(Debug, Deserialize, schemars::JsonSchema)]
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
 pub struct SetStudioArgs {
     #[schemars(
-        description = "The studio_id field from list_studios output. Omit to clear selection."
+        description = "A studio_id, label, place name or place_id from list_studios output. Omit to clear selection."
     )]
     pub studio_id: Option<String>,
 }
@@ -12,11 +13,11 @@ pub struct SetStudioArgs {
 impl RBXStudioServer {
     #[doc = include_str!("set_studio.md")]
     #[tool(annotations(
-        // Modifies session routing state
+        // Changes this session's routing; the policy lets it through read-only mode
         read_only_hint = false,
         // Only changes which studio is targeted, no data loss
         destructive_hint = false,
-        // Setting the same studio_id twice has the same effect
+        // Selecting the same studio twice has the same effect
         idempotent_hint = true,
         // Operates on internal session state only
         open_world_hint = false
@@ -26,48 +27,41 @@ impl RBXStudioServer {
         ctx: RequestContext<RoleServer>,
         Parameters(args): Parameters<SetStudioArgs>,
     ) -> Result<CallToolResult, ErrorData> {
-        let mcp_session_id = Self::extract_mcp_session_id(&ctx);
+        let started = Instant::now();
+        let result = self.select_studio(&ctx, args.studio_id.clone()).await;
+        self.audit_local(&ctx, "set_studio", &args, started, &result)
+            .await;
+        result
+    }
+}
+
+impl RBXStudioServer {
+    async fn select_studio(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        studio_id: Option<String>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let mcp_session_id = self.extract_mcp_session_id(ctx);
         let mut s = self.state.lock().await;
 
-        match args.studio_id {
+        match studio_id {
             None => {
-                let session = s
-                    .sessions
-                    .entry(mcp_session_id)
-                    .or_insert_with(SessionState::new);
-                session.selected_studio_id = None;
+                touch_session(&mut s, &mcp_session_id).selected_studio_id = None;
                 Ok(CallToolResult::success(vec![Content::text(
                     "Studio selection cleared.",
                 )]))
             }
-            Some(id_str) => {
-                let studio_id = id_str.parse::<uuid::Uuid>().map_err(|_| {
-                    ErrorData::invalid_params(format!("Invalid studio_id: {id_str}"), None)
-                })?;
-
-                let conn = match s.connections.get(&studio_id) {
-                    Some(conn) => conn,
-                    None => {
-                        let available: Vec<String> = s
-                            .connections
-                            .iter()
-                            .map(|(id, c)| format!("  {} - {}", id, c.place_name))
-                            .collect();
-                        return Ok(CallToolResult::error(vec![Content::text(format!(
-                            "No studio with studio_id {studio_id}. Call `list_studios` to see available studios.\nConnected:\n{}",
-                            available.join("\n")
-                        ))]));
-                    }
+            Some(query) => {
+                let studio_id = match find_studio(&s, &query) {
+                    Ok(studio_id) => studio_id,
+                    Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
                 };
+                let conn = &s.connections[&studio_id];
 
                 let metadata =
                     serde_json::to_string_pretty(&conn.to_info(studio_id)).unwrap_or_default();
 
-                let session = s
-                    .sessions
-                    .entry(mcp_session_id)
-                    .or_insert_with(SessionState::new);
-                session.selected_studio_id = Some(studio_id);
+                touch_session(&mut s, &mcp_session_id).selected_studio_id = Some(studio_id);
                 Ok(CallToolResult::success(vec![Content::text(metadata)]))
             }
         }
@@ -0,0 +1,83 @@
+use super::prelude::*;
+use crate::resources::StudioResource;
+use crate::selection::find_studio;
+use crate::server_state::resolve_studio_id;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct SetStudioLabelArgs {
+    #[schemars(
+        description = "A studio_id, label, place name or place_id of the studio to label, as for set_studio. Defaults to this session's studio."
+    )]
+    pub studio_id: Option<String>,
+    #[schemars(description = "The new label. Omit to remove the studio's label.")]
+    pub label: Option<String>,
+}
+
+#[tool_router(router = set_studio_label_route, vis = "pub")]
+impl RBXStudioServer {
+    #[doc = include_str!("set_studio_label.md")]
+    #[tool(annotations(
+        // Changes what other sessions and auto_select rules see, though never a place
+        read_only_hint = false,
+        destructive_hint = false,
+        // A repeat can fail once the studio it named by its old label has been relabelled
+        idempotent_hint = false,
+        open_world_hint = false
+    ))]
+    async fn set_studio_label(
+        &self,
+        ctx: RequestContext<RoleServer>,
+        Parameters(args): Parameters<SetStudioLabelArgs>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let started = Instant::now();
+        let result = self.label_studio(&ctx, &args).await;
+        self.audit_local(&ctx, "set_studio_label", &args, started, &result)
+            .await;
+        result
+    }
+}
+
+impl RBXStudioServer {
+    async fn label_studio(
+        &self,
+        ctx: &RequestContext<RoleServer>,
+        args: &SetStudioLabelArgs,
+    ) -> Result<CallToolResult, ErrorData> {
+        let label = args.label.as_deref().map(str::trim);
+        if let Some(label) = label {
+            if label.is_empty() || label.parse::<Uuid>().is_ok() {
+                return Err(ErrorData::invalid_params(
+                    format!("Invalid label \"{label}\": it must be non-empty and not a studio_id"),
+                    None,
+                ));
+            }
+        }
+        let session = self.resolve_session(ctx).await;
+        let mut s = self.state.lock().await;
+        let studio_id = match &args.studio_id {
+            Some(query) => match find_studio(&s, query) {
+                Ok(studio_id) => studio_id,
+                Err(message) => return Ok(CallToolResult::error(vec![Content::text(message)])),
+            },
+            None => match resolve_studio_id(&s, &session) {
+                Ok(studio_id) => studio_id,
+                Err(error_result) => return Ok(error_result),
+            },
+        };
+        if let Some((other, _)) = s.connections.iter().find(|(id, conn)| {
+            **id != studio_id && label.is_some() && conn.label.as_deref() == label
+        }) {
+            return Ok(CallToolResult::error(vec![Content::text(format!(
+                "Studio {other} is already labelled \"{}\". Labels must be unique.",
+                label.unwrap_or_default()
+            ))]));
+        }
+
+        let conn = s.connections.get_mut(&studio_id).unwrap();
+        conn.label = label.map(str::to_string);
+        let metadata = serde_json::to_string_pretty(&conn.to_info(studio_id)).unwrap_or_default();
+        s.notify_resource_updated(studio_id, StudioResource::Info);
+        Ok(CallToolResult::success(vec![Content::text(metadata)]))
+    }
+}
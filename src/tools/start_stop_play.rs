@@ -1,3 +1,4 @@
+use super::get_studio_mode::StudioMode;
 use super::prelude::*;
 
 #[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
@@ -16,10 +17,22 @@ pub struct StartStopPlayArgs {
     pub mode: StartStopPlayMode,
 }
 
+#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct StartStopPlayResult {
+    #[schemars(description = "Studio mode after the call")]
+    pub mode: StudioMode,
+    #[schemars(description = "What happened, e.g. \"Started play\" or \"Already in play mode\"")]
+    pub message: String,
+}
+
+impl StudioResult for StartStopPlayResult {}
+
 #[tool_router(router = start_stop_play_route, vis = "pub")]
 impl RBXStudioServer {
     #[doc = include_str!("start_stop_play.md")]
-    #[tool(annotations(
+    #[tool(
+        output_schema = output_schema::<StartStopPlayResult>(),
+        annotations(
         // Starts/stops play mode, modifying Studio state
         read_only_hint = false,
         // Starting/stopping play does not permanently destroy data
@@ -28,13 +41,14 @@ impl RBXStudioServer {
         idempotent_hint = true,
         // Operates entirely within the Studio session
         open_world_hint = false
-    ))]
+        )
+    )]
     async fn start_stop_play(
         &self,
         ctx: RequestContext<RoleServer>,
         Parameters(args): Parameters<StartStopPlayArgs>,
     ) -> Result<CallToolResult, ErrorData> {
-        self.dispatch_to_studio(&ctx, "start_stop_play", &args)
+        self.dispatch_to_studio::<StartStopPlayResult>(&ctx, "start_stop_play", &args)
             .await
     }
 }
@@ -0,0 +1,326 @@
+//! Framing for the plugin websocket. Every message is a msgpack map, sent either as a binary
+//! frame or base64 text (the format plugins used before negotiation existed). Messages larger
+//! than the negotiated frame size travel as a series of `chunk` messages and are reassembled by
+//! id on the other side.
+
+use crate::error::Result;
+use axum::extract::ws::Message;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const B64: base64::engine::general_purpose::GeneralPurpose =
+    base64::engine::general_purpose::STANDARD;
+
+/// Largest frame the server sends, whatever the plugin asks for.
+const MAX_FRAME_SIZE: usize = 1024 * 1024;
+/// Smallest frame size worth chunking into; leaves room for the chunk envelope.
+const MIN_FRAME_SIZE: usize = 1024;
+/// Bytes reserved in each chunk frame for the fields around `data`.
+const CHUNK_OVERHEAD: usize = 64;
+/// Reassembled messages larger than this are dropped.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Version of the plugin websocket protocol. Bump it whenever a message or tool argument shape
+/// changes incompatibly; Studios whose plugin reports a different version are not sent tools.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Hash of the plugin sources bundled into this binary, computed by `build.rs`.
+pub const PLUGIN_BUILD_HASH: &str = env!("PLUGIN_BUILD_HASH");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameEncoding {
+    Base64,
+    Binary,
+}
+
+/// How messages are framed on one websocket link, agreed during registration.
+#[derive(Clone, Copy, Debug)]
+pub struct WireFormat {
+    pub encoding: FrameEncoding,
+    /// `None` for plugins that cannot reassemble chunks.
+    pub max_frame_size: Option<usize>,
+}
+
+impl WireFormat {
+    /// What plugins that predate negotiation speak. Also used for the registration handshake.
+    pub const LEGACY: Self = Self {
+        encoding: FrameEncoding::Base64,
+        max_frame_size: None,
+    };
+
+    /// Picks binary frames when the plugin offers them. Plugins that offer no encodings predate
+    /// negotiation and get `LEGACY`.
+    pub fn negotiate(offered: &[FrameEncoding], max_frame_size: Option<usize>) -> Self {
+        if offered.is_empty() {
+            return Self::LEGACY;
+        }
+        let encoding = if offered.contains(&FrameEncoding::Binary) {
+            FrameEncoding::Binary
+        } else {
+            FrameEncoding::Base64
+        };
+        let max_frame_size = max_frame_size
+            .unwrap_or(MAX_FRAME_SIZE)
+            .clamp(MIN_FRAME_SIZE, MAX_FRAME_SIZE);
+        Self {
+            encoding,
+            max_frame_size: Some(max_frame_size),
+        }
+    }
+
+    /// Frames `message`, splitting it into chunks if it does not fit in one frame.
+    pub fn frames(&self, message: OutboundMessage) -> Result<Vec<Message>> {
+        let budget = match (self.max_frame_size, self.encoding) {
+            (None, _) => usize::MAX,
+            (Some(size), FrameEncoding::Binary) => size,
+            (Some(size), FrameEncoding::Base64) => size / 4 * 3,
+        };
+        if message.body.len() <= budget {
+            return Ok(vec![self.frame(message.body)]);
+        }
+
+        let id = message.id.unwrap_or_else(Uuid::new_v4);
+        let slices = message.body.chunks(budget - CHUNK_OVERHEAD);
+        let count = slices.len() as u32;
+        slices
+            .enumerate()
+            .map(|(index, data)| {
+                let chunk = ChunkMessage {
+                    msg_type: "chunk".to_string(),
+                    id,
+                    index: index as u32,
+                    count,
+                    data: data.to_vec(),
+                };
+                Ok(self.frame(rmp_serde::to_vec_named(&chunk)?))
+            })
+            .collect()
+    }
+
+    fn frame(&self, body: Vec<u8>) -> Message {
+        match self.encoding {
+            FrameEncoding::Binary => Message::Binary(body.into()),
+            FrameEncoding::Base64 => Message::Text(B64.encode(body).into()),
+        }
+    }
+}
+
+/// Returns the msgpack body of a data frame, or `None` for control frames. Both encodings are
+/// accepted regardless of what was negotiated.
+pub fn unframe(message: &Message) -> Option<Result<Vec<u8>>> {
+    match message {
+        Message::Binary(bytes) => Some(Ok(bytes.to_vec())),
+        Message::Text(text) => Some(B64.decode(text.as_str()).map_err(Into::into)),
+        _ => None,
+    }
+}
+
+/// A msgpack-encoded message waiting to be framed for whichever link is current.
+#[derive(Debug)]
+pub struct OutboundMessage {
+    /// Request id, reused as the chunk id so the plugin can tell which request is arriving.
+    pub id: Option<Uuid>,
+    pub body: Vec<u8>,
+}
+
+impl OutboundMessage {
+    pub fn encode<T: Serialize>(value: &T, id: Option<Uuid>) -> Result<Self> {
+        Ok(Self {
+            id,
+            body: rmp_serde::to_vec_named(value)?,
+        })
+    }
+}
+
+/// One slice of a message too large for a single frame.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChunkMessage {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub id: Uuid,
+    /// 0-based position of this slice.
+    pub index: u32,
+    pub count: u32,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
+pub enum ChunkOutcome {
+    /// More chunks are needed; `received` of `count` have arrived so far.
+    Partial {
+        received: u32,
+        count: u32,
+    },
+    Complete(Vec<u8>),
+}
+
+/// Collects chunks for one websocket link. Chunks of a message arrive in order, but chunks of
+/// different messages may interleave.
+#[derive(Default)]
+pub struct Reassembler {
+    partial: HashMap<Uuid, PartialMessage>,
+}
+
+struct PartialMessage {
+    count: u32,
+    received: u32,
+    body: Vec<u8>,
+}
+
+impl Reassembler {
+    pub fn push(&mut self, chunk: ChunkMessage) -> Result<ChunkOutcome> {
+        let partial = self
+            .partial
+            .entry(chunk.id)
+            .or_insert_with(|| PartialMessage {
+                count: chunk.count,
+                received: 0,
+                body: Vec::new(),
+            });
+        if chunk.index != partial.received || chunk.count != partial.count {
+            self.partial.remove(&chunk.id);
+            return Err(color_eyre::eyre::eyre!(
+                "chunk {}/{} of {} arrived out of order",
+                chunk.index + 1,
+                chunk.count,
+                chunk.id
+            )
+            .into());
+        }
+        if partial.body.len() + chunk.data.len() > MAX_MESSAGE_SIZE {
+            self.partial.remove(&chunk.id);
+            return Err(color_eyre::eyre::eyre!(
+                "message {} exceeds {MAX_MESSAGE_SIZE} bytes",
+                chunk.id
+            )
+            .into());
+        }
+        partial.body.extend_from_slice(&chunk.data);
+        partial.received += 1;
+        if partial.received < partial.count {
+            return Ok(ChunkOutcome::Partial {
+                received: partial.received,
+                count: partial.count,
+            });
+        }
+        let partial = self.partial.remove(&chunk.id).unwrap();
+        Ok(ChunkOutcome::Complete(partial.body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: Uuid, index: u32, count: u32, data: &[u8]) -> ChunkMessage {
+        ChunkMessage {
+            msg_type: "chunk".to_string(),
+            id,
+            index,
+            count,
+            data: data.to_vec(),
+        }
+    }
+
+    fn complete(outcome: ChunkOutcome) -> Vec<u8> {
+        match outcome {
+            ChunkOutcome::Complete(body) => body,
+            ChunkOutcome::Partial { received, count } => {
+                panic!("only {received} of {count} chunks arrived")
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_order_chunks_are_rejected() {
+        let mut reassembler = Reassembler::default();
+        let id = Uuid::new_v4();
+        assert!(matches!(
+            reassembler.push(chunk(id, 0, 3, b"a")).unwrap(),
+            ChunkOutcome::Partial {
+                received: 1,
+                count: 3
+            }
+        ));
+        assert!(reassembler.push(chunk(id, 2, 3, b"c")).is_err());
+
+        // The message was dropped, so it starts over from the first chunk
+        assert!(reassembler.push(chunk(id, 1, 3, b"b")).is_err());
+        reassembler.push(chunk(id, 0, 1, b"a")).unwrap();
+    }
+
+    #[test]
+    fn mismatched_count_is_rejected() {
+        let mut reassembler = Reassembler::default();
+        let id = Uuid::new_v4();
+        reassembler.push(chunk(id, 0, 3, b"a")).unwrap();
+        assert!(reassembler.push(chunk(id, 1, 2, b"b")).is_err());
+        assert!(reassembler.partial.is_empty());
+    }
+
+    #[test]
+    fn interleaved_messages_reassemble_by_id() {
+        let mut reassembler = Reassembler::default();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        reassembler.push(chunk(first, 0, 2, b"hello ")).unwrap();
+        reassembler.push(chunk(second, 0, 2, b"good")).unwrap();
+        assert_eq!(
+            complete(reassembler.push(chunk(second, 1, 2, b"bye")).unwrap()),
+            b"goodbye"
+        );
+        assert_eq!(
+            complete(reassembler.push(chunk(first, 1, 2, b"world")).unwrap()),
+            b"hello world"
+        );
+        assert!(reassembler.partial.is_empty());
+    }
+
+    #[test]
+    fn oversized_messages_are_dropped() {
+        let mut reassembler = Reassembler::default();
+        let id = Uuid::new_v4();
+        let data = vec![0; MAX_MESSAGE_SIZE / 2 + 1];
+        reassembler.push(chunk(id, 0, 2, &data)).unwrap();
+        assert!(reassembler.push(chunk(id, 1, 2, &data)).is_err());
+        assert!(reassembler.partial.is_empty());
+    }
+
+    #[test]
+    fn base64_chunks_fit_the_smallest_frame_and_round_trip() {
+        let format = WireFormat::negotiate(&[FrameEncoding::Base64], Some(0));
+        assert_eq!(format.max_frame_size, Some(MIN_FRAME_SIZE));
+
+        let body: Vec<u8> = (0..10 * MIN_FRAME_SIZE).map(|i| i as u8).collect();
+        let id = Uuid::new_v4();
+        let frames = format
+            .frames(OutboundMessage {
+                id: Some(id),
+                body: body.clone(),
+            })
+            .unwrap();
+        assert!(frames.len() > 1);
+
+        let mut reassembler = Reassembler::default();
+        let mut reassembled = None;
+        for frame in &frames {
+            let Message::Text(text) = frame else {
+                panic!("expected a base64 text frame, got {frame:?}");
+            };
+            assert!(
+                text.len() <= MIN_FRAME_SIZE,
+                "frame of {} bytes",
+                text.len()
+            );
+            let chunk: ChunkMessage =
+                rmp_serde::from_slice(&unframe(frame).unwrap().unwrap()).unwrap();
+            assert_eq!(chunk.id, id);
+            if let ChunkOutcome::Complete(done) = reassembler.push(chunk).unwrap() {
+                reassembled = Some(done);
+            }
+        }
+        assert_eq!(reassembled, Some(body));
+    }
+}
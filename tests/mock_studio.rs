@@ -0,0 +1,1496 @@
+//! Drives the server binary end to end: a mock Studio on `/ws` and an MCP client on `/mcp`.
+
+use rbx_studio_mcp::mock_studio::{MockEvent, MockStudio, MockStudioConfig, MockTool, Reply};
+use rmcp::model::{
+    CallToolRequestParams, CallToolResult, ClientInfo, CreateElicitationRequestParams,
+    CreateElicitationResult, ElicitationAction, ElicitationCapability, FormElicitationCapability,
+    Meta, NumberOrString, ProgressNotificationParam, ProgressToken, ReadResourceRequestParams,
+    ResourceContents, ResourceUpdatedNotificationParam, SubscribeRequestParams,
+};
+use rmcp::service::{NotificationContext, Peer, RequestContext, RunningService};
+use rmcp::transport::streamable_http_client::StreamableHttpClientTransportConfig;
+use rmcp::transport::StreamableHttpClientTransport;
+use rmcp::{ClientHandler, RoleClient, ServiceExt};
+use serde_json::json;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+const EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The server binary running on its own port with its own config directory.
+struct TestServer {
+    child: Child,
+    port: u16,
+    home: PathBuf,
+    token: String,
+}
+
+impl TestServer {
+    async fn start(args: &[&str]) -> Self {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let home =
+            std::env::temp_dir().join(format!("rbx-studio-mcp-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&home).unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_rbx-studio-mcp"))
+            .arg("--plugin-port")
+            .arg(port.to_string())
+            .args(args)
+            .env_clear()
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join("config"))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let mut server = Self {
+            child,
+            port,
+            home,
+            token: String::new(),
+        };
+
+        let token_path = server.home.join("config/rbx-studio-mcp/auth_token");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let listening = tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .is_ok();
+            if listening {
+                if let Ok(token) = std::fs::read_to_string(&token_path) {
+                    server.token = token.trim().to_string();
+                    return server;
+                }
+            }
+            assert!(Instant::now() < deadline, "the server did not start");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    fn studio_config(&self) -> MockStudioConfig {
+        MockStudioConfig::new(format!("ws://127.0.0.1:{}/ws", self.port), &self.token)
+    }
+
+    async fn studio(&self) -> MockStudio {
+        MockStudio::connect(self.studio_config()).await.unwrap()
+    }
+
+    async fn client(&self) -> RunningService<RoleClient, ()> {
+        self.client_with(()).await
+    }
+
+    async fn client_with<H: ClientHandler>(&self, handler: H) -> RunningService<RoleClient, H> {
+        let config = StreamableHttpClientTransportConfig::with_uri(format!(
+            "http://127.0.0.1:{}/mcp",
+            self.port
+        ))
+        .auth_header(self.token.clone());
+        handler
+            .serve(StreamableHttpClientTransport::from_config(config))
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.home);
+    }
+}
+
+/// Passes on the notifications the server sends: resource notifications as their URI or
+/// `list_changed`, progress as its message.
+struct Notifications(mpsc::UnboundedSender<String>);
+
+impl ClientHandler for Notifications {
+    async fn on_resource_updated(
+        &self,
+        params: ResourceUpdatedNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        let _ = self.0.send(params.uri);
+    }
+
+    async fn on_resource_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        let _ = self.0.send("list_changed".to_string());
+    }
+
+    async fn on_progress(
+        &self,
+        params: ProgressNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        let _ = self.0.send(params.message.unwrap_or_default());
+    }
+}
+
+/// A client whose user answers approval prompts: `Some` fills in the form, `None` declines it.
+struct Approver(Option<bool>);
+
+impl ClientHandler for Approver {
+    async fn create_elicitation(
+        &self,
+        _request: CreateElicitationRequestParams,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<CreateElicitationResult, rmcp::ErrorData> {
+        Ok(CreateElicitationResult {
+            action: match self.0 {
+                Some(_) => ElicitationAction::Accept,
+                None => ElicitationAction::Decline,
+            },
+            content: self.0.map(|approve| json!({ "approve": approve })),
+        })
+    }
+
+    fn get_info(&self) -> ClientInfo {
+        let mut info = ClientInfo::default();
+        info.capabilities.elicitation = Some(ElicitationCapability {
+            form: Some(FormElicitationCapability::default()),
+            ..Default::default()
+        });
+        info
+    }
+}
+
+async fn call(client: &Peer<RoleClient>, tool: &str, args: serde_json::Value) -> CallToolResult {
+    client
+        .call_tool(CallToolRequestParams {
+            meta: None,
+            name: tool.to_string().into(),
+            arguments: args.as_object().cloned(),
+            task: None,
+        })
+        .await
+        .unwrap()
+}
+
+async fn read(client: &Peer<RoleClient>, uri: &str) -> String {
+    let result = client
+        .read_resource(ReadResourceRequestParams {
+            meta: None,
+            uri: uri.to_string(),
+        })
+        .await
+        .unwrap();
+    match result.contents.into_iter().next() {
+        Some(ResourceContents::TextResourceContents { text, .. }) => text,
+        other => panic!("expected text contents, got {other:?}"),
+    }
+}
+
+fn text(result: &CallToolResult) -> &str {
+    result
+        .content
+        .first()
+        .and_then(|content| content.as_text())
+        .map(|content| content.text.as_str())
+        .unwrap_or_default()
+}
+
+/// Calls `get_console_output` until the console holds `count` entries, since streamed entries
+/// reach the server independently of MCP requests.
+async fn wait_for_console(client: &Peer<RoleClient>, count: usize) {
+    let deadline = Instant::now() + EVENT_TIMEOUT;
+    loop {
+        let result = call(client, "get_console_output", json!({})).await;
+        let entries = result.structured_content.unwrap()["entries"]
+            .as_array()
+            .unwrap()
+            .len();
+        if entries >= count {
+            return;
+        }
+        assert!(Instant::now() < deadline, "console has {entries} entries");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+async fn studio_count(client: &Peer<RoleClient>) -> usize {
+    let result = call(client, "list_studios", json!({})).await;
+    result.structured_content.unwrap()["studios"]
+        .as_array()
+        .unwrap()
+        .len()
+}
+
+#[tokio::test]
+async fn run_code_round_trip() {
+    let server = TestServer::start(&[]).await;
+    let mut studio = server.studio().await;
+    let response =
+        json!({ "logs": [{ "level": "output", "message": "hi" }], "returned": [2], "error": null });
+    studio.on("run_code", Reply::Success(response.clone()));
+    let client = server.client().await;
+
+    let result = call(
+        &client,
+        "run_code",
+        json!({ "command": "print('hi') return 2" }),
+    )
+    .await;
+    assert_eq!(result.is_error, Some(false));
+    assert_eq!(result.structured_content, Some(response));
+    match studio.next_event(EVENT_TIMEOUT).await {
+        Some(MockEvent::Call { tool, args, .. }) => {
+            assert_eq!(tool, "run_code");
+            assert_eq!(args, json!({ "command": "print('hi') return 2" }));
+        }
+        other => panic!("expected the call, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn stdio_instances_proxy_to_the_running_server() {
+    let server = TestServer::start(&[]).await;
+    let _studio = server.studio().await;
+    // A second instance finds the port taken and relays stdio to the first
+    let mut proxy = tokio::process::Command::new(env!("CARGO_BIN_EXE_rbx-studio-mcp"))
+        .arg("--plugin-port")
+        .arg(server.port.to_string())
+        .arg("--stdio")
+        .env_clear()
+        .env("HOME", &server.home)
+        .env("XDG_CONFIG_HOME", server.home.join("config"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let transport = (proxy.stdout.take().unwrap(), proxy.stdin.take().unwrap());
+    let client = ().serve(transport).await.unwrap();
+
+    let tools = client.list_all_tools().await.unwrap();
+    assert!(tools.iter().any(|tool| tool.name == "run_code"));
+    assert_eq!(studio_count(&client).await, 1);
+}
+
+#[tokio::test]
+async fn studio_errors_become_tool_errors() {
+    let server = TestServer::start(&[]).await;
+    let studio = server.studio().await;
+    studio.on(
+        "run_code",
+        Reply::Failure("attempt to index nil".to_string()),
+    );
+    let client = server.client().await;
+
+    let result = call(&client, "run_code", json!({ "command": "x.y = 1" })).await;
+    assert_eq!(result.is_error, Some(true));
+    assert!(text(&result).contains("attempt to index nil"));
+
+    // Code that threw is an error too, with its output kept
+    let output = json!({
+        "logs": [{ "level": "output", "message": "before" }],
+        "returned": [],
+        "error": "attempt to call a nil value",
+    });
+    studio.on("run_code", Reply::Success(output.clone()));
+    let result = call(
+        &client,
+        "run_code",
+        json!({ "command": "print('before') x()" }),
+    )
+    .await;
+    assert_eq!(result.is_error, Some(true));
+    assert_eq!(result.structured_content, Some(output));
+}
+
+#[tokio::test]
+async fn slow_replies_time_out_and_are_cancelled() {
+    let server = TestServer::start(&["--tool-timeout", "run_code=1"]).await;
+    let mut studio = server.studio().await;
+    studio.on(
+        "run_code",
+        Reply::Delayed(Duration::from_secs(5), Box::new(Reply::Success(json!({})))),
+    );
+    let client = server.client().await;
+
+    let result = call(&client, "run_code", json!({ "command": "task.wait(5)" })).await;
+    assert!(text(&result).starts_with("Timed out"), "{}", text(&result));
+    let Some(MockEvent::Call { id, .. }) = studio.next_event(EVENT_TIMEOUT).await else {
+        panic!("expected the call");
+    };
+    assert_eq!(
+        studio.next_event(EVENT_TIMEOUT).await,
+        Some(MockEvent::Cancel { id })
+    );
+}
+
+#[tokio::test]
+async fn disconnects_fail_pending_calls() {
+    let server = TestServer::start(&["--reconnect-grace", "0"]).await;
+    let studio = server.studio().await;
+    studio.on("run_code", Reply::Disconnect);
+    let client = server.client().await;
+
+    let result = call(&client, "run_code", json!({ "command": "" })).await;
+    assert_eq!(result.is_error, Some(true));
+    assert!(
+        text(&result).contains("Studio disconnected"),
+        "{}",
+        text(&result)
+    );
+    assert_eq!(studio_count(&client).await, 0);
+}
+
+#[tokio::test]
+async fn reconnecting_studio_keeps_its_id() {
+    let server = TestServer::start(&[]).await;
+    let mut studio = server.studio().await;
+    studio.on("get_studio_mode", Reply::Success(json!({ "mode": "stop" })));
+    let client = server.client().await;
+    let studio_id = studio.studio_id;
+
+    studio.disconnect();
+    studio.reconnect().await.unwrap();
+    assert_eq!(studio.studio_id, studio_id);
+    assert_eq!(studio_count(&client).await, 1);
+    let result = call(&client, "get_studio_mode", json!({})).await;
+    assert_eq!(result.structured_content, Some(json!({ "mode": "stop" })));
+}
+
+#[tokio::test]
+async fn requests_queued_while_disconnected_reach_the_returning_studio() {
+    let server = TestServer::start(&[]).await;
+    let mut studio = server.studio().await;
+    studio.on("run_code", Reply::Ignore);
+    studio.on("get_studio_mode", Reply::Success(json!({ "mode": "stop" })));
+    let client = server.client().await;
+
+    let in_flight = {
+        let client = client.clone();
+        tokio::spawn(async move { call(&client, "run_code", json!({ "command": "" })).await })
+    };
+    let Some(MockEvent::Call { .. }) = studio.next_event(EVENT_TIMEOUT).await else {
+        panic!("expected the call");
+    };
+    // The answer to a request already sent is lost with the link
+    studio.disconnect();
+    let result = tokio::time::timeout(EVENT_TIMEOUT, in_flight)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.is_error, Some(true));
+    assert!(text(&result).contains("disconnected"), "{}", text(&result));
+
+    // A request made during the grace window waits for the Studio to come back
+    let queued = {
+        let client = client.clone();
+        tokio::spawn(async move { call(&client, "get_studio_mode", json!({})).await })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    studio.reconnect().await.unwrap();
+    let result = tokio::time::timeout(EVENT_TIMEOUT, queued)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.structured_content, Some(json!({ "mode": "stop" })));
+}
+
+#[tokio::test]
+async fn requests_in_flight_fail_when_a_reconnect_takes_over() {
+    let server = TestServer::start(&[]).await;
+    let config = server.studio_config();
+    let mut studio = MockStudio::connect(config.clone()).await.unwrap();
+    studio.on("run_code", Reply::Ignore);
+    let client = server.client().await;
+
+    let pending = {
+        let client = client.clone();
+        tokio::spawn(async move { call(&client, "run_code", json!({ "command": "" })).await })
+    };
+    let Some(MockEvent::Call { .. }) = studio.next_event(EVENT_TIMEOUT).await else {
+        panic!("expected the call");
+    };
+    // The old socket is still open when the Studio registers again
+    let returning = MockStudio::connect(config).await.unwrap();
+    assert_eq!(returning.studio_id, studio.studio_id);
+    let result = tokio::time::timeout(EVENT_TIMEOUT, pending)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        text(&result).contains("reconnected before answering"),
+        "{}",
+        text(&result)
+    );
+    // Later requests go to the new link, never the replaced one
+    returning.on("get_studio_mode", Reply::Success(json!({ "mode": "stop" })));
+    let result = call(&client, "get_studio_mode", json!({})).await;
+    assert_eq!(result.structured_content, Some(json!({ "mode": "stop" })));
+}
+
+#[tokio::test]
+async fn malformed_frames_are_ignored() {
+    let server = TestServer::start(&["--tool-timeout", "run_code=1"]).await;
+    let studio = server.studio().await;
+    studio.send_raw(Message::Text("not base64!".into()));
+    studio.send_raw(Message::Binary(vec![0xc1, 0xc1].into()));
+    studio.once("run_code", Reply::Malformed);
+    studio.on(
+        "run_code",
+        Reply::Success(json!({ "logs": [], "returned": [], "error": null })),
+    );
+    let client = server.client().await;
+
+    let result = call(&client, "run_code", json!({ "command": "" })).await;
+    assert!(text(&result).starts_with("Timed out"), "{}", text(&result));
+    let result = call(&client, "run_code", json!({ "command": "" })).await;
+    assert_eq!(result.is_error, Some(false));
+}
+
+#[tokio::test]
+async fn large_messages_are_chunked_both_ways() {
+    let server = TestServer::start(&[]).await;
+    let mut config = server.studio_config();
+    config.max_frame_size = Some(4096);
+    let mut studio = MockStudio::connect(config).await.unwrap();
+    let logs: Vec<_> = (0..500)
+        .map(|i| json!({ "level": "output", "message": format!("line {i}") }))
+        .collect();
+    let response = json!({ "logs": logs, "returned": [], "error": null });
+    studio.on("run_code", Reply::Success(response.clone()));
+    let client = server.client().await;
+
+    let command = "print('x')\n".repeat(2000);
+    let result = call(&client, "run_code", json!({ "command": command })).await;
+    assert_eq!(result.structured_content, Some(response));
+    let Some(MockEvent::Call { args, .. }) = studio.next_event(EVENT_TIMEOUT).await else {
+        panic!("expected the call");
+    };
+    assert_eq!(args["command"], json!(command));
+}
+
+#[tokio::test]
+async fn unresponsive_studios_are_evicted() {
+    let server = TestServer::start(&[
+        "--heartbeat-interval",
+        "0.2",
+        "--heartbeat-max-missed",
+        "2",
+        "--reconnect-grace",
+        "0",
+    ])
+    .await;
+    let studio = server.studio().await;
+    let client = server.client().await;
+    assert_eq!(studio_count(&client).await, 1);
+
+    studio.answer_pings(false);
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert_eq!(studio_count(&client).await, 0);
+}
+
+#[tokio::test]
+async fn recorded_responses_are_replayed_in_order() {
+    let server = TestServer::start(&[]).await;
+    let studio = server.studio().await;
+    let log = server.home.join("recorded.jsonl");
+    let records = [
+        json!({ "tool": "get_studio_mode", "success": true, "response": "{\"mode\":\"stop\"}" }),
+        json!({ "tool": "run_code", "success": false, "response": "[{\"type\":\"text\",\"text\":\"boom\"}]" }),
+        json!({ "tool": "get_studio_mode", "success": true, "response": "{\"mode\":\"start_play\"}" }),
+    ];
+    let lines: Vec<String> = records.iter().map(|record| record.to_string()).collect();
+    std::fs::write(&log, lines.join("\n")).unwrap();
+    assert_eq!(studio.load_recorded(&log).unwrap(), 3);
+    let client = server.client().await;
+
+    let first = call(&client, "get_studio_mode", json!({})).await;
+    assert_eq!(first.structured_content, Some(json!({ "mode": "stop" })));
+    let failed = call(&client, "run_code", json!({ "command": "" })).await;
+    assert_eq!(text(&failed), "boom");
+    let second = call(&client, "get_studio_mode", json!({})).await;
+    assert_eq!(
+        second.structured_content,
+        Some(json!({ "mode": "start_play" }))
+    );
+}
+
+#[tokio::test]
+async fn replay_runs_only_calls_that_reached_studio_from_the_audit_log() {
+    let server = TestServer::start(&[]).await;
+    let studio = server.studio().await;
+    studio.on("get_studio_mode", Reply::Success(json!({ "mode": "stop" })));
+    let client = server.client().await;
+
+    call(&client, "list_studios", json!({})).await;
+    call(&client, "set_studio_label", json!({ "label": "main" })).await;
+    call(&client, "list_pending_requests", json!({})).await;
+    call(&client, "get_studio_mode", json!({})).await;
+
+    let result = call(&client, "replay", json!({ "dry_run": true })).await;
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    let report = result.structured_content.unwrap();
+    let tools: Vec<&str> = report["calls"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|call| call["tool"].as_str().unwrap())
+        .collect();
+    assert_eq!(tools, ["get_studio_mode"]);
+
+    let outside = server.home.join("secret.jsonl");
+    std::fs::write(&outside, "not an audit record").unwrap();
+    let error = client
+        .call_tool(CallToolRequestParams {
+            meta: None,
+            name: "replay".into(),
+            arguments: json!({ "log": outside.to_string_lossy(), "dry_run": true })
+                .as_object()
+                .cloned(),
+            task: None,
+        })
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("rotated copies") && !error.contains("not an audit record"),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn replays_judge_each_tool_by_its_own_result() {
+    let server = TestServer::start(&[]).await;
+    let studio = server.studio().await;
+    studio.on("get_studio_mode", Reply::Success(json!({ "mode": "stop" })));
+    let ran = json!({ "logs": [], "returned": [1], "error": null });
+    studio.once("run_code", Reply::Success(ran));
+    let client = server.client().await;
+    call(&client, "run_code", json!({ "command": "return 1" })).await;
+    call(&client, "get_studio_mode", json!({})).await;
+
+    // This time the code throws, which Studio still reports as a successful round trip
+    let threw = json!({ "logs": [], "returned": [], "error": "boom" });
+    studio.once("run_code", Reply::Success(threw));
+    let args = json!({ "stop_on_error": true, "diff": true });
+    let result = call(&client, "replay", args).await;
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    let report = result.structured_content.unwrap();
+    let calls = report["calls"].as_array().unwrap();
+    assert_eq!(calls.len(), 1, "{report}");
+    assert_eq!(calls[0]["tool"], "run_code");
+    assert_eq!(calls[0]["recorded_success"], true);
+    assert_eq!(calls[0]["success"], false);
+    assert_eq!(calls[0]["matches"], false);
+    assert_eq!(report["stopped"], true);
+}
+
+#[tokio::test]
+async fn audit_logs_rotate_and_rotated_copies_can_be_replayed() {
+    // Every record overflows one byte, so each lands in a fresh file
+    let server = TestServer::start(&["--audit-max-bytes", "1", "--audit-keep", "2"]).await;
+    let studio = server.studio().await;
+    studio.on(
+        "run_code",
+        Reply::Success(json!({ "logs": [], "returned": [] })),
+    );
+    let client = server.client().await;
+    let dir = server.home.join("config/rbx-studio-mcp");
+    let command_in = |name: &str| -> Option<String> {
+        let text = std::fs::read_to_string(dir.join(name)).ok()?;
+        assert_eq!(text.lines().count(), 1, "{name}: {text}");
+        let record: serde_json::Value = serde_json::from_str(&text).unwrap();
+        Some(record["arguments"]["command"].as_str()?.to_string())
+    };
+    let replayed = |log: String| {
+        let client = client.clone();
+        async move {
+            let result = call(&client, "replay", json!({ "log": log, "dry_run": true })).await;
+            assert_eq!(result.is_error, Some(false), "{}", text(&result));
+            let report = result.structured_content.unwrap();
+            report["calls"][0]["arguments"]["command"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        }
+    };
+
+    for n in 1..=4 {
+        call(
+            &client,
+            "run_code",
+            json!({ "command": format!("return {n}") }),
+        )
+        .await;
+    }
+    assert_eq!(command_in("audit.jsonl").as_deref(), Some("return 4"));
+    assert_eq!(command_in("audit.1.jsonl").as_deref(), Some("return 3"));
+    assert_eq!(command_in("audit.2.jsonl").as_deref(), Some("return 2"));
+    assert!(!dir.join("audit.3.jsonl").exists());
+
+    assert_eq!(replayed("audit.2.jsonl".to_string()).await, "return 2");
+    // The replay's own record pushed the others along
+    let path = dir.join("audit.2.jsonl").to_string_lossy().into_owned();
+    assert_eq!(replayed(path).await, "return 3");
+    let error = client
+        .call_tool(CallToolRequestParams {
+            meta: None,
+            name: "replay".into(),
+            arguments: json!({ "log": "audit.3.jsonl", "dry_run": true })
+                .as_object()
+                .cloned(),
+            task: None,
+        })
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("rotated copies"), "{error}");
+}
+
+#[tokio::test]
+async fn plugin_tools_are_listed_and_called() {
+    let server = TestServer::start(&[]).await;
+    let mut config = server.studio_config();
+    config.custom_tools.push(MockTool {
+        name: "count_parts".to_string(),
+        description: Some("Counts the parts in the workspace".to_string()),
+        input_schema: json!({ "type": "object", "properties": {} }),
+    });
+    let studio = MockStudio::connect(config).await.unwrap();
+    studio.on("count_parts", Reply::Success(json!(42)));
+    let client = server.client().await;
+
+    let tools = client.list_all_tools().await.unwrap();
+    assert!(tools.iter().any(|tool| tool.name == "count_parts"));
+    let result = call(&client, "count_parts", json!({})).await;
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    assert_eq!(text(&result), "42");
+}
+
+#[tokio::test]
+async fn studios_are_listed_as_resources() {
+    let server = TestServer::start(&[]).await;
+    let studio = server.studio().await;
+    studio.on("get_studio_mode", Reply::Success(json!({ "mode": "stop" })));
+    studio.console(&[("output", "Hello"), ("warning", "world!")], 0);
+    let client = server.client().await;
+    wait_for_console(&client, 2).await;
+    let base = format!("studio://{}", studio.studio_id);
+
+    let uris: Vec<String> = client
+        .list_all_resources()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|resource| resource.raw.uri)
+        .collect();
+    assert_eq!(
+        uris,
+        [
+            format!("{base}/info"),
+            format!("{base}/console"),
+            format!("{base}/mode")
+        ]
+    );
+    let info: serde_json::Value = serde_json::from_str(&read(&client, &uris[0]).await).unwrap();
+    assert_eq!(info["studio_id"], json!(studio.studio_id.to_string()));
+    assert_eq!(read(&client, &uris[1]).await, "Hello\nworld!");
+    let mode: serde_json::Value = serde_json::from_str(&read(&client, &uris[2]).await).unwrap();
+    assert_eq!(mode, json!({ "mode": "stop" }));
+}
+
+#[tokio::test]
+async fn subscribers_hear_about_console_output_and_disconnects() {
+    let server = TestServer::start(&["--reconnect-grace", "0"]).await;
+    let mut studio = server.studio().await;
+    let (tx, mut notifications) = mpsc::unbounded_channel();
+    let client = server.client_with(Notifications(tx)).await;
+    let base = format!("studio://{}", studio.studio_id);
+    for resource in ["info", "console"] {
+        client
+            .subscribe(SubscribeRequestParams {
+                meta: None,
+                uri: format!("{base}/{resource}"),
+            })
+            .await
+            .unwrap();
+    }
+    let mut next = async || {
+        tokio::time::timeout(EVENT_TIMEOUT, notifications.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    };
+
+    studio.console(&[("output", "Hello world!")], 0);
+    assert_eq!(next().await, format!("{base}/console"));
+    studio.disconnect();
+    let mut seen = vec![next().await, next().await, next().await];
+    seen.sort();
+    assert_eq!(
+        seen,
+        [
+            "list_changed".to_string(),
+            format!("{base}/console"),
+            format!("{base}/info")
+        ]
+    );
+}
+
+#[tokio::test]
+async fn console_output_is_filtered_and_paged() {
+    let server = TestServer::start(&["--console-buffer", "4"]).await;
+    let studio = server.studio().await;
+    let client = server.client().await;
+    studio.console(&[("output", "one"), ("warning", "two")], 0);
+    // Three entries are lost in the plugin and `one` is evicted from the buffer
+    studio.console(
+        &[("error", "three"), ("output", "four"), ("output", "five")],
+        3,
+    );
+    wait_for_console(&client, 4).await;
+    let console = async |args: serde_json::Value| {
+        let result = call(&client, "get_console_output", args).await;
+        assert_eq!(result.is_error, Some(false), "{}", text(&result));
+        result.structured_content.unwrap()
+    };
+    let messages = |page: &serde_json::Value| -> Vec<String> {
+        page["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["message"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let latest = console(json!({ "limit": 2 })).await;
+    assert_eq!(messages(&latest), ["four", "five"]);
+    assert_eq!(latest["next_cursor"], json!(8));
+
+    let page = console(json!({ "cursor": 0, "limit": 2 })).await;
+    assert_eq!(messages(&page), ["two", "three"]);
+    assert_eq!(page["entries"][1]["seq"], json!(5));
+    assert_eq!(page["missed"], json!(1));
+    // A zero limit still returns an entry rather than skipping past it
+    let page = console(json!({ "cursor": page["next_cursor"], "limit": 0 })).await;
+    assert_eq!(messages(&page), ["four"]);
+    let page = console(json!({ "cursor": page["next_cursor"], "limit": 2 })).await;
+    assert_eq!(messages(&page), ["five"]);
+    let page = console(json!({ "cursor": page["next_cursor"] })).await;
+    assert!(messages(&page).is_empty());
+    assert_eq!(page["next_cursor"], json!(8));
+
+    let errors = console(json!({ "levels": ["warning", "error"] })).await;
+    assert_eq!(messages(&errors), ["two", "three"]);
+    let matched = console(json!({ "pattern": "^f" })).await;
+    assert_eq!(messages(&matched), ["four", "five"]);
+    let invalid = call(&client, "get_console_output", json!({ "pattern": "(" })).await;
+    assert_eq!(invalid.is_error, Some(true));
+}
+
+#[tokio::test]
+async fn older_plugins_do_not_stream_console_output() {
+    let server = TestServer::start(&[]).await;
+    let mut config = server.studio_config();
+    config.events.clear();
+    let _studio = MockStudio::connect(config).await.unwrap();
+    let client = server.client().await;
+
+    let result = call(&client, "get_console_output", json!({})).await;
+    assert_eq!(result.is_error, Some(true));
+    assert!(
+        text(&result).contains("update the plugin"),
+        "{}",
+        text(&result)
+    );
+}
+
+#[tokio::test]
+async fn plugin_progress_is_relayed_as_notifications() {
+    let server = TestServer::start(&[]).await;
+    let studio = server.studio().await;
+    let messages = [
+        "Script injected",
+        "Play started",
+        "[output] Hello world!",
+        "Stopping play mode",
+    ];
+    let result = json!({
+        "success": true,
+        "value": "nil",
+        "error": null,
+        "logs": [{ "level": "output", "message": "Hello world!", "ts": 0.5 }],
+        "errors": [],
+        "duration": 0.5,
+        "is_timeout": false,
+    });
+    studio.on(
+        "run_script_in_play_mode",
+        Reply::Progress(
+            messages.map(String::from).to_vec(),
+            Box::new(Reply::Delayed(
+                Duration::from_millis(200),
+                Box::new(Reply::Success(result)),
+            )),
+        ),
+    );
+    let (tx, mut notifications) = mpsc::unbounded_channel();
+    let client = server.client_with(Notifications(tx)).await;
+    let args = json!({ "code": "print('Hello world!')", "mode": "start_play" });
+
+    let result = client
+        .call_tool(CallToolRequestParams {
+            meta: Some(Meta::with_progress_token(ProgressToken(
+                NumberOrString::Number(1),
+            ))),
+            name: "run_script_in_play_mode".into(),
+            arguments: args.as_object().cloned(),
+            task: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    for message in messages {
+        let received = tokio::time::timeout(EVENT_TIMEOUT, notifications.recv()).await;
+        assert_eq!(received.unwrap().unwrap(), message);
+    }
+}
+
+#[tokio::test]
+async fn metrics_count_tool_calls_and_connections() {
+    let server = TestServer::start(&[]).await;
+    let mut studio = server.studio().await;
+    studio.on("get_studio_mode", Reply::Success(json!({ "mode": "stop" })));
+    studio.on("run_code", Reply::Failure("boom".to_string()));
+    let client = server.client().await;
+    call(&client, "get_studio_mode", json!({})).await;
+    call(&client, "run_code", json!({ "command": "error('boom')" })).await;
+    studio.disconnect();
+    studio.reconnect().await.unwrap();
+
+    let url = format!("http://127.0.0.1:{}/metrics", server.port);
+    let unauthorized = reqwest::get(&url).await.unwrap();
+    assert_eq!(unauthorized.status(), 401);
+    // Only the plugin's websocket may put the token in the URL
+    let in_query = reqwest::get(format!("{url}?token={}", server.token))
+        .await
+        .unwrap();
+    assert_eq!(in_query.status(), 401);
+    let metrics = reqwest::Client::new()
+        .get(&url)
+        .bearer_auth(&server.token)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    for line in [
+        r#"rbx_mcp_tool_calls_total{tool="get_studio_mode",place_id="1818"} 1"#,
+        r#"rbx_mcp_tool_errors_total{tool="get_studio_mode",place_id="1818"} 0"#,
+        r#"rbx_mcp_tool_errors_total{tool="run_code",place_id="1818"} 1"#,
+        r#"rbx_mcp_tool_call_duration_seconds_count{tool="run_code",place_id="1818"} 1"#,
+        "rbx_mcp_studio_connections_total 1",
+        "rbx_mcp_studio_reconnects_total 1",
+        r#"rbx_mcp_studios{status="connected"} 1"#,
+        "rbx_mcp_pending_requests 0",
+    ] {
+        assert!(metrics.lines().any(|l| l == line), "{line} in:\n{metrics}");
+    }
+}
+
+#[tokio::test]
+async fn requests_without_valid_credentials_are_rejected() {
+    let server = TestServer::start(&["--allowed-origin", "http://localhost:6274"]).await;
+    let mcp = format!("http://127.0.0.1:{}/mcp", server.port);
+    let http = reqwest::Client::new();
+    let status = |request: reqwest::RequestBuilder| async move {
+        request.send().await.unwrap().status().as_u16()
+    };
+
+    assert_eq!(status(http.post(&mcp)).await, 401);
+    assert_eq!(status(http.post(&mcp).bearer_auth("wrong")).await, 401);
+    // Only the plugin's websocket may put the token in the URL
+    let in_query = format!("{mcp}?token={}", server.token);
+    assert_eq!(status(http.post(&in_query)).await, 401);
+    let elsewhere = format!(
+        "http://127.0.0.1:{}/metrics?token={}",
+        server.port, server.token
+    );
+    assert_eq!(status(http.get(&elsewhere)).await, 401);
+
+    // A valid token does not help a foreign host or a disallowed browser origin
+    let foreign_host = http
+        .post(&mcp)
+        .bearer_auth(&server.token)
+        .header("Host", format!("attacker.example:{}", server.port));
+    assert_eq!(status(foreign_host).await, 403);
+    let foreign_origin = http
+        .post(&mcp)
+        .bearer_auth(&server.token)
+        .header("Origin", "http://attacker.example");
+    assert_eq!(status(foreign_origin).await, 403);
+    let allowed_origin = http
+        .post(&mcp)
+        .bearer_auth(&server.token)
+        .header("Origin", "http://localhost:6274");
+    assert!(![401, 403].contains(&status(allowed_origin).await));
+
+    let wrong_token = MockStudioConfig::new(format!("ws://127.0.0.1:{}/ws", server.port), "wrong");
+    assert!(MockStudio::connect(wrong_token).await.is_err());
+    server.studio().await;
+}
+
+#[tokio::test]
+async fn run_code_multi_fans_out_by_place() {
+    let server = TestServer::start(&[]).await;
+    let first = server.studio().await;
+    let second = server.studio().await;
+    let mut config = server.studio_config();
+    config.place_id = 7;
+    config.game_id = 2;
+    let other = MockStudio::connect(config).await.unwrap();
+    let output = json!({ "logs": [], "returned": [1], "error": null });
+    first.on("run_code", Reply::Success(output.clone()));
+    second.on("run_code", Reply::Failure("Studio is busy".to_string()));
+    other.on("run_code", Reply::Success(output.clone()));
+    let client = server.client().await;
+
+    let result = call(
+        &client,
+        "run_code_multi",
+        json!({ "command": "return 1", "place_id": 1818 }),
+    )
+    .await;
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    let results = result.structured_content.unwrap()["results"].clone();
+    assert_eq!(
+        results,
+        json!([
+            {
+                "studio_id": first.studio_id.to_string(),
+                "place_id": 1818,
+                "place_name": "Mock Place",
+                "success": true,
+                "result": output,
+                "error": null,
+            },
+            {
+                "studio_id": second.studio_id.to_string(),
+                "place_id": 1818,
+                "place_name": "Mock Place",
+                "success": false,
+                "result": null,
+                "error": "Studio is busy",
+            },
+        ])
+    );
+
+    let result = call(
+        &client,
+        "run_code_multi",
+        json!({ "command": "return 1", "all": true }),
+    )
+    .await;
+    assert_eq!(
+        result.structured_content.unwrap()["results"]
+            .as_array()
+            .unwrap()
+            .len(),
+        3
+    );
+    let result = call(
+        &client,
+        "run_code_multi",
+        json!({ "command": "return 1", "place_id": 7, "game_id": 2 }),
+    )
+    .await;
+    assert_eq!(result.is_error, Some(true));
+}
+
+#[tokio::test]
+async fn run_code_multi_asks_for_approval_once() {
+    let config_path =
+        std::env::temp_dir().join(format!("rbx-studio-mcp-test-{}.toml", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &config_path,
+        "[approval]\nrequire = [\"run_code\"]\n\n[[policy.rules]]\nplace_id = 7\ndeny = [\"run_code\"]\n",
+    )
+    .unwrap();
+    let config_arg = config_path.to_str().unwrap();
+    let server = TestServer::start(&["--config", config_arg, "--audit", "true"]).await;
+    let first = server.studio().await;
+    let mut config = server.studio_config();
+    config.place_id = 7;
+    let denied = MockStudio::connect(config).await.unwrap();
+    let output = json!({ "logs": [], "returned": [1], "error": null });
+    first.on("run_code", Reply::Success(output));
+    let client = server.client().await;
+    let http = reqwest::Client::new();
+    let approvals = format!("http://127.0.0.1:{}/approvals", server.port);
+
+    let fan_out = {
+        let client = client.clone();
+        tokio::spawn(async move {
+            let args = json!({ "command": "return 1", "all": true });
+            call(&client, "run_code_multi", args).await
+        })
+    };
+    let deadline = Instant::now() + EVENT_TIMEOUT;
+    let pending = loop {
+        let pending: Vec<serde_json::Value> = http
+            .get(&approvals)
+            .bearer_auth(&server.token)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if !pending.is_empty() {
+            break pending;
+        }
+        assert!(Instant::now() < deadline, "no call is waiting for approval");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    };
+    assert_eq!(pending.len(), 1, "{pending:?}");
+    assert_eq!(pending[0]["tool"], "run_code_multi");
+    let id = pending[0]["id"].as_str().unwrap();
+    let approved = http
+        .post(format!("{approvals}/{id}/approve"))
+        .bearer_auth(&server.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(approved.status(), 200);
+
+    // Nothing else waits for approval, while each Studio's policy still applies
+    let result = tokio::time::timeout(EVENT_TIMEOUT, fan_out)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    let results = result.structured_content.unwrap()["results"].clone();
+    assert_eq!(results[0]["studio_id"], first.studio_id.to_string());
+    assert_eq!(results[0]["success"], true);
+    assert_eq!(results[1]["studio_id"], denied.studio_id.to_string());
+    assert!(
+        results[1]["error"]
+            .as_str()
+            .unwrap()
+            .contains("tool policy"),
+        "{results}"
+    );
+
+    let log = server.home.join("config/rbx-studio-mcp/audit.jsonl");
+    let records: Vec<serde_json::Value> = std::fs::read_to_string(log)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let summary: Vec<(&str, bool, bool)> = records
+        .iter()
+        .map(|record| {
+            (
+                record["tool"].as_str().unwrap(),
+                record.get("refused").is_some(),
+                record.get("local").is_some(),
+            )
+        })
+        .collect();
+    assert!(summary.contains(&("run_code", false, false)), "{summary:?}");
+    assert!(summary.contains(&("run_code", true, false)), "{summary:?}");
+    assert_eq!(summary.last(), Some(&("run_code_multi", false, true)));
+    let _ = std::fs::remove_file(config_path);
+}
+
+#[tokio::test]
+async fn read_only_mode_still_lets_sessions_pick_a_studio() {
+    let server = TestServer::start(&["--read-only"]).await;
+    let studio = server.studio().await;
+    let client = server.client().await;
+
+    let tools = client.list_all_tools().await.unwrap();
+    let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_ref()).collect();
+    assert!(names.contains(&"set_studio"), "{names:?}");
+    assert!(!names.contains(&"run_code"), "{names:?}");
+    let result = call(
+        &client,
+        "set_studio",
+        json!({ "studio_id": studio.studio_id.to_string() }),
+    )
+    .await;
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    let result = call(&client, "run_code", json!({ "command": "" })).await;
+    assert_eq!(result.is_error, Some(true));
+    assert!(text(&result).contains("read-only"), "{}", text(&result));
+}
+
+#[tokio::test]
+async fn config_reloads_apply_and_tell_studios_where_to_reconnect() {
+    let server = TestServer::start(&[]).await;
+    let config_path = server.home.join("config/rbx-studio-mcp/config.toml");
+    let studio = server.studio().await;
+    studio.on("get_studio_mode", Reply::Success(json!({ "mode": "stop" })));
+    let mut older = server.studio_config();
+    older.accepts = Vec::new();
+    let older = MockStudio::connect(older).await.unwrap();
+    let client = server.client().await;
+    let refused = || async {
+        let result = call(&client, "get_studio_mode", json!({})).await;
+        result.is_error == Some(true)
+    };
+    let wait_for = |url: String| {
+        let studio = &studio;
+        async move {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while studio.endpoint().as_ref() != Some(&url) {
+                assert!(
+                    Instant::now() < deadline,
+                    "{:?} is not {url}",
+                    studio.endpoint()
+                );
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+    };
+    let set_studio = json!({ "studio_id": studio.studio_id.to_string() });
+    assert_eq!(
+        call(&client, "set_studio", set_studio).await.is_error,
+        Some(false)
+    );
+
+    // Registration tells the plugin where the server is now
+    let current = format!("ws://localhost:{}/ws", server.port);
+    wait_for(current.clone()).await;
+    assert!(!refused().await);
+
+    // The harness passes --plugin-port, which keeps winning over the file
+    std::fs::write(
+        &config_path,
+        "bind_address = \"10.0.0.1\"\nplugin_port = 1\n\n[policy]\ndeny = [\"get_studio_mode\"]\n",
+    )
+    .unwrap();
+    let moved = format!("ws://10.0.0.1:{}/ws", server.port);
+    wait_for(moved.clone()).await;
+    assert!(refused().await);
+    assert_eq!(older.endpoint(), None);
+
+    // A file that does not parse leaves the previous settings in place
+    std::fs::write(&config_path, "plugin_port = \"not a port\"\n").unwrap();
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert!(refused().await);
+    assert_eq!(studio.endpoint(), Some(moved));
+
+    std::fs::write(&config_path, "").unwrap();
+    wait_for(current).await;
+    assert!(!refused().await);
+}
+
+#[tokio::test]
+async fn approvals_wait_in_the_queue_for_a_human() {
+    let server = TestServer::start(&["--require-approval", "run_code", "--audit", "true"]).await;
+    let mut studio = server.studio().await;
+    studio.on(
+        "run_code",
+        Reply::Success(json!({ "logs": [], "returned": [] })),
+    );
+    let client = server.client().await;
+    let http = reqwest::Client::new();
+    let base = format!("http://127.0.0.1:{}/approvals", server.port);
+    let waiting = || async {
+        let deadline = Instant::now() + EVENT_TIMEOUT;
+        loop {
+            let pending: Vec<serde_json::Value> = http
+                .get(&base)
+                .bearer_auth(&server.token)
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            if let Some(request) = pending.first() {
+                assert_eq!(request["tool"], "run_code");
+                return request["id"].as_str().unwrap().to_string();
+            }
+            assert!(Instant::now() < deadline, "no call is waiting for approval");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    };
+    let decide = |id: String, action: &'static str, body: serde_json::Value| {
+        let request = http
+            .post(format!("{base}/{id}/{action}"))
+            .bearer_auth(&server.token)
+            .json(&body);
+        async move { request.send().await.unwrap().status().as_u16() }
+    };
+    let run_code = |command: &'static str| {
+        let client = client.clone();
+        tokio::spawn(async move { call(&client, "run_code", json!({ "command": command })).await })
+    };
+
+    // Reads are not held up
+    let result = call(&client, "list_studios", json!({})).await;
+    assert_eq!(result.is_error, Some(false));
+
+    // This client cannot show an elicitation form, so the call waits in the queue
+    let approved = run_code("return 1");
+    let id = waiting().await;
+    assert_eq!(decide(id.clone(), "approve", json!({})).await, 200);
+    let result = approved.await.unwrap();
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    // Decided calls leave the queue
+    assert_eq!(decide(id, "reject", json!({})).await, 404);
+
+    let rejected = run_code("return 2");
+    let id = waiting().await;
+    assert_eq!(
+        decide(id, "reject", json!({ "reason": "not in prod" })).await,
+        200
+    );
+    let result = rejected.await.unwrap();
+    assert_eq!(result.is_error, Some(true));
+    assert!(text(&result).contains("not in prod"), "{}", text(&result));
+
+    let Some(MockEvent::Call { args, .. }) = studio.next_event(EVENT_TIMEOUT).await else {
+        panic!("expected the approved call");
+    };
+    assert_eq!(args["command"], "return 1");
+    assert_eq!(studio.next_event(Duration::from_millis(200)).await, None);
+
+    let log = server.home.join("config/rbx-studio-mcp/audit.jsonl");
+    let records: Vec<serde_json::Value> = std::fs::read_to_string(log)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .filter(|record: &serde_json::Value| record["tool"] == "run_code")
+        .collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["arguments"]["command"], "return 1");
+    assert_eq!(records[0].get("refused"), None);
+    assert_eq!(records[1]["arguments"]["command"], "return 2");
+    assert_eq!(records[1]["refused"], true);
+}
+
+#[tokio::test]
+async fn unanswered_approvals_time_out_into_refusals() {
+    let server =
+        TestServer::start(&["--require-approval", "run_code", "--approval-timeout", "1"]).await;
+    let mut studio = server.studio().await;
+    let client = server.client().await;
+
+    let result = call(&client, "run_code", json!({ "command": "" })).await;
+    assert_eq!(result.is_error, Some(true));
+    assert!(
+        text(&result).contains("no one approved it within 1s"),
+        "{}",
+        text(&result)
+    );
+    assert_eq!(studio.next_event(Duration::from_millis(200)).await, None);
+}
+
+#[tokio::test]
+async fn clients_that_support_elicitation_ask_their_user() {
+    let server = TestServer::start(&["--require-approval", "run_code"]).await;
+    let studio = server.studio().await;
+    studio.on(
+        "run_code",
+        Reply::Success(json!({ "logs": [], "returned": [] })),
+    );
+
+    let approving = server.client_with(Approver(Some(true))).await;
+    let result = call(&approving, "run_code", json!({ "command": "" })).await;
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+
+    for answer in [Some(false), None] {
+        let refusing = server.client_with(Approver(answer)).await;
+        let result = call(&refusing, "run_code", json!({ "command": "" })).await;
+        assert_eq!(result.is_error, Some(true));
+        assert!(
+            text(&result).contains("a human rejected it"),
+            "{}",
+            text(&result)
+        );
+    }
+}
+
+#[tokio::test]
+async fn studios_are_selected_by_label_and_auto_select_rules() {
+    let config_path =
+        std::env::temp_dir().join(format!("rbx-studio-mcp-test-{}.toml", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &config_path,
+        "[[auto_select]]\nplace_id = 1818\n\n[[auto_select]]\nplace_name = \"lobby\"\n",
+    )
+    .unwrap();
+    let config_arg = config_path.to_str().unwrap();
+    let server = TestServer::start(&["--config", config_arg, "--reconnect-grace", "0"]).await;
+    let mut config = server.studio_config();
+    config.label = Some("main".to_string());
+    let first = MockStudio::connect(config).await.unwrap();
+    let mut second = server.studio().await;
+    let mut config = server.studio_config();
+    config.place_id = 7;
+    config.place_name = "Lobby".to_string();
+    let lobby = MockStudio::connect(config).await.unwrap();
+    let client = server.client().await;
+    let studio_id = |result: CallToolResult| -> String {
+        let info: serde_json::Value = serde_json::from_str(text(&result)).unwrap();
+        info["studio_id"].as_str().unwrap().to_string()
+    };
+
+    // Two Studios have place 1818 open, so the first rule is skipped
+    let result = call(&client, "get_studio", json!({})).await;
+    assert_eq!(studio_id(result), lobby.studio_id.to_string());
+
+    let result = call(&client, "set_studio", json!({ "studio_id": "main" })).await;
+    assert_eq!(studio_id(result), first.studio_id.to_string());
+    let result = call(&client, "set_studio", json!({ "studio_id": "1818" })).await;
+    assert_eq!(result.is_error, Some(true));
+    assert!(text(&result).contains("Several studios match"));
+    assert!(text(&result).contains("[main]"));
+
+    let args = json!({ "studio_id": second.studio_id.to_string(), "label": "main" });
+    let result = call(&client, "set_studio_label", args).await;
+    assert_eq!(result.is_error, Some(true));
+    let args = json!({ "studio_id": second.studio_id.to_string(), "label": "backup" });
+    let result = call(&client, "set_studio_label", args).await;
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    let args = json!({ "studio_id": "backup", "label": "spare" });
+    let result = call(&client, "set_studio_label", args).await;
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    let result = call(&client, "set_studio", json!({ "studio_id": "spare" })).await;
+    assert_eq!(studio_id(result), second.studio_id.to_string());
+
+    // Once the other Studio on place 1818 is gone, the first rule picks the labelled one
+    second.disconnect();
+    let deadline = Instant::now() + EVENT_TIMEOUT;
+    while studio_count(&client).await != 2 {
+        assert!(Instant::now() < deadline, "the Studio did not disconnect");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let other_client = server.client().await;
+    let result = call(&other_client, "get_studio", json!({})).await;
+    assert_eq!(studio_id(result), first.studio_id.to_string());
+    let _ = std::fs::remove_file(config_path);
+}
+
+#[tokio::test]
+async fn leases_lock_out_other_sessions_and_queue_waiters() {
+    let server = TestServer::start(&[]).await;
+    let studio = server.studio().await;
+    let output = json!({ "logs": [], "returned": [], "error": null });
+    studio.on("run_code", Reply::Success(output));
+    let owner = server.client().await;
+    let other = server.client().await;
+
+    let args = json!({ "studio_id": "1818", "ttl_secs": 60 });
+    let result = call(&owner, "acquire_lease", args).await;
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    let lease = result.structured_content.unwrap();
+    assert_eq!(lease["mode"], "exclusive");
+    let owner_session = lease["session_id"].as_str().unwrap().to_string();
+
+    let result = call(&other, "run_code", json!({ "command": "return" })).await;
+    assert_eq!(result.is_error, Some(true));
+    assert!(text(&result).contains(&format!("locked by session {owner_session}")));
+    let result = call(&owner, "run_code", json!({ "command": "return" })).await;
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    let result = call(&other, "acquire_lease", json!({ "mode": "shared" })).await;
+    assert_eq!(result.is_error, Some(true));
+
+    let result = call(&owner, "list_studios", json!({})).await;
+    let leases = result.structured_content.unwrap()["studios"][0]["leases"].clone();
+    assert_eq!(leases[0]["session_id"], owner_session.as_str());
+
+    let waiter = {
+        let other = other.clone();
+        tokio::spawn(async move { call(&other, "acquire_lease", json!({ "wait_secs": 10 })).await })
+    };
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!waiter.is_finished());
+    let result = call(&owner, "release_lease", json!({ "studio_id": "Nowhere" })).await;
+    assert_eq!(result.is_error, Some(true));
+    assert!(text(&result).contains("No studio matches \"Nowhere\""));
+    let result = call(
+        &owner,
+        "release_lease",
+        json!({ "studio_id": "mock place" }),
+    )
+    .await;
+    assert_eq!(text(&result), "Lease released.");
+    let result = tokio::time::timeout(EVENT_TIMEOUT, waiter)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    assert_ne!(
+        result.structured_content.unwrap()["session_id"],
+        owner_session.as_str()
+    );
+
+    let result = call(&owner, "run_code", json!({ "command": "return" })).await;
+    assert!(text(&result).contains("locked by session"));
+}
+
+#[tokio::test]
+async fn mutating_calls_to_a_studio_run_one_at_a_time() {
+    let server = TestServer::start(&[]).await;
+    let mut studio = server.studio().await;
+    let output = json!({ "logs": [], "returned": [], "error": null });
+    studio.on(
+        "run_code",
+        Reply::Delayed(Duration::from_millis(500), Box::new(Reply::Success(output))),
+    );
+    studio.on("get_studio_mode", Reply::Success(json!({ "mode": "stop" })));
+    let client = server.client().await;
+
+    let mut calls = Vec::new();
+    for n in 0..3 {
+        let client = client.clone();
+        calls.push(tokio::spawn(async move {
+            call(
+                &client,
+                "run_code",
+                json!({ "command": format!("return {n}") }),
+            )
+            .await
+        }));
+        // Fixes the order the calls reach the server
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // Read-only calls do not wait behind the queue
+    let result = tokio::time::timeout(
+        Duration::from_millis(300),
+        call(&client, "get_studio_mode", json!({})),
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.is_error, Some(false), "{}", text(&result));
+
+    let result = call(&client, "list_pending_requests", json!({})).await;
+    let pending = result.structured_content.unwrap();
+    assert_eq!(pending["concurrency"], 1);
+    let requests = &pending["studios"][0];
+    assert_eq!(requests["running"].as_array().unwrap().len(), 1);
+    assert_eq!(requests["running"][0]["tool"], "run_code");
+    assert_eq!(requests["queued"].as_array().unwrap().len(), 2);
+
+    for call in calls {
+        let result = call.await.unwrap();
+        assert_eq!(result.is_error, Some(false), "{}", text(&result));
+    }
+    let mut commands = Vec::new();
+    while let Some(event) = studio.next_event(Duration::from_millis(100)).await {
+        if let MockEvent::Call { tool, args, .. } = event {
+            if tool == "run_code" {
+                commands.push(args["command"].clone());
+            }
+        }
+    }
+    assert_eq!(commands, ["return 0", "return 1", "return 2"]);
+}